@@ -1,7 +1,7 @@
 use std::io::Read;
 
 fn main() {
-    let _screen = crossterm::RawScreen::into_raw_mode().unwrap();
+    let _screen = crossterm::screen::RawScreen::into_raw_mode().unwrap();
     loop {
         let stdin = std::io::stdin();
         let mut stdin = stdin.lock();
@@ -0,0 +1,169 @@
+use snafu::ResultExt as _;
+
+#[derive(Debug, snafu::Snafu)]
+pub enum Error {
+    #[snafu(display(
+        "failed to parse alias {}: {} ({})",
+        name,
+        source,
+        source.location()
+    ))]
+    Parse {
+        name: String,
+        source: crate::parser::Error,
+    },
+
+    #[snafu(display("alias {} must expand to a simple command", name))]
+    NotSimpleCommand { name: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+static ALIASES: std::sync::Mutex<std::collections::BTreeMap<String, String>> =
+    std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+fn aliases(
+) -> std::sync::MutexGuard<'static, std::collections::BTreeMap<String, String>>
+{
+    ALIASES.lock().unwrap()
+}
+
+pub fn get(name: &str) -> Option<String> {
+    aliases().get(name).cloned()
+}
+
+pub fn set(name: &str, value: &str) {
+    aliases().insert(name.to_string(), value.to_string());
+}
+
+pub fn remove(name: &str) -> bool {
+    aliases().remove(name).is_some()
+}
+
+pub fn clear() {
+    aliases().clear();
+}
+
+pub fn all() -> Vec<(String, String)> {
+    aliases()
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+// formats an alias definition in a way that can be evaluated to recreate it
+pub fn format(name: &str, value: &str) -> String {
+    format!("alias {}={}", name, crate::parser::quote(value))
+}
+
+// replaces the command name with the alias value, as long as the command
+// name is an unquoted alias name. the value is parsed as a simple command
+// whose words are spliced in before the original arguments. an alias is
+// never expanded again within its own expansion, which allows things like
+// `alias ls='ls -F'` and prevents infinite loops for aliases which refer
+// to each other.
+pub fn expand(
+    command: &crate::ast::SimpleCommand,
+) -> Result<crate::ast::SimpleCommand> {
+    let mut seen = std::collections::HashSet::new();
+    let mut command = command.clone();
+    while let Some(name) =
+        command.words.first().and_then(crate::ast::Word::as_literal)
+    {
+        let name = name.to_string();
+        let Some(value) = get(&name) else { break };
+        if !seen.insert(name.clone()) {
+            break;
+        }
+        let mut expanded = parse(&name, &value)?;
+        expanded
+            .assignments
+            .splice(0..0, command.assignments.drain(..));
+        expanded.words.extend(command.words.drain(1..));
+        expanded.redirects.append(&mut command.redirects);
+        command = expanded;
+    }
+    Ok(command)
+}
+
+fn parse(name: &str, value: &str) -> Result<crate::ast::SimpleCommand> {
+    let mut program = crate::parser::parse(value).context(Parse { name })?;
+    if program.lists.is_empty() {
+        return Ok(crate::ast::SimpleCommand::default());
+    }
+    if program.lists.len() == 1 && program.lists[0].rest.is_empty() {
+        let mut pipeline = program.lists.remove(0).first;
+        if !pipeline.negated && pipeline.commands.len() == 1 {
+            if let crate::ast::Command::Simple(command) =
+                pipeline.commands.remove(0)
+            {
+                return Ok(command);
+            }
+        }
+    }
+    NotSimpleCommand { name }.fail()
+}
+
+#[cfg(test)]
+mod tests {
+    // aliases are global, so each test uses its own names
+    fn expand(line: &str) -> super::Result<String> {
+        let mut program = crate::parser::parse(line).unwrap();
+        let command = program.lists.remove(0).first.commands.remove(0);
+        let crate::ast::Command::Simple(command) = command else {
+            panic!("not a simple command: {}", line);
+        };
+        super::expand(&command)
+            .map(|command| crate::ast::Command::Simple(command).to_string())
+    }
+
+    #[test]
+    fn self_reference() {
+        super::set("self_ls", "self_ls -F");
+        assert_eq!(expand("self_ls src").unwrap(), "self_ls -F src");
+        super::set("self_echo", "X=1 self_echo a >>log");
+        assert_eq!(
+            expand("Y=2 self_echo b <in").unwrap(),
+            "Y=2 X=1 self_echo a b >>log <in"
+        );
+    }
+
+    #[test]
+    fn mutual_recursion() {
+        super::set("mutual_a", "mutual_b 1");
+        super::set("mutual_b", "mutual_a 2");
+        assert_eq!(expand("mutual_a x").unwrap(), "mutual_a 2 1 x");
+        assert_eq!(expand("mutual_b x").unwrap(), "mutual_b 1 2 x");
+
+        // each alias is only skipped once it has been expanded, so chains
+        // still work
+        super::set("chain_a", "chain_b a");
+        super::set("chain_b", "chain_c b");
+        super::set("chain_c", "echo c");
+        assert_eq!(expand("chain_a").unwrap(), "echo c b a");
+    }
+
+    #[test]
+    fn only_unquoted_command_names() {
+        super::set("quoted_ll", "ls -l");
+        assert_eq!(expand("'quoted_ll' x").unwrap(), "'quoted_ll' x");
+        assert_eq!(expand("echo quoted_ll").unwrap(), "echo quoted_ll");
+        assert_eq!(expand("quoted_ll x").unwrap(), "ls -l x");
+    }
+
+    #[test]
+    fn invalid_values() {
+        super::set("invalid_pipe", "ls | less");
+        assert!(matches!(
+            expand("invalid_pipe"),
+            Err(super::Error::NotSimpleCommand { .. })
+        ));
+        super::set("invalid_quote", "echo 'a");
+        assert!(matches!(
+            expand("invalid_quote"),
+            Err(super::Error::Parse { .. })
+        ));
+        super::set("invalid_empty", "");
+        assert_eq!(expand("invalid_empty x").unwrap(), "x");
+    }
+}
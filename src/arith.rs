@@ -0,0 +1,441 @@
+use std::convert::TryFrom as _;
+
+#[derive(Debug, snafu::Snafu)]
+pub enum Error {
+    #[snafu(display("division by zero"))]
+    DivisionByZero,
+
+    #[snafu(display("integer overflow"))]
+    Overflow,
+
+    #[snafu(display("negative exponent"))]
+    NegativeExponent,
+
+    #[snafu(display("invalid number `{}`", text))]
+    InvalidNumber { text: String },
+
+    #[snafu(display("variable {} is not a number: `{}`", name, value))]
+    NotANumber { name: String, value: String },
+
+    #[snafu(display("attempted assignment to non-variable"))]
+    InvalidAssignment,
+
+    #[snafu(display("unexpected `{}`", token))]
+    UnexpectedToken { token: String },
+
+    #[snafu(display("unexpected end of expression"))]
+    UnexpectedEnd,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// longer operators need to come first, so that they are matched in
+// preference to their prefixes
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&",
+    "||", "+=", "-=", "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/",
+    "%", "<", ">", "!", "~", "&", "^", "|", "=", "?", ":", ",", "(", ")",
+];
+
+const ASSIGNMENT_OPERATORS: &[&str] = &[
+    "=", "+=", "-=", "*=", "/=", "%=", "<<=", ">>=", "&=", "^=", "|=",
+];
+
+// binds tighter than any binary operator, including `**`
+const UNARY_BINDING_POWER: u8 = 29;
+
+// evaluates an integer arithmetic expression, as used by `$((...))`,
+// `((...))`, and `let`. variables are read from and assigned to the shell
+// environment.
+pub fn eval(expr: &str) -> Result<i64> {
+    let mut parser = Parser {
+        tokens: tokenize(expr)?.into_iter().peekable(),
+    };
+    if parser.tokens.peek().is_none() {
+        return Ok(0);
+    }
+    let expr = parser.expr(0)?;
+    if let Some(token) = parser.tokens.next() {
+        return Err(token.unexpected());
+    }
+    expr.eval()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+impl Token {
+    fn unexpected(&self) -> Error {
+        Error::UnexpectedToken {
+            token: match self {
+                Self::Number(n) => n.to_string(),
+                Self::Name(name) => name.clone(),
+                Self::Op(op) => (*op).to_string(),
+            },
+        }
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut rest = expr.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            let len = rest
+                .find(|c: char| {
+                    !(c.is_ascii_alphanumeric() || c == '_' || c == '#')
+                })
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            tokens.push(if c.is_ascii_digit() {
+                Token::Number(parse_number(word)?)
+            } else {
+                Token::Name(word.to_string())
+            });
+            rest = &rest[len..];
+        } else if let Some(op) =
+            OPERATORS.iter().find(|op| rest.starts_with(**op))
+        {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return UnexpectedToken {
+                token: c.to_string(),
+            }
+            .fail();
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+// supports decimal, octal (with a leading 0), hex (with a leading 0x), and
+// arbitrary bases from 2 to 36 (like 2#1010)
+fn parse_number(text: &str) -> Result<i64> {
+    // the prefixes are checked in order, which a chain of closures would
+    // obscure
+    #[allow(clippy::option_if_let_else)]
+    let (digits, radix) = if let Some(idx) = text.find('#') {
+        let radix = text[..idx]
+            .parse()
+            .ok()
+            .filter(|radix| (2..=36).contains(radix));
+        (&text[idx + 1..], radix)
+    } else if let Some(hex) =
+        text.strip_prefix("0x").or_else(|| text.strip_prefix("0X"))
+    {
+        (hex, Some(16))
+    } else if text.len() > 1 && text.starts_with('0') {
+        (&text[1..], Some(8))
+    } else {
+        (text, Some(10))
+    };
+    let invalid = || Error::InvalidNumber {
+        text: text.to_string(),
+    };
+    let radix = radix.ok_or_else(invalid)?;
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+    i64::from_str_radix(digits, radix).map_err(|e| match e.kind() {
+        std::num::IntErrorKind::PosOverflow => Error::Overflow,
+        _ => invalid(),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Number(i64),
+    Var(String),
+    Unary(&'static str, Box<Self>),
+    Binary(&'static str, Box<Self>, Box<Self>),
+    Assign(&'static str, String, Box<Self>),
+    Increment {
+        name: String,
+        delta: i64,
+        prefix: bool,
+    },
+    Conditional(Box<Self>, Box<Self>, Box<Self>),
+}
+
+struct Parser {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser {
+    fn expect(&mut self, op: &str) -> Result<()> {
+        match self.tokens.next() {
+            Some(Token::Op(found)) if found == op => Ok(()),
+            Some(token) => Err(token.unexpected()),
+            None => UnexpectedEnd.fail(),
+        }
+    }
+
+    fn name(&mut self) -> Result<String> {
+        match self.tokens.next() {
+            Some(Token::Name(name)) => Ok(name),
+            Some(token) => Err(token.unexpected()),
+            None => UnexpectedEnd.fail(),
+        }
+    }
+
+    // precedence climbing, using the same precedence levels as bash
+    fn expr(&mut self, min_binding_power: u8) -> Result<Expr> {
+        let mut lhs = match self.tokens.next() {
+            Some(Token::Number(n)) => Expr::Number(n),
+            Some(Token::Name(name)) => match self.tokens.peek() {
+                Some(Token::Op(op @ ("++" | "--"))) => {
+                    let delta = if *op == "++" { 1 } else { -1 };
+                    self.tokens.next();
+                    Expr::Increment {
+                        name,
+                        delta,
+                        prefix: false,
+                    }
+                }
+                _ => Expr::Var(name),
+            },
+            Some(Token::Op("(")) => {
+                let expr = self.expr(0)?;
+                self.expect(")")?;
+                expr
+            }
+            Some(Token::Op(op @ ("++" | "--"))) => Expr::Increment {
+                name: self.name()?,
+                delta: if op == "++" { 1 } else { -1 },
+                prefix: true,
+            },
+            Some(Token::Op(op @ ("+" | "-" | "!" | "~"))) => {
+                Expr::Unary(op, Box::new(self.expr(UNARY_BINDING_POWER)?))
+            }
+            Some(token) => return Err(token.unexpected()),
+            None => return UnexpectedEnd.fail(),
+        };
+
+        loop {
+            let op = match self.tokens.peek() {
+                None | Some(Token::Op(")" | ":")) => break,
+                Some(Token::Op(op)) => *op,
+                Some(token) => return Err(token.unexpected()),
+            };
+            let Some((left, right)) = binding_power(op) else {
+                return Err(Token::Op(op).unexpected());
+            };
+            if left < min_binding_power {
+                break;
+            }
+            self.tokens.next();
+
+            lhs = if op == "?" {
+                let if_true = self.expr(0)?;
+                self.expect(":")?;
+                let if_false = self.expr(right)?;
+                Expr::Conditional(
+                    Box::new(lhs),
+                    Box::new(if_true),
+                    Box::new(if_false),
+                )
+            } else if ASSIGNMENT_OPERATORS.contains(&op) {
+                let Expr::Var(name) = lhs else {
+                    return InvalidAssignment.fail();
+                };
+                Expr::Assign(op, name, Box::new(self.expr(right)?))
+            } else {
+                Expr::Binary(op, Box::new(lhs), Box::new(self.expr(right)?))
+            };
+        }
+        Ok(lhs)
+    }
+}
+
+// returns the left and right binding powers of an infix operator. right
+// associative operators have a lower right binding power.
+fn binding_power(op: &str) -> Option<(u8, u8)> {
+    Some(match op {
+        "," => (1, 2),
+        op if ASSIGNMENT_OPERATORS.contains(&op) => (4, 3),
+        "?" => (6, 5),
+        "||" => (7, 8),
+        "&&" => (9, 10),
+        "|" => (11, 12),
+        "^" => (13, 14),
+        "&" => (15, 16),
+        "==" | "!=" => (17, 18),
+        "<" | "<=" | ">" | ">=" => (19, 20),
+        "<<" | ">>" => (21, 22),
+        "+" | "-" => (23, 24),
+        "*" | "/" | "%" => (25, 26),
+        "**" => (28, 27),
+        _ => return None,
+    })
+}
+
+impl Expr {
+    fn eval(&self) -> Result<i64> {
+        match self {
+            Self::Number(n) => Ok(*n),
+            Self::Var(name) => var(name),
+            Self::Unary(op, expr) => {
+                let value = expr.eval()?;
+                match *op {
+                    "+" => Ok(value),
+                    "-" => value.checked_neg().ok_or(Error::Overflow),
+                    "!" => Ok(i64::from(value == 0)),
+                    "~" => Ok(!value),
+                    _ => unreachable!(),
+                }
+            }
+            // these need to short circuit
+            Self::Binary("&&", lhs, rhs) => {
+                Ok(i64::from(lhs.eval()? != 0 && rhs.eval()? != 0))
+            }
+            Self::Binary("||", lhs, rhs) => {
+                Ok(i64::from(lhs.eval()? != 0 || rhs.eval()? != 0))
+            }
+            Self::Binary(",", lhs, rhs) => {
+                lhs.eval()?;
+                rhs.eval()
+            }
+            Self::Binary(op, lhs, rhs) => {
+                binary(op, lhs.eval()?, rhs.eval()?)
+            }
+            Self::Assign(op, name, expr) => {
+                let rhs = expr.eval()?;
+                let value = if *op == "=" {
+                    rhs
+                } else {
+                    binary(op.trim_end_matches('='), var(name)?, rhs)?
+                };
+                crate::env::set_var(name, &value.to_string());
+                Ok(value)
+            }
+            Self::Increment {
+                name,
+                delta,
+                prefix,
+            } => {
+                let old = var(name)?;
+                let new = old.checked_add(*delta).ok_or(Error::Overflow)?;
+                crate::env::set_var(name, &new.to_string());
+                Ok(if *prefix { new } else { old })
+            }
+            Self::Conditional(condition, if_true, if_false) => {
+                if condition.eval()? != 0 {
+                    if_true.eval()
+                } else {
+                    if_false.eval()
+                }
+            }
+        }
+    }
+}
+
+fn binary(op: &str, lhs: i64, rhs: i64) -> Result<i64> {
+    let shift = || {
+        u32::try_from(rhs)
+            .ok()
+            .filter(|shift| *shift < i64::BITS)
+            .ok_or(Error::Overflow)
+    };
+    let value = match op {
+        "+" => lhs.checked_add(rhs),
+        "-" => lhs.checked_sub(rhs),
+        "*" => lhs.checked_mul(rhs),
+        "/" | "%" => {
+            snafu::ensure!(rhs != 0, DivisionByZero);
+            if op == "/" {
+                lhs.checked_div(rhs)
+            } else {
+                lhs.checked_rem(rhs)
+            }
+        }
+        "**" => {
+            snafu::ensure!(rhs >= 0, NegativeExponent);
+            u32::try_from(rhs).ok().and_then(|rhs| lhs.checked_pow(rhs))
+        }
+        "<<" => lhs.checked_shl(shift()?),
+        ">>" => lhs.checked_shr(shift()?),
+        "<" => Some(i64::from(lhs < rhs)),
+        "<=" => Some(i64::from(lhs <= rhs)),
+        ">" => Some(i64::from(lhs > rhs)),
+        ">=" => Some(i64::from(lhs >= rhs)),
+        "==" => Some(i64::from(lhs == rhs)),
+        "!=" => Some(i64::from(lhs != rhs)),
+        "&" => Some(lhs & rhs),
+        "^" => Some(lhs ^ rhs),
+        "|" => Some(lhs | rhs),
+        _ => unreachable!(),
+    };
+    value.ok_or(Error::Overflow)
+}
+
+// unset and empty variables are treated as 0
+fn var(name: &str) -> Result<i64> {
+    let value = crate::env::var(name).unwrap_or_default();
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(0);
+    }
+    let (negative, digits) = value
+        .strip_prefix('-')
+        .map_or((false, value), |digits| (true, digits));
+    let n = parse_number(digits).map_err(|_| Error::NotANumber {
+        name: name.to_string(),
+        value: value.to_string(),
+    })?;
+    Ok(if negative { -n } else { n })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3").unwrap(), 7);
+        assert_eq!(eval("(1 + 2) * 3").unwrap(), 9);
+        assert_eq!(eval("10 - 4 - 3").unwrap(), 3);
+        assert_eq!(eval("2 ** 3 ** 2").unwrap(), 512);
+        assert_eq!(eval("-2 ** 2").unwrap(), 4);
+        assert_eq!(eval("1 << 2 + 1").unwrap(), 8);
+        assert_eq!(eval("6 & 3 | 8").unwrap(), 10);
+        assert_eq!(eval("1 + 1 == 2 && 3 > 2").unwrap(), 1);
+        assert_eq!(eval("0 || 2 < 1").unwrap(), 0);
+        assert_eq!(eval("1 ? 2 : 3").unwrap(), 2);
+        assert_eq!(eval("0 ? 2 : 1 ? 4 : 5").unwrap(), 4);
+        assert_eq!(eval("!0 + ~0").unwrap(), 0);
+    }
+
+    #[test]
+    fn overflow() {
+        assert!(matches!(
+            eval("9223372036854775807 + 1"),
+            Err(Error::Overflow)
+        ));
+        assert!(matches!(
+            eval("-9223372036854775807 - 2"),
+            Err(Error::Overflow)
+        ));
+        assert!(matches!(eval("2 ** 63"), Err(Error::Overflow)));
+        assert!(matches!(eval("1 << 64"), Err(Error::Overflow)));
+        assert!(matches!(
+            eval("(-9223372036854775807 - 1) / -1"),
+            Err(Error::Overflow)
+        ));
+        assert!(matches!(eval("2 ** -1"), Err(Error::NegativeExponent)));
+        assert_eq!(eval("2 ** 62").unwrap(), 1 << 62);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert!(matches!(eval("1 / 0"), Err(Error::DivisionByZero)));
+        assert!(matches!(eval("1 % 0"), Err(Error::DivisionByZero)));
+        assert!(matches!(eval("5 / (3 - 3)"), Err(Error::DivisionByZero)));
+        assert_eq!(eval("7 / 2").unwrap(), 3);
+        assert_eq!(eval("-7 % 3").unwrap(), -1);
+    }
+}
@@ -0,0 +1,677 @@
+// the syntax tree produced by the parser. the Display implementations
+// print it back out as shell code which parses to the same tree (other than
+// spans).
+
+use std::fmt::Write as _;
+
+// byte offsets of the start and end of a node in the parsed input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize)]
+pub struct Program {
+    pub lists: Vec<AndOrList>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct AndOrList {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum Connector {
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Pipeline {
+    pub negated: bool,
+    pub commands: Vec<Command>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum Command {
+    Simple(SimpleCommand),
+    BraceGroup(Program),
+    If(If),
+    Loop(Loop),
+    For(For),
+    Case(Case),
+    FunctionDef(FunctionDef),
+    // `((expr))`, which succeeds if the expression is nonzero
+    Arithmetic(Word),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum Redirect {
+    // `[n]<file`
+    Input(FileRedirect),
+    // `[n]>file`
+    Output(FileRedirect),
+    // `[n]>>file`
+    Append(FileRedirect),
+    // `[n]>&m` or `[n]<&m`, which make n a copy of m
+    Duplicate(Duplicate),
+    Heredoc(Heredoc),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct FileRedirect {
+    // defaults to 0 for input and 1 for output
+    pub fd: u32,
+    pub target: Word,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Duplicate {
+    pub fd: u32,
+    pub source: u32,
+    // `<&` rather than `>&`, which only changes the default fd
+    pub input: bool,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Heredoc {
+    // the delimiter with any quoting removed
+    pub delimiter: String,
+    // if any part of the delimiter was quoted, the body isn't expanded
+    pub quoted: bool,
+    // `<<-`, which removes leading tabs from the body and the delimiter
+    pub strip_tabs: bool,
+    pub body: Word,
+    // the redirection itself, not including the body
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct If {
+    // the `if` clause followed by any `elif` clauses
+    pub conditionals: Vec<Conditional>,
+    pub else_body: Option<Program>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Conditional {
+    pub condition: Program,
+    pub body: Program,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum LoopKind {
+    While,
+    Until,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Loop {
+    pub kind: LoopKind,
+    pub condition: Program,
+    pub body: Program,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct For {
+    pub name: String,
+    // None means to iterate over the positional parameters
+    pub words: Option<Vec<Word>>,
+    pub body: Program,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Case {
+    pub word: Word,
+    pub items: Vec<CaseItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: Program,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct FunctionDef {
+    pub name: String,
+    pub body: std::sync::Arc<Command>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum WordPart {
+    Literal(String),
+    SingleQuoted(String),
+    DoubleQuoted(Vec<Self>),
+    Param(String),
+    CommandSubst(Program),
+    ProcessSubst(ProcessSubstKind, Program),
+    // `$((expr))`. the expression is expanded like a double quoted string
+    // before being evaluated.
+    Arithmetic(Word),
+    Tilde,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum ProcessSubstKind {
+    // `<(...)`, which the command reads from
+    Read,
+    // `>(...)`, which the command writes to
+    Write,
+}
+
+impl Word {
+    // returns the text of the word if it contains no quoting or
+    // expansions, which is what is required for things like reserved words
+    // and alias names to be recognized
+    #[must_use]
+    pub fn as_literal(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [WordPart::Literal(s)] => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut printer = Printer::default();
+        for (i, list) in self.lists.iter().enumerate() {
+            if i > 0 {
+                printer.newline();
+            }
+            printer.and_or(list);
+        }
+        f.write_str(&printer.finish())
+    }
+}
+
+impl std::fmt::Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut printer = Printer::default();
+        printer.command(self);
+        f.write_str(&printer.finish())
+    }
+}
+
+impl std::fmt::Display for Word {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut printer = Printer::default();
+        printer.word(self);
+        f.write_str(&printer.finish())
+    }
+}
+
+// how the text of a word part needs to be escaped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Context {
+    Unquoted,
+    DoubleQuoted,
+    Heredoc,
+    Arithmetic,
+}
+
+#[derive(Default)]
+struct Printer<'a> {
+    out: String,
+    indent: usize,
+    // here-document bodies have to go after the end of the current line
+    heredocs: Vec<&'a Heredoc>,
+}
+
+impl<'a> Printer<'a> {
+    fn finish(mut self) -> String {
+        if !self.heredocs.is_empty() {
+            self.out.push('\n');
+            self.flush_heredocs();
+        }
+        self.out
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.flush_heredocs();
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+    }
+
+    fn flush_heredocs(&mut self) {
+        for heredoc in std::mem::take(&mut self.heredocs) {
+            match heredoc.body.parts.as_slice() {
+                [WordPart::SingleQuoted(body)] if heredoc.quoted => {
+                    self.out.push_str(body);
+                }
+                [WordPart::DoubleQuoted(parts)] => {
+                    self.parts(parts, Context::Heredoc);
+                }
+                parts => self.parts(parts, Context::Heredoc),
+            }
+            self.out.push_str(&heredoc.delimiter);
+            self.out.push('\n');
+        }
+    }
+
+    // prints lists on a single line, as for conditions and substitutions
+    fn program_inline(&mut self, program: &'a Program) {
+        let heredocs = self.heredocs.len();
+        for (i, list) in program.lists.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str("; ");
+            }
+            self.and_or(list);
+        }
+        if self.heredocs.len() > heredocs {
+            self.newline();
+        }
+    }
+
+    // prints lists on separate lines with an extra level of indentation,
+    // leaving the output at the start of a new line for the closing word
+    fn block(&mut self, program: &'a Program) {
+        self.indent += 1;
+        for list in &program.lists {
+            self.newline();
+            self.and_or(list);
+        }
+        self.indent -= 1;
+        self.newline();
+    }
+
+    fn and_or(&mut self, list: &'a AndOrList) {
+        self.pipeline(&list.first);
+        for (connector, pipeline) in &list.rest {
+            self.out.push_str(match connector {
+                Connector::And => " && ",
+                Connector::Or => " || ",
+            });
+            self.pipeline(pipeline);
+        }
+    }
+
+    fn pipeline(&mut self, pipeline: &'a Pipeline) {
+        if pipeline.negated {
+            self.out.push_str("! ");
+        }
+        for (i, command) in pipeline.commands.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(" | ");
+            }
+            self.command(command);
+        }
+    }
+
+    fn command(&mut self, command: &'a Command) {
+        match command {
+            Command::Simple(command) => self.simple_command(command),
+            Command::BraceGroup(program) => {
+                self.out.push('{');
+                self.block(program);
+                self.out.push('}');
+            }
+            Command::If(if_) => {
+                for (i, conditional) in if_.conditionals.iter().enumerate() {
+                    self.out.push_str(if i == 0 { "if " } else { "elif " });
+                    self.program_inline(&conditional.condition);
+                    self.out.push_str("; then");
+                    self.block(&conditional.body);
+                }
+                if let Some(body) = &if_.else_body {
+                    self.out.push_str("else");
+                    self.block(body);
+                }
+                self.out.push_str("fi");
+            }
+            Command::Loop(loop_) => {
+                self.out.push_str(match loop_.kind {
+                    LoopKind::While => "while ",
+                    LoopKind::Until => "until ",
+                });
+                self.program_inline(&loop_.condition);
+                self.out.push_str("; do");
+                self.block(&loop_.body);
+                self.out.push_str("done");
+            }
+            Command::For(for_) => {
+                self.out.push_str("for ");
+                self.out.push_str(&for_.name);
+                if let Some(words) = &for_.words {
+                    self.out.push_str(" in");
+                    for word in words {
+                        self.out.push(' ');
+                        self.word(word);
+                    }
+                }
+                self.out.push_str("; do");
+                self.block(&for_.body);
+                self.out.push_str("done");
+            }
+            Command::Case(case) => {
+                self.out.push_str("case ");
+                self.word(&case.word);
+                self.out.push_str(" in");
+                self.indent += 1;
+                for item in &case.items {
+                    self.newline();
+                    for (i, pattern) in item.patterns.iter().enumerate() {
+                        if i > 0 {
+                            self.out.push_str(" | ");
+                        }
+                        self.word(pattern);
+                    }
+                    self.out.push(')');
+                    self.block(&item.body);
+                    self.out.push_str(";;");
+                }
+                self.indent -= 1;
+                self.newline();
+                self.out.push_str("esac");
+            }
+            Command::FunctionDef(def) => {
+                self.out.push_str(&def.name);
+                self.out.push_str("() ");
+                self.command(&def.body);
+            }
+            Command::Arithmetic(expr) => {
+                self.out.push_str("((");
+                self.parts(&expr.parts, Context::Arithmetic);
+                self.out.push_str("))");
+            }
+        }
+    }
+
+    fn simple_command(&mut self, command: &'a SimpleCommand) {
+        let mut first = true;
+        let mut separate = |printer: &mut Self| {
+            if !first {
+                printer.out.push(' ');
+            }
+            first = false;
+        };
+        for assignment in &command.assignments {
+            separate(self);
+            self.out.push_str(&assignment.name);
+            self.out.push('=');
+            self.parts(&assignment.value.parts, Context::Unquoted);
+        }
+        for word in &command.words {
+            separate(self);
+            self.word(word);
+        }
+        for redirect in &command.redirects {
+            separate(self);
+            match redirect {
+                Redirect::Input(redirect) => {
+                    self.file_redirect(redirect, 0, "<");
+                }
+                Redirect::Output(redirect) => {
+                    self.file_redirect(redirect, 1, ">");
+                }
+                Redirect::Append(redirect) => {
+                    self.file_redirect(redirect, 1, ">>");
+                }
+                Redirect::Duplicate(dup) => {
+                    let (default_fd, op) =
+                        if dup.input { (0, "<&") } else { (1, ">&") };
+                    if dup.fd != default_fd {
+                        self.out.push_str(&dup.fd.to_string());
+                    }
+                    write!(self.out, "{op}{}", dup.source).unwrap();
+                }
+                Redirect::Heredoc(heredoc) => {
+                    self.out.push_str("<<");
+                    if heredoc.strip_tabs {
+                        self.out.push('-');
+                    }
+                    if heredoc.quoted {
+                        self.single_quoted(&heredoc.delimiter);
+                    } else {
+                        self.out.push_str(&heredoc.delimiter);
+                    }
+                    self.heredocs.push(heredoc);
+                }
+            }
+        }
+    }
+
+    fn file_redirect(
+        &mut self,
+        redirect: &'a FileRedirect,
+        default_fd: u32,
+        op: &str,
+    ) {
+        if redirect.fd != default_fd {
+            self.out.push_str(&redirect.fd.to_string());
+        }
+        self.out.push_str(op);
+        self.word(&redirect.target);
+    }
+
+    fn word(&mut self, word: &'a Word) {
+        // an empty word still has to be written as something
+        if word.parts.is_empty() {
+            self.out.push_str("''");
+        } else {
+            self.parts(&word.parts, Context::Unquoted);
+        }
+    }
+
+    fn parts(&mut self, parts: &'a [WordPart], context: Context) {
+        for (i, part) in parts.iter().enumerate() {
+            match part {
+                WordPart::Literal(s) => self.literal(s, context),
+                WordPart::SingleQuoted(s) => {
+                    if context == Context::Unquoted
+                        || context == Context::Arithmetic
+                    {
+                        self.single_quoted(s);
+                    } else {
+                        self.literal(s, context);
+                    }
+                }
+                WordPart::DoubleQuoted(parts) => {
+                    self.out.push('"');
+                    self.parts(parts, Context::DoubleQuoted);
+                    self.out.push('"');
+                }
+                WordPart::Param(name) => {
+                    // `$name` is ambiguous when followed by something that
+                    // could be part of the name
+                    let ambiguous = match parts.get(i + 1) {
+                        Some(WordPart::Literal(s)) => {
+                            s.starts_with(|c: char| {
+                                c.is_ascii_alphanumeric() || c == '_'
+                            })
+                        }
+                        _ => false,
+                    };
+                    if crate::parser::is_name(name) && !ambiguous
+                        || !crate::parser::is_name(name)
+                            && name.chars().count() == 1
+                    {
+                        self.out.push('$');
+                        self.out.push_str(name);
+                    } else {
+                        self.out.push_str("${");
+                        self.out.push_str(name);
+                        self.out.push('}');
+                    }
+                }
+                WordPart::CommandSubst(program) => {
+                    self.out.push_str("$(");
+                    self.program_inline(program);
+                    self.out.push(')');
+                }
+                WordPart::ProcessSubst(kind, program) => {
+                    self.out.push_str(match kind {
+                        ProcessSubstKind::Read => "<(",
+                        ProcessSubstKind::Write => ">(",
+                    });
+                    self.program_inline(program);
+                    self.out.push(')');
+                }
+                WordPart::Arithmetic(expr) => {
+                    self.out.push_str("$((");
+                    self.parts(&expr.parts, Context::Arithmetic);
+                    self.out.push_str("))");
+                }
+                WordPart::Tilde => self.out.push('~'),
+            }
+        }
+    }
+
+    fn literal(&mut self, s: &str, context: Context) {
+        for c in s.chars() {
+            let special = match context {
+                Context::Unquoted => {
+                    c.is_whitespace() || "'\"\\`;&|()<>".contains(c)
+                }
+                Context::Arithmetic => "'\"\\`".contains(c),
+                Context::DoubleQuoted => "\"\\$`".contains(c),
+                Context::Heredoc => "\\$`".contains(c),
+            };
+            if special && c == '\n' {
+                // a backslash before a newline would be a line continuation
+                self.out.push_str("'\n'");
+                continue;
+            }
+            if special {
+                self.out.push('\\');
+            }
+            self.out.push(c);
+        }
+    }
+
+    fn single_quoted(&mut self, s: &str) {
+        if s == "'" {
+            self.out.push_str("\\'");
+        } else {
+            self.out.push('\'');
+            self.out.push_str(&s.replace('\'', "'\\''"));
+            self.out.push('\'');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // the json form of a tree, without the spans, which change when the
+    // tree is printed back out
+    fn shape(program: &super::Program) -> serde_json::Value {
+        fn strip(value: &mut serde_json::Value) {
+            match value {
+                serde_json::Value::Object(map) => {
+                    map.remove("span");
+                    map.values_mut().for_each(strip);
+                }
+                serde_json::Value::Array(values) => {
+                    values.iter_mut().for_each(strip);
+                }
+                _ => {}
+            }
+        }
+        let mut value = serde_json::to_value(program).unwrap();
+        strip(&mut value);
+        value
+    }
+
+    #[test]
+    fn round_trip() {
+        let scripts = [
+            "echo hello world",
+            "FOO=bar BAZ='a b' env",
+            "echo a | tr a b | cat",
+            "! grep -q x file && echo missing || echo found",
+            "echo hi >out; echo again >>out; cat <out",
+            "make 2>&1 >log | less; cmd 3>fd3 2>/dev/null 0<&3",
+            "cat <&3 4<&0 1<&2 0>&1 >&2",
+            "foo |\n  bar",
+            ">empty; echo >#hash",
+            "echo \"$HOME\" '$HOME' \\$HOME ${x} $(pwd) `date`",
+            "echo ~/src \"a\\\"b\" 'it'\\''s' $((1 + 2 * 3))",
+            "diff <(ls a) <(ls b) >(cat)",
+            "cat <<EOF >out\nhello $name\nEOF\necho done",
+            "cat <<'EOF'\n$literal\nEOF",
+            "if true; then echo yes; elif false; then :; else echo no; fi",
+            "while read line; do echo \"$line\"; done",
+            "until false; do break; done",
+            "for x in a b c; do echo $x | cat; done",
+            "case $1 in a|b) echo ab ;; *) echo other ;; esac",
+            "f() { echo \"$@\" >&2; return 1; }",
+            "{ echo a; echo b; } && ((x > 1))",
+        ];
+        for script in &scripts {
+            let program = crate::parser::parse(script).unwrap();
+            let printed = program.to_string();
+            let reparsed = crate::parser::parse(&printed)
+                .unwrap_or_else(|e| panic!("{}\n{}", printed, e));
+            assert_eq!(shape(&program), shape(&reparsed), "{printed}");
+            // printing is stable once the source has been normalized
+            assert_eq!(reparsed.to_string(), printed);
+        }
+
+        // the direction of a duplication is kept, even though it only
+        // matters for the default fd
+        let printed = crate::parser::parse("cat <&3 4<&0 1<&2 0>&1 >&2")
+            .unwrap()
+            .to_string();
+        assert_eq!(printed, "cat <&3 4<&0 1<&2 0>&1 >&2");
+    }
+
+    #[test]
+    fn pipelines_and_redirects() {
+        let program =
+            crate::parser::parse("echo a | cat 2>&1 >>log <in").unwrap();
+        let value = shape(&program);
+        let commands = &value["lists"][0]["first"]["commands"];
+        assert_eq!(commands.as_array().unwrap().len(), 2);
+        assert_eq!(
+            commands[1]["Simple"]["redirects"],
+            serde_json::json!([
+                { "Duplicate": { "fd": 2, "source": 1, "input": false } },
+                {
+                    "Append": {
+                        "fd": 1,
+                        "target": { "parts": [{ "Literal": "log" }] }
+                    }
+                },
+                {
+                    "Input": {
+                        "fd": 0,
+                        "target": { "parts": [{ "Literal": "in" }] }
+                    }
+                },
+            ])
+        );
+
+        for script in &["echo >", "echo 2>&x", "echo a |", "| cat"] {
+            assert!(crate::parser::parse(script).is_err(), "{}", script);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+extern crate nbsh;
+
+use std::io::Read as _;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => nbsh::repl::repl(),
+        Some("--dump-ast") if args.len() <= 2 => {
+            dump_ast(args.get(1).map(String::as_str));
+        }
+        Some("-n") if args.len() <= 2 => {
+            check(args.get(1).map(String::as_str));
+        }
+        _ => {
+            eprintln!("usage: nbsh [-n | --dump-ast] [FILE]");
+            std::process::exit(2);
+        }
+    }
+}
+
+// prints the syntax tree of a script (or stdin) as json
+fn dump_ast(path: Option<&str>) {
+    let (name, script) = read_script(path);
+    match nbsh::parser::parse(&script) {
+        Ok(program) => {
+            println!("{}", serde_json::to_string_pretty(&program).unwrap());
+        }
+        Err(e) => {
+            eprint!("{}", e.report(name, &script));
+            std::process::exit(1);
+        }
+    }
+}
+
+// reports all syntax errors in a script (or stdin) without running it
+fn check(path: Option<&str>) {
+    let (name, script) = read_script(path);
+    let errors = nbsh::parser::check(&script);
+    for e in &errors {
+        eprint!("{}", e.report(name, &script));
+    }
+    if !errors.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+fn read_script(path: Option<&str>) -> (&str, String) {
+    let name = path.unwrap_or("-");
+    let script = match path {
+        None | Some("-") => {
+            let mut script = String::new();
+            std::io::stdin().read_to_string(&mut script).map(|_| script)
+        }
+        Some(path) => std::fs::read_to_string(path),
+    };
+    match script {
+        Ok(script) => (name, script),
+        Err(e) => {
+            eprintln!("nbsh: {}: {}", name, e);
+            std::process::exit(1);
+        }
+    }
+}
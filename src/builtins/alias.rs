@@ -0,0 +1,108 @@
+pub struct Alias;
+
+impl super::Builtin for Alias {
+    fn name(&self) -> &'static str {
+        "alias"
+    }
+
+    fn usage(&self) -> &'static str {
+        "alias [name[=value]...]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "define or list aliases"
+    }
+
+    fn help(&self) -> &'static str {
+        "with no arguments, lists all aliases. name=value defines an alias,\n\
+         and a name by itself prints the alias with that name."
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        alias(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+pub struct Unalias;
+
+impl super::Builtin for Unalias {
+    fn name(&self) -> &'static str {
+        "unalias"
+    }
+
+    fn usage(&self) -> &'static str {
+        "unalias [-a | name...]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "remove aliases"
+    }
+
+    fn help(&self) -> &'static str {
+        "-a  remove all aliases"
+    }
+
+    fn arity(&self) -> (usize, Option<usize>) {
+        (1, None)
+    }
+
+    fn run(
+        &self,
+        args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        unalias(args).map(|()| 0)
+    }
+}
+
+fn alias(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    if args.is_empty() {
+        for (name, value) in crate::aliases::all() {
+            output.extend(
+                format!("{}\n", crate::aliases::format(&name, &value))
+                    .into_bytes(),
+            );
+        }
+        return Ok(());
+    }
+
+    // like in sh, report all of the missing aliases rather than stopping at
+    // the first one
+    let mut res = Ok(());
+    for arg in args {
+        if let Some(idx) = arg.find('=') {
+            crate::aliases::set(&arg[..idx], &arg[idx + 1..]);
+        } else if let Some(value) = crate::aliases::get(arg) {
+            output.extend(
+                format!("{}\n", crate::aliases::format(arg, &value))
+                    .into_bytes(),
+            );
+        } else {
+            res = super::UnknownAlias { name: arg }.fail();
+        }
+    }
+    res
+}
+
+fn unalias(args: &[String]) -> super::Result<()> {
+    if args[0] == "-a" {
+        snafu::ensure!(
+            args.len() == 1,
+            super::TooManyParams {
+                cmd: "unalias",
+                args,
+                expected: 1_usize,
+            }
+        );
+        crate::aliases::clear();
+        return Ok(());
+    }
+
+    let mut res = Ok(());
+    for arg in args {
+        if !crate::aliases::remove(arg) {
+            res = super::UnknownAlias { name: arg }.fail();
+        }
+    }
+    res
+}
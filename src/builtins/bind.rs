@@ -0,0 +1,100 @@
+use snafu::ResultExt as _;
+
+pub struct Bind;
+
+impl super::Builtin for Bind {
+    fn name(&self) -> &'static str {
+        "bind"
+    }
+
+    fn usage(&self) -> &'static str {
+        "bind [-l | -p | -r keys... | keys... action]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "define or list key bindings"
+    }
+
+    fn help(&self) -> &'static str {
+        "-l       list the actions that keys can be bound to\n\
+         -p       list the current bindings (the default)\n\
+         -r keys  remove the binding for keys\n\
+         keys are written like `C-a` or `M-x`, and can be a sequence."
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        bind(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+fn bind(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    match args.first().map(String::as_str) {
+        None | Some("-p") => {
+            snafu::ensure!(
+                args.len() <= 1,
+                super::TooManyParams {
+                    cmd: "bind",
+                    args,
+                    expected: 1_usize,
+                }
+            );
+            for (keys, action) in crate::keybindings::bindings() {
+                output.extend(
+                    format!(
+                        "bind {} {}\n",
+                        crate::keybindings::format_keys(&keys),
+                        action.name()
+                    )
+                    .into_bytes(),
+                );
+            }
+        }
+        Some("-l") => {
+            snafu::ensure!(
+                args.len() <= 1,
+                super::TooManyParams {
+                    cmd: "bind",
+                    args,
+                    expected: 1_usize,
+                }
+            );
+            for action in crate::keybindings::Action::ALL {
+                output.extend(format!("{}\n", action.name()).into_bytes());
+            }
+        }
+        Some("-r") => {
+            snafu::ensure!(
+                args.len() >= 2,
+                super::NotEnoughParams {
+                    cmd: "bind",
+                    args,
+                    expected: 2_usize,
+                }
+            );
+            let keys = crate::keybindings::parse_keys(&args[1..])
+                .context(super::Bind)?;
+            snafu::ensure!(
+                crate::keybindings::unbind(&keys),
+                super::Unbind {
+                    keys: crate::keybindings::format_keys(&keys),
+                }
+            );
+        }
+        Some(_) => {
+            snafu::ensure!(
+                args.len() >= 2,
+                super::NotEnoughParams {
+                    cmd: "bind",
+                    args,
+                    expected: 2_usize,
+                }
+            );
+            let (action, keys) = args.split_last().unwrap();
+            let action = action.parse().context(super::Bind)?;
+            let keys =
+                crate::keybindings::parse_keys(keys).context(super::Bind)?;
+            crate::keybindings::bind(keys, action).context(super::Bind)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,480 @@
+use snafu::{OptionExt as _, ResultExt as _};
+
+pub struct Break;
+
+impl super::Builtin for Break {
+    fn name(&self) -> &'static str {
+        "break"
+    }
+
+    fn usage(&self) -> &'static str {
+        "break [n]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "exit from a loop"
+    }
+
+    fn help(&self) -> &'static str {
+        "n  the number of enclosing loops to exit (default 1)"
+    }
+
+    fn arity(&self) -> (usize, Option<usize>) {
+        (0, Some(1))
+    }
+
+    fn run(
+        &self,
+        args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        loop_control(self.name(), args).map(|()| 0)
+    }
+}
+
+pub struct Continue;
+
+impl super::Builtin for Continue {
+    fn name(&self) -> &'static str {
+        "continue"
+    }
+
+    fn usage(&self) -> &'static str {
+        "continue [n]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "skip to the next iteration of a loop"
+    }
+
+    fn help(&self) -> &'static str {
+        "n  resume the nth enclosing loop (default 1)"
+    }
+
+    fn arity(&self) -> (usize, Option<usize>) {
+        (0, Some(1))
+    }
+
+    fn run(
+        &self,
+        args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        loop_control(self.name(), args).map(|()| 0)
+    }
+}
+
+pub struct Return;
+
+impl super::Builtin for Return {
+    fn name(&self) -> &'static str {
+        "return"
+    }
+
+    fn usage(&self) -> &'static str {
+        "return [n]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "return from a function or sourced file"
+    }
+
+    fn help(&self) -> &'static str {
+        "n  the status to return (default: the status of the last command)"
+    }
+
+    fn arity(&self) -> (usize, Option<usize>) {
+        (0, Some(1))
+    }
+
+    fn run(
+        &self,
+        args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        ret(args)
+    }
+}
+
+pub struct Local;
+
+impl super::Builtin for Local {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn usage(&self) -> &'static str {
+        "local name[=value]..."
+    }
+
+    fn summary(&self) -> &'static str {
+        "define variables local to a function"
+    }
+
+    fn run(
+        &self,
+        args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        local(args).map(|()| 0)
+    }
+}
+
+pub struct Export;
+
+impl super::Builtin for Export {
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    fn usage(&self) -> &'static str {
+        "export [name[=value]...]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "make variables visible to commands"
+    }
+
+    fn help(&self) -> &'static str {
+        "with no arguments, lists the exported variables."
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        export(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+pub struct Let;
+
+impl super::Builtin for Let {
+    fn name(&self) -> &'static str {
+        "let"
+    }
+
+    fn usage(&self) -> &'static str {
+        "let expr..."
+    }
+
+    fn summary(&self) -> &'static str {
+        "evaluate arithmetic expressions"
+    }
+
+    fn help(&self) -> &'static str {
+        "the status is 0 if the last expression is nonzero, and 1 otherwise."
+    }
+
+    fn arity(&self) -> (usize, Option<usize>) {
+        (1, None)
+    }
+
+    fn run(
+        &self,
+        args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        let_(args)
+    }
+}
+
+pub struct Exit;
+
+impl super::Builtin for Exit {
+    fn name(&self) -> &'static str {
+        "exit"
+    }
+
+    fn usage(&self) -> &'static str {
+        "exit [n]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "exit the shell"
+    }
+
+    fn help(&self) -> &'static str {
+        "n  the exit status (default: the status of the last command)\n\
+         the shell exits once the current command line has finished."
+    }
+
+    fn arity(&self) -> (usize, Option<usize>) {
+        (0, Some(1))
+    }
+
+    fn run(
+        &self,
+        args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        exit(args)
+    }
+}
+
+pub struct Colon;
+
+impl super::Builtin for Colon {
+    fn name(&self) -> &'static str {
+        ":"
+    }
+
+    fn usage(&self) -> &'static str {
+        ": [arg...]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "do nothing, successfully, ignoring any arguments"
+    }
+
+    fn run(
+        &self,
+        _args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        Ok(0)
+    }
+}
+
+pub struct True;
+
+impl super::Builtin for True {
+    fn name(&self) -> &'static str {
+        "true"
+    }
+
+    fn usage(&self) -> &'static str {
+        "true"
+    }
+
+    fn summary(&self) -> &'static str {
+        "do nothing, successfully"
+    }
+
+    fn run(
+        &self,
+        _args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        Ok(0)
+    }
+}
+
+pub struct False;
+
+impl super::Builtin for False {
+    fn name(&self) -> &'static str {
+        "false"
+    }
+
+    fn usage(&self) -> &'static str {
+        "false"
+    }
+
+    fn summary(&self) -> &'static str {
+        "do nothing, unsuccessfully"
+    }
+
+    fn run(
+        &self,
+        _args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        Ok(1)
+    }
+}
+
+// `source` and `.` are the same builtin under two names
+pub struct Source(pub &'static str);
+
+impl super::Builtin for Source {
+    fn name(&self) -> &'static str {
+        self.0
+    }
+
+    fn usage(&self) -> &'static str {
+        if self.0 == "." {
+            ". file [arg...]"
+        } else {
+            "source file [arg...]"
+        }
+    }
+
+    fn summary(&self) -> &'static str {
+        "run a file in the current shell"
+    }
+
+    fn help(&self) -> &'static str {
+        "names without a slash are looked up in $PATH first. any args become\n\
+         the positional parameters while the file runs."
+    }
+
+    fn arity(&self) -> (usize, Option<usize>) {
+        (1, None)
+    }
+
+    // running the script is handled by eval, so this only needs to check
+    // that it can be loaded
+    fn run(
+        &self,
+        args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        source(self.name(), args).map(|_| 0)
+    }
+
+    fn script(
+        &self,
+        args: &[String],
+    ) -> super::Result<Option<super::Script>> {
+        source(self.name(), args).map(Some)
+    }
+}
+
+fn local(args: &[String]) -> super::Result<()> {
+    for arg in args {
+        let (name, value) = arg
+            .split_once('=')
+            .map_or((arg.as_str(), None), |(name, value)| {
+                (name, Some(value))
+            });
+        snafu::ensure!(
+            crate::parser::is_name(name),
+            super::InvalidName { cmd: "local", name }
+        );
+        snafu::ensure!(
+            crate::env::set_local(name, value),
+            super::NotInFunction { cmd: "local" }
+        );
+    }
+    Ok(())
+}
+
+fn export(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    if args.is_empty() {
+        for (name, value) in crate::env::exported_vars() {
+            output.extend(
+                format!("export {}={}\n", name, crate::parser::quote(&value))
+                    .into_bytes(),
+            );
+        }
+        return Ok(());
+    }
+
+    for arg in args {
+        let (name, value) = arg
+            .split_once('=')
+            .map_or((arg.as_str(), None), |(name, value)| {
+                (name, Some(value))
+            });
+        snafu::ensure!(
+            crate::parser::is_name(name),
+            super::InvalidName {
+                cmd: "export",
+                name
+            }
+        );
+        crate::env::export(name, value);
+    }
+    Ok(())
+}
+
+// like in sh, the status reflects the value of the last expression, so that
+// it can be used as a condition
+fn let_(args: &[String]) -> super::Result<i32> {
+    let mut value = 0;
+    for expr in args {
+        value =
+            crate::arith::eval(expr).context(super::Arithmetic { expr })?;
+    }
+    Ok(i32::from(value == 0))
+}
+
+fn loop_control(cmd: &str, args: &[String]) -> super::Result<()> {
+    let count = if let Some(arg) = args.first() {
+        arg.parse()
+            .ok()
+            .filter(|count| *count > 0)
+            .context(super::InvalidNumber { cmd, arg })?
+    } else {
+        1
+    };
+    let loops = crate::env::loops();
+    snafu::ensure!(loops > 0, super::NotInLoop { cmd });
+    // like in sh, breaking out of more loops than exist just exits all of
+    // them
+    let count = std::cmp::min(count, loops);
+    crate::env::set_control(if cmd == "break" {
+        crate::env::Control::Break(count)
+    } else {
+        crate::env::Control::Continue(count)
+    });
+    Ok(())
+}
+
+fn ret(args: &[String]) -> super::Result<i32> {
+    snafu::ensure!(
+        crate::env::depth() > 0,
+        super::NotInFunction { cmd: "return" }
+    );
+    let status = if let Some(arg) = args.first() {
+        arg.parse()
+            .ok()
+            .context(super::InvalidNumber { cmd: "return", arg })?
+    } else {
+        crate::env::status()
+    };
+    crate::env::set_control(crate::env::Control::Return);
+    Ok(status)
+}
+
+// the shell exits once the current command line is done. like in sh, the
+// default status is that of the last command.
+fn exit(args: &[String]) -> super::Result<i32> {
+    let status = if let Some(arg) = args.first() {
+        arg.parse::<i32>()
+            .ok()
+            .context(super::InvalidNumber { cmd: "exit", arg })?
+    } else {
+        crate::env::status()
+    };
+    crate::env::set_control(crate::env::Control::Exit);
+    Ok(status & 0xff)
+}
+
+// the script runs with the given arguments as its positional parameters,
+// or with the current ones if there aren't any
+fn source(cmd: &str, args: &[String]) -> super::Result<super::Script> {
+    let path = find_script(&args[0]);
+    let contents =
+        std::fs::read_to_string(&path).context(super::SourceRead {
+            cmd,
+            path: path.to_string_lossy(),
+        })?;
+    let program = crate::parser::parse(&contents).map_err(Box::new).context(
+        super::SourceParse {
+            cmd,
+            path: path.to_string_lossy(),
+        },
+    )?;
+    let args = if args.len() > 1 {
+        args[1..].to_vec()
+    } else {
+        crate::env::args()
+    };
+    Ok(super::Script { program, args })
+}
+
+// like in sh, names without a slash are looked up in $PATH (although they
+// don't need to be executable), and then in the current directory
+fn find_script(name: &str) -> std::path::PathBuf {
+    if !name.contains('/') {
+        let path_var = crate::env::var("PATH").unwrap_or_default();
+        let found = path_var
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| std::path::Path::new(dir).join(name))
+            .find(|path| path.is_file());
+        if let Some(path) = found {
+            return path;
+        }
+    }
+    std::path::PathBuf::from(name)
+}
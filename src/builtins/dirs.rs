@@ -0,0 +1,455 @@
+use snafu::{OptionExt as _, ResultExt as _};
+use std::os::unix::fs::MetadataExt as _;
+
+pub struct Cd;
+
+impl super::Builtin for Cd {
+    fn name(&self) -> &'static str {
+        "cd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "cd [dir]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "change the current directory"
+    }
+
+    fn help(&self) -> &'static str {
+        "with no dir, changes to $HOME."
+    }
+
+    fn arity(&self) -> (usize, Option<usize>) {
+        (0, Some(1))
+    }
+
+    fn run(
+        &self,
+        args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        cd(args).map(|()| 0)
+    }
+}
+
+pub struct Pushd;
+
+impl super::Builtin for Pushd {
+    fn name(&self) -> &'static str {
+        "pushd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "pushd [dir | +N | -N]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "push a directory onto the directory stack"
+    }
+
+    fn help(&self) -> &'static str {
+        "dir  change to dir, saving the current directory on the stack\n\
+         +N   rotate the stack so that the nth entry from the left is first\n\
+         -N   rotate the stack so that the nth entry from the right is first\n\
+         with no arguments, swaps the first two entries."
+    }
+
+    fn arity(&self) -> (usize, Option<usize>) {
+        (0, Some(1))
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        pushd(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+pub struct Popd;
+
+impl super::Builtin for Popd {
+    fn name(&self) -> &'static str {
+        "popd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "popd [+N | -N]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "pop a directory off the directory stack"
+    }
+
+    fn help(&self) -> &'static str {
+        "+N  remove the nth entry from the left\n\
+         -N  remove the nth entry from the right\n\
+         with no arguments, changes to the directory on top of the stack."
+    }
+
+    fn arity(&self) -> (usize, Option<usize>) {
+        (0, Some(1))
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        popd(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+pub struct Dirs;
+
+impl super::Builtin for Dirs {
+    fn name(&self) -> &'static str {
+        "dirs"
+    }
+
+    fn usage(&self) -> &'static str {
+        "dirs [-clpv]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "show the directory stack"
+    }
+
+    fn help(&self) -> &'static str {
+        "-c  clear the directory stack\n\
+         -l  show full paths rather than abbreviating $HOME\n\
+         -p  show one entry per line\n\
+         -v  show one entry per line, with its position in the stack\n\
+         $DIRSTACK expands to the stack as shown by default, for use in PS1."
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        dirs(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+pub struct Z;
+
+impl super::Builtin for Z {
+    fn name(&self) -> &'static str {
+        "z"
+    }
+
+    fn usage(&self) -> &'static str {
+        "z [-l | -x] [fragment...]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "jump to a frequently used directory"
+    }
+
+    fn help(&self) -> &'static str {
+        "-l  list the matching directories with their scores\n\
+         -x  forget the current directory\n\
+         changes to the highest ranked directory matching all fragments,\n\
+         ranked by how often and how recently it was visited."
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        z(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+pub struct Pwd;
+
+impl super::Builtin for Pwd {
+    fn name(&self) -> &'static str {
+        "pwd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "pwd [-L | -P]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "print the current directory"
+    }
+
+    fn help(&self) -> &'static str {
+        "-L  print $PWD if it names the current directory (the default)\n\
+         -P  print the directory with all symlinks resolved"
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        pwd(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+fn cd(args: &[String]) -> super::Result<()> {
+    let dir = if let Some(dir) = args.first() {
+        std::convert::From::from(dir)
+    } else {
+        std::env::var_os("HOME").context(super::ChdirUnknownHome)?
+    };
+    chdir(std::path::Path::new(&dir))
+}
+
+// every directory change is recorded for `z`
+// like in sh, $PWD is the path that was used to get to the current
+// directory (without resolving symlinks), and $OLDPWD is the previous one
+fn chdir(dir: &std::path::Path) -> super::Result<()> {
+    let old = logical_cwd()?;
+    nix::unistd::chdir(dir).context(super::Chdir {
+        dir: dir.to_string_lossy(),
+    })?;
+    let cwd = std::env::current_dir().context(super::CurrentDir)?;
+    let pwd = Some(normalize(&old.join(dir)))
+        .filter(|pwd| same_file(pwd, &cwd))
+        .unwrap_or_else(|| cwd.clone());
+    crate::env::set_var("OLDPWD", &old.to_string_lossy());
+    crate::env::set_var("PWD", &pwd.to_string_lossy());
+    crate::frecency::add(&cwd);
+    Ok(())
+}
+
+// $PWD if it names the current directory, otherwise the current directory
+// as the os reports it
+fn logical_cwd() -> super::Result<std::path::PathBuf> {
+    let cwd = std::env::current_dir().context(super::CurrentDir)?;
+    Ok(crate::env::var("PWD")
+        .map(std::path::PathBuf::from)
+        .filter(|pwd| pwd.is_absolute() && same_file(pwd, &cwd))
+        .unwrap_or(cwd))
+}
+
+// removes `.` and `..` components without looking at the filesystem, so
+// `..` goes back up through a symlink rather than to its target's parent
+fn normalize(path: &std::path::Path) -> std::path::PathBuf {
+    let mut normalized = std::path::PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// the current directory followed by the directories saved by `pushd`
+fn dir_stack() -> super::Result<Vec<std::path::PathBuf>> {
+    let mut dirs = vec![logical_cwd()?];
+    dirs.extend(crate::env::saved_dirs());
+    Ok(dirs)
+}
+
+// the top of the stack is always the current directory, so changing it
+// means changing directories
+fn set_dir_stack(mut dirs: Vec<std::path::PathBuf>) -> super::Result<()> {
+    chdir(&dirs[0])?;
+    dirs.remove(0);
+    crate::env::set_saved_dirs(dirs);
+    Ok(())
+}
+
+// parses `+N` (counting from the top of the stack) or `-N` (counting from
+// the bottom), returning None if the argument isn't of that form
+fn dir_stack_index(
+    cmd: &str,
+    arg: &str,
+    len: usize,
+) -> super::Result<Option<usize>> {
+    let (from_top, n) = match arg.split_at_checked(1) {
+        Some(("+", n)) => (true, n),
+        Some(("-", n)) => (false, n),
+        _ => return Ok(None),
+    };
+    let Ok(n) = n.parse::<usize>() else {
+        return Ok(None);
+    };
+    snafu::ensure!(n < len, super::DirStackIndex { cmd, arg });
+    Ok(Some(if from_top { n } else { len - 1 - n }))
+}
+
+fn format_dir(dir: &std::path::Path, tilde: bool) -> String {
+    if tilde {
+        crate::env::tilde_dir(dir)
+    } else {
+        dir.display().to_string()
+    }
+}
+
+fn print_dir_stack(dirs: &[std::path::PathBuf], output: &mut Vec<u8>) {
+    let dirs: Vec<_> = dirs.iter().map(|dir| format_dir(dir, true)).collect();
+    output.extend(format!("{}\n", dirs.join(" ")).into_bytes());
+}
+
+// `pushd dir` saves the current directory and changes to dir, `pushd +N`
+// rotates the stack so that the Nth directory is on top, and `pushd` with
+// no arguments swaps the top two directories
+fn pushd(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    let mut dirs = dir_stack()?;
+    if let Some(arg) = args.first() {
+        if let Some(idx) = dir_stack_index("pushd", arg, dirs.len())? {
+            dirs.rotate_left(idx);
+            set_dir_stack(dirs.clone())?;
+        } else {
+            chdir(std::path::Path::new(arg))?;
+            dirs.insert(0, logical_cwd()?);
+            crate::env::set_saved_dirs(dirs[1..].to_vec());
+        }
+    } else {
+        snafu::ensure!(
+            dirs.len() >= 2,
+            super::DirStackEmpty { cmd: "pushd" }
+        );
+        dirs.swap(0, 1);
+        set_dir_stack(dirs.clone())?;
+    }
+    print_dir_stack(&dirs, output);
+    Ok(())
+}
+
+// `popd` removes the top directory from the stack and changes to the new
+// top, and `popd +N` removes the Nth directory
+fn popd(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    let mut dirs = dir_stack()?;
+    snafu::ensure!(dirs.len() >= 2, super::DirStackEmpty { cmd: "popd" });
+    let idx = if let Some(arg) = args.first() {
+        dir_stack_index("popd", arg, dirs.len())?.context(
+            super::InvalidOption {
+                cmd: "popd",
+                option: arg,
+            },
+        )?
+    } else {
+        0
+    };
+    dirs.remove(idx);
+    if idx == 0 {
+        set_dir_stack(dirs.clone())?;
+    } else {
+        crate::env::set_saved_dirs(dirs[1..].to_vec());
+    }
+    print_dir_stack(&dirs, output);
+    Ok(())
+}
+
+// `dirs -c` clears the stack, `-v` numbers the entries, `-p` prints one
+// entry per line, and `-l` doesn't abbreviate $HOME as ~
+fn dirs(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    let mut verbose = false;
+    let mut per_line = false;
+    let mut tilde = true;
+    for arg in args {
+        match arg.as_str() {
+            "-c" => {
+                crate::env::set_saved_dirs(vec![]);
+                return Ok(());
+            }
+            "-v" => verbose = true,
+            "-p" => per_line = true,
+            "-l" => tilde = false,
+            option => {
+                return super::InvalidOption {
+                    cmd: "dirs",
+                    option,
+                }
+                .fail()
+            }
+        }
+    }
+    let dirs = dir_stack()?;
+    let dirs: Vec<_> =
+        dirs.iter().map(|dir| format_dir(dir, tilde)).collect();
+    if verbose {
+        for (i, dir) in dirs.iter().enumerate() {
+            output.extend(format!("{i:2}  {dir}\n").into_bytes());
+        }
+    } else if per_line {
+        for dir in dirs {
+            output.extend(format!("{dir}\n").into_bytes());
+        }
+    } else {
+        output.extend(format!("{}\n", dirs.join(" ")).into_bytes());
+    }
+    Ok(())
+}
+
+// `z fragment...` changes to the highest ranked visited directory matching
+// the fragments, `z -l [fragment...]` lists the matches with their scores,
+// and `z -x` forgets the current directory
+fn z(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    match args.first().map(String::as_str) {
+        None | Some("-l") => {
+            let fragments = args.get(1..).unwrap_or_default();
+            let now = crate::frecency::now();
+            // best matches last, so they are closest to the prompt
+            for entry in crate::frecency::matches(fragments).iter().rev() {
+                output.extend(
+                    format!(
+                        "{:<10.1} {}\n",
+                        entry.score(now),
+                        entry.path.display()
+                    )
+                    .into_bytes(),
+                );
+            }
+            Ok(())
+        }
+        Some("-x") => {
+            snafu::ensure!(
+                args.len() == 1,
+                super::TooManyParams {
+                    cmd: "z",
+                    args,
+                    expected: 1_usize,
+                }
+            );
+            let dir = std::env::current_dir().context(super::CurrentDir)?;
+            snafu::ensure!(
+                crate::frecency::remove(&dir),
+                super::NotFound {
+                    cmd: "z",
+                    name: dir.to_string_lossy(),
+                }
+            );
+            Ok(())
+        }
+        Some(option) if option.starts_with('-') => {
+            super::InvalidOption { cmd: "z", option }.fail()
+        }
+        Some(_) => {
+            let entry = crate::frecency::matches(args)
+                .into_iter()
+                .next()
+                .context(super::NotFound {
+                    cmd: "z",
+                    name: args.join(" "),
+                })?;
+            chdir(&entry.path)
+        }
+    }
+}
+
+// `pwd -P` resolves symlinks, while `pwd -L` (the default) prints the path
+// that was used to get to the current directory, if it is known
+fn pwd(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    let mut physical = false;
+    for arg in args {
+        match arg.as_str() {
+            "-L" => physical = false,
+            "-P" => physical = true,
+            option => {
+                return super::InvalidOption { cmd: "pwd", option }.fail()
+            }
+        }
+    }
+    let dir = if physical {
+        std::env::current_dir()
+            .and_then(std::fs::canonicalize)
+            .context(super::CurrentDir)?
+    } else {
+        logical_cwd()?
+    };
+    output.extend(format!("{}\n", dir.display()).into_bytes());
+    Ok(())
+}
+
+fn same_file(a: &std::path::Path, b: &std::path::Path) -> bool {
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
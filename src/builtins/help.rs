@@ -0,0 +1,77 @@
+use std::io::Write as _;
+
+pub struct Help;
+
+impl super::Builtin for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn usage(&self) -> &'static str {
+        "help [name...]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "show information about builtins"
+    }
+
+    fn help(&self) -> &'static str {
+        "with no names, lists all builtins."
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        help(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+fn help(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    if args.is_empty() {
+        list(output);
+        return Ok(());
+    }
+
+    let mut res = Ok(());
+    for (i, name) in args.iter().enumerate() {
+        let Some(builtin) = super::get(name) else {
+            res = super::NotFound { cmd: "help", name }.fail();
+            continue;
+        };
+        if i > 0 {
+            writeln!(output).unwrap();
+        }
+        writeln!(output, "usage: {}", builtin.usage()).unwrap();
+        writeln!(output).unwrap();
+        writeln!(output, "{}", builtin.summary()).unwrap();
+        if !builtin.help().is_empty() {
+            writeln!(output).unwrap();
+            for line in builtin.help().lines() {
+                writeln!(output, "  {line}").unwrap();
+            }
+        }
+    }
+    res
+}
+
+fn list(output: &mut Vec<u8>) {
+    let width = super::all()
+        .iter()
+        .map(|builtin| builtin.name().len())
+        .max()
+        .unwrap_or(0);
+    for builtin in super::all() {
+        writeln!(
+            output,
+            "  {:<width$}  {}",
+            builtin.name(),
+            builtin.summary(),
+            width = width
+        )
+        .unwrap();
+    }
+    writeln!(output).unwrap();
+    writeln!(
+        output,
+        "run `help name` for more information about a builtin"
+    )
+    .unwrap();
+}
@@ -0,0 +1,173 @@
+use snafu::OptionExt as _;
+use std::io::Write as _;
+
+pub struct History;
+
+impl super::Builtin for History {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn usage(&self) -> &'static str {
+        "history [-lt] [-c | -d n | n | pattern]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "show or edit the command history"
+    }
+
+    fn help(&self) -> &'static str {
+        "-t       show when each line was run\n\
+         -l       also show the duration, exit status and directory\n\
+         -c       clear the history, and the lines saved by this shell\n\
+         -d n     delete line n, including from the saved history\n\
+         n        only show the last n lines\n\
+         pattern  only show lines containing pattern\n\
+         lines can be rerun with `!!` (the previous line), `!n` (line n),\n\
+         `!-n` (the nth previous line) or `!prefix` (the last line\n\
+         starting with prefix), and `!$` is the last word of the previous\n\
+         line."
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        history(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+fn history(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    let mut args = args;
+    let mut times = false;
+    let mut long = false;
+    while let Some(arg) = args.first() {
+        if arg.len() < 2
+            || !arg.starts_with('-')
+            || !arg[1..].chars().all(|c| c == 't' || c == 'l')
+        {
+            break;
+        }
+        times |= arg.contains('t');
+        long |= arg.contains('l');
+        args = &args[1..];
+    }
+
+    match args.first().map(String::as_str) {
+        Some("-c") => {
+            snafu::ensure!(
+                args.len() == 1,
+                super::TooManyParams {
+                    cmd: "history",
+                    args,
+                    expected: 1_usize,
+                }
+            );
+            crate::history::clear();
+        }
+        Some("-d") => {
+            snafu::ensure!(
+                args.len() >= 2,
+                super::NotEnoughParams {
+                    cmd: "history",
+                    args,
+                    expected: 2_usize,
+                }
+            );
+            snafu::ensure!(
+                args.len() <= 2,
+                super::TooManyParams {
+                    cmd: "history",
+                    args,
+                    expected: 2_usize,
+                }
+            );
+            let arg = &args[1];
+            let n: usize =
+                arg.parse().ok().context(super::InvalidNumber {
+                    cmd: "history",
+                    arg,
+                })?;
+            snafu::ensure!(
+                n > 0 && crate::history::remove(n - 1),
+                super::HistoryIndex { arg }
+            );
+        }
+        Some(option) if option.starts_with('-') => {
+            return super::InvalidOption {
+                cmd: "history",
+                option,
+            }
+            .fail();
+        }
+        _ => {
+            snafu::ensure!(
+                args.len() <= 1,
+                super::TooManyParams {
+                    cmd: "history",
+                    args,
+                    expected: 1_usize,
+                }
+            );
+            // like in bash, a number is a count of lines to show rather
+            // than a pattern
+            let mut count = None;
+            let mut query = crate::history::Query::new();
+            match args.first() {
+                Some(arg)
+                    if !arg.is_empty()
+                        && arg.chars().all(|c| c.is_ascii_digit()) =>
+                {
+                    count = Some(arg.parse::<usize>().ok().context(
+                        super::InvalidNumber {
+                            cmd: "history",
+                            arg,
+                        },
+                    )?);
+                }
+                Some(pattern) => query = query.contains(pattern),
+                None => {}
+            }
+            let mut entries = crate::history::search(&query);
+            if let Some(count) = count {
+                entries.drain(..entries.len().saturating_sub(count));
+            }
+            for (idx, entry) in entries {
+                write!(output, "{:>5}  ", idx + 1).unwrap();
+                if times || long {
+                    write!(
+                        output,
+                        "{}  ",
+                        crate::history::format_time(entry.start)
+                    )
+                    .unwrap();
+                }
+                if long {
+                    write!(
+                        output,
+                        "{:>8}  {:>3}  {}  ",
+                        entry
+                            .duration
+                            .map_or_else(|| "-".to_string(), format_duration),
+                        entry.status.map_or_else(
+                            || "-".to_string(),
+                            |s| s.to_string()
+                        ),
+                        entry.cwd.display()
+                    )
+                    .unwrap();
+                }
+                writeln!(output, "{}", entry.line).unwrap();
+            }
+        }
+    }
+    Ok(())
+}
+
+// durations are in milliseconds
+fn format_duration(duration: u64) -> String {
+    if duration < 1000 {
+        format!("{duration}ms")
+    } else if duration < 60 * 1000 {
+        format!("{}.{:02}s", duration / 1000, duration % 1000 / 10)
+    } else {
+        format!("{}m{:02}s", duration / 60000, duration % 60000 / 1000)
+    }
+}
@@ -0,0 +1,278 @@
+pub struct Type;
+
+impl super::Builtin for Type {
+    fn name(&self) -> &'static str {
+        "type"
+    }
+
+    fn usage(&self) -> &'static str {
+        "type [-t] name..."
+    }
+
+    fn summary(&self) -> &'static str {
+        "describe how a command name would be run"
+    }
+
+    fn help(&self) -> &'static str {
+        "-t  print only one of alias, function, builtin or file"
+    }
+
+    fn arity(&self) -> (usize, Option<usize>) {
+        (1, None)
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        type_(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+pub struct Which;
+
+impl super::Builtin for Which {
+    fn name(&self) -> &'static str {
+        "which"
+    }
+
+    fn usage(&self) -> &'static str {
+        "which name..."
+    }
+
+    fn summary(&self) -> &'static str {
+        "show what a command name refers to"
+    }
+
+    fn arity(&self) -> (usize, Option<usize>) {
+        (1, None)
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        which(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+pub struct Command;
+
+impl super::Builtin for Command {
+    fn name(&self) -> &'static str {
+        "command"
+    }
+
+    fn usage(&self) -> &'static str {
+        "command [-v | -V] name..."
+    }
+
+    fn summary(&self) -> &'static str {
+        "run a command, bypassing functions"
+    }
+
+    fn help(&self) -> &'static str {
+        "-v  print how each name would be run\n\
+         -V  describe how each name would be run"
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        command(args, &mut io.stdout)
+    }
+}
+
+pub struct Hash;
+
+impl super::Builtin for Hash {
+    fn name(&self) -> &'static str {
+        "hash"
+    }
+
+    fn usage(&self) -> &'static str {
+        "hash [-r | name...]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "remember or list the locations of commands"
+    }
+
+    fn help(&self) -> &'static str {
+        "-r  forget all remembered locations\n\
+         with no arguments, lists the remembered locations."
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        hash(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+fn type_(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    let (terse, names) = match args.first().map(String::as_str) {
+        Some("-t") => (true, &args[1..]),
+        Some(option) if option.starts_with('-') => {
+            return super::InvalidOption {
+                cmd: "type",
+                option,
+            }
+            .fail();
+        }
+        _ => (false, args),
+    };
+    snafu::ensure!(
+        !names.is_empty(),
+        super::NotEnoughParams {
+            cmd: "type",
+            args,
+            expected: 1_usize,
+        }
+    );
+
+    let mut res = Ok(());
+    for name in names {
+        let Some(resolution) = crate::resolver::resolve(name) else {
+            res = super::NotFound { cmd: "type", name }.fail();
+            continue;
+        };
+        let description = if terse {
+            match resolution {
+                crate::resolver::Resolution::Alias(_) => "alias",
+                crate::resolver::Resolution::Function(_) => "function",
+                crate::resolver::Resolution::Builtin => "builtin",
+                crate::resolver::Resolution::Executable { .. } => "file",
+            }
+            .to_string()
+        } else {
+            describe(name, resolution)
+        };
+        output.extend(format!("{description}\n").into_bytes());
+    }
+    res
+}
+
+fn describe(name: &str, resolution: crate::resolver::Resolution) -> String {
+    match resolution {
+        crate::resolver::Resolution::Alias(value) => {
+            format!("{name} is aliased to `{value}`")
+        }
+        crate::resolver::Resolution::Function(body) => {
+            let def =
+                crate::ast::Command::FunctionDef(crate::ast::FunctionDef {
+                    name: name.to_string(),
+                    body,
+                });
+            format!("{name} is a function\n{def}")
+        }
+        crate::resolver::Resolution::Builtin => {
+            format!("{name} is a shell builtin")
+        }
+        crate::resolver::Resolution::Executable { path, hashed } => {
+            if hashed {
+                format!("{} is hashed ({})", name, path.display())
+            } else {
+                format!("{} is {}", name, path.display())
+            }
+        }
+    }
+}
+
+fn which(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    let mut res = Ok(());
+    for name in args {
+        let line = match crate::resolver::resolve(name) {
+            Some(crate::resolver::Resolution::Alias(value)) => {
+                format!("{name}: aliased to {value}")
+            }
+            Some(crate::resolver::Resolution::Function(_)) => {
+                format!("{name}: shell function")
+            }
+            Some(crate::resolver::Resolution::Builtin) => {
+                format!("{name}: shell builtin")
+            }
+            Some(crate::resolver::Resolution::Executable {
+                path, ..
+            }) => path.display().to_string(),
+            None => {
+                res = super::NotFound { cmd: "which", name }.fail();
+                continue;
+            }
+        };
+        output.extend(format!("{line}\n").into_bytes());
+    }
+    res
+}
+
+// running a command through `command` is handled by eval, so this only
+// needs to handle looking commands up
+fn command(args: &[String], output: &mut Vec<u8>) -> super::Result<i32> {
+    let verbose = match args.first().map(String::as_str) {
+        None => return Ok(0),
+        Some("-v") => false,
+        Some("-V") => true,
+        Some(option) => {
+            return super::InvalidOption {
+                cmd: "command",
+                option,
+            }
+            .fail()
+        }
+    };
+
+    // like in sh, missing commands are only reported through the exit
+    // status
+    let mut status = 0;
+    for name in &args[1..] {
+        let Some(resolution) = crate::resolver::resolve(name) else {
+            status = 1;
+            continue;
+        };
+        let line = if verbose {
+            describe(name, resolution)
+        } else {
+            match resolution {
+                crate::resolver::Resolution::Alias(value) => {
+                    crate::aliases::format(name, &value)
+                }
+                crate::resolver::Resolution::Function(_)
+                | crate::resolver::Resolution::Builtin => name.clone(),
+                crate::resolver::Resolution::Executable { path, .. } => {
+                    path.display().to_string()
+                }
+            }
+        };
+        output.extend(format!("{line}\n").into_bytes());
+    }
+    Ok(status)
+}
+
+fn hash(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    match args.first().map(String::as_str) {
+        None => {
+            for (name, path) in crate::resolver::hashed() {
+                output.extend(
+                    format!("{}\t{}\n", name, path.display()).into_bytes(),
+                );
+            }
+            Ok(())
+        }
+        Some("-r") => {
+            snafu::ensure!(
+                args.len() == 1,
+                super::TooManyParams {
+                    cmd: "hash",
+                    args,
+                    expected: 1_usize,
+                }
+            );
+            crate::resolver::clear_hash();
+            Ok(())
+        }
+        Some(option) if option.starts_with('-') => super::InvalidOption {
+            cmd: "hash",
+            option,
+        }
+        .fail(),
+        Some(_) => {
+            let mut res = Ok(());
+            for name in args {
+                if crate::resolver::rehash(name).is_none() {
+                    res = super::NotFound { cmd: "hash", name }.fail();
+                }
+            }
+            res
+        }
+    }
+}
@@ -0,0 +1,514 @@
+use futures::future::Future as _;
+use snafu::{OptionExt as _, ResultExt as _};
+use std::io::Write as _;
+use std::os::fd::AsFd as _;
+use std::os::unix::process::ExitStatusExt as _;
+
+mod alias;
+mod bind;
+mod control;
+mod dirs;
+mod help;
+mod history;
+mod lookup;
+mod process;
+mod read;
+mod test;
+mod text;
+
+#[derive(Debug, snafu::Snafu)]
+pub enum Error {
+    #[snafu(display("unknown builtin {}", cmd))]
+    UnknownBuiltin { cmd: String },
+
+    #[snafu(display(
+        "not enough parameters for {} (got {}, expected {})",
+        cmd, args.len(), expected
+    ))]
+    NotEnoughParams {
+        cmd: String,
+        args: Vec<String>,
+        expected: usize,
+    },
+
+    #[snafu(display(
+        "too many parameters for {} (got {}, expected {})",
+        cmd, args.len(), expected
+    ))]
+    TooManyParams {
+        cmd: String,
+        args: Vec<String>,
+        expected: usize,
+    },
+
+    #[snafu(display("failed to cd to {}: {}", dir, source))]
+    Chdir { dir: String, source: nix::Error },
+
+    #[snafu(display("failed to cd: $HOME not set"))]
+    ChdirUnknownHome,
+
+    #[snafu(display("failed to get the current directory: {}", source))]
+    CurrentDir { source: std::io::Error },
+
+    #[snafu(display("{}: directory stack empty", cmd))]
+    DirStackEmpty { cmd: String },
+
+    #[snafu(display(
+        "{}: {}: directory stack index out of range",
+        cmd,
+        arg
+    ))]
+    DirStackIndex { cmd: String, arg: String },
+
+    #[snafu(display("history: {}: history position out of range", arg))]
+    HistoryIndex { arg: String },
+
+    #[snafu(display("alias {} not found", name))]
+    UnknownAlias { name: String },
+
+    #[snafu(display("failed to bind: {}", source))]
+    Bind { source: crate::keybindings::Error },
+
+    #[snafu(display("failed to unbind {}: no such binding", keys))]
+    Unbind { keys: String },
+
+    #[snafu(display("{}: can only be used in a loop", cmd))]
+    NotInLoop { cmd: String },
+
+    #[snafu(display("{}: can only be used in a function", cmd))]
+    NotInFunction { cmd: String },
+
+    #[snafu(display("{}: numeric argument required, got {}", cmd, arg))]
+    InvalidNumber { cmd: String, arg: String },
+
+    #[snafu(display("{}: invalid variable name {}", cmd, name))]
+    InvalidName { cmd: String, name: String },
+
+    #[snafu(display("failed to evaluate `{}`: {}", expr, source))]
+    Arithmetic {
+        expr: String,
+        source: crate::arith::Error,
+    },
+
+    #[snafu(display("{}: {}: not found", cmd, name))]
+    NotFound { cmd: String, name: String },
+
+    #[snafu(display("{}: invalid option {}", cmd, option))]
+    InvalidOption { cmd: String, option: String },
+
+    #[snafu(display("{}: {}", cmd, message))]
+    Test { cmd: String, message: String },
+
+    #[snafu(display("{}: failed to read {}: {}", cmd, path, source))]
+    SourceRead {
+        cmd: String,
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display(
+        "{}: failed to parse {}: {} ({})",
+        cmd,
+        path,
+        source,
+        source.location()
+    ))]
+    SourceParse {
+        cmd: String,
+        path: String,
+        source: Box<crate::parser::Error>,
+    },
+
+    #[snafu(display("exec: failed to run {}: {}", cmd, source))]
+    Exec { cmd: String, source: std::io::Error },
+
+    #[snafu(display("umask: invalid mode {}", mode))]
+    InvalidMode { mode: String },
+
+    #[snafu(display("ulimit: failed to {} {}: {}", action, name, source))]
+    Rlimit {
+        action: String,
+        name: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("printf: invalid format {}", format))]
+    InvalidFormat { format: String },
+
+    #[snafu(display("failed to read input: {}", source))]
+    ReadInput { source: std::io::Error },
+
+    #[snafu(display("failed to write output: {}", source))]
+    WriteOutput { source: std::io::Error },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    // like in sh, misuse of a builtin is distinguished from it failing
+    pub fn status(&self) -> i32 {
+        match self {
+            Self::NotEnoughParams { .. }
+            | Self::TooManyParams { .. }
+            | Self::InvalidOption { .. }
+            | Self::Test { .. } => 2,
+            Self::Exec { .. } => 126,
+            _ => 1,
+        }
+    }
+}
+
+// a command which is implemented by the shell itself, usually because it
+// needs to change the state of the shell
+pub trait Builtin: Sync {
+    fn name(&self) -> &'static str;
+
+    // a summary of the arguments, like `cd [dir]`
+    fn usage(&self) -> &'static str;
+
+    // a one line description, shown in the list of builtins by `help`
+    fn summary(&self) -> &'static str;
+
+    // a description of the options and behavior, shown by `help name`
+    fn help(&self) -> &'static str {
+        ""
+    }
+
+    // the minimum and (if there is one) maximum number of arguments, which
+    // are checked before the builtin is run
+    fn arity(&self) -> (usize, Option<usize>) {
+        (0, None)
+    }
+
+    // returns the exit status
+    fn run(&self, args: &[String], io: &mut Io) -> Result<i32>;
+
+    // builtins like `source` run shell code in the current shell rather
+    // than doing anything themselves. if this returns a script, eval runs
+    // it instead of calling run.
+    fn script(&self, _args: &[String]) -> Result<Option<Script>> {
+        Ok(None)
+    }
+}
+
+pub struct Script {
+    pub program: crate::ast::Program,
+    // the positional parameters to run the script with
+    pub args: Vec<String>,
+}
+
+// the standard input and output of a builtin. builtins run in the shell
+// process rather than under a pty, so output is collected and then either
+// reported as output events or written to wherever stdout was redirected.
+#[derive(Default)]
+pub struct Io {
+    stdin: Option<std::sync::Arc<std::os::fd::OwnedFd>>,
+    pub stdout: Vec<u8>,
+    // goes to the terminal unless stderr itself was redirected
+    pub stderr: Vec<u8>,
+}
+
+impl Io {
+    // the redirected input if there is one, otherwise the terminal
+    pub fn stdin(&self) -> std::io::Result<std::fs::File> {
+        let fd = match &self.stdin {
+            Some(fd) => fd.try_clone()?,
+            None => std::io::stdin().as_fd().try_clone_to_owned()?,
+        };
+        Ok(fd.into())
+    }
+}
+
+// sorted by name
+const BUILTINS: &[&dyn Builtin] = &[
+    &control::Source("."),
+    &control::Colon,
+    &test::Bracket,
+    &alias::Alias,
+    &bind::Bind,
+    &control::Break,
+    &dirs::Cd,
+    &lookup::Command,
+    &control::Continue,
+    &dirs::Dirs,
+    &text::Echo,
+    &process::Exec,
+    &control::Exit,
+    &control::Export,
+    &control::False,
+    &lookup::Hash,
+    &help::Help,
+    &history::History,
+    &control::Let,
+    &control::Local,
+    &dirs::Popd,
+    &text::Printf,
+    &dirs::Pushd,
+    &dirs::Pwd,
+    &read::Read,
+    &control::Return,
+    &control::Source("source"),
+    &test::Test,
+    &control::True,
+    &lookup::Type,
+    &process::Ulimit,
+    &process::Umask,
+    &alias::Unalias,
+    &lookup::Which,
+    &dirs::Z,
+];
+
+pub fn get(cmd: &str) -> Option<&'static dyn Builtin> {
+    BUILTINS
+        .iter()
+        .copied()
+        .find(|builtin| builtin.name() == cmd)
+}
+
+pub fn all() -> &'static [&'static dyn Builtin] {
+    BUILTINS
+}
+
+pub fn is_builtin(cmd: &str) -> bool {
+    get(cmd).is_some()
+}
+
+// returns a stream of the events produced by running the builtin
+pub fn run(cmd: &str, args: &[String]) -> Result<Execution> {
+    let builtin = get(cmd).context(UnknownBuiltin { cmd })?;
+    Ok(Execution::new(builtin, args))
+}
+
+// the script to run for builtins like `source`, if cmd is one of those
+pub fn script(cmd: &str, args: &[String]) -> Result<Option<Script>> {
+    let builtin = get(cmd).context(UnknownBuiltin { cmd })?;
+    check_arity(builtin, args)?;
+    builtin.script(args)
+}
+
+fn check_arity(builtin: &dyn Builtin, args: &[String]) -> Result<()> {
+    let (min, max) = builtin.arity();
+    snafu::ensure!(
+        args.len() >= min,
+        NotEnoughParams {
+            cmd: builtin.name(),
+            args,
+            expected: min,
+        }
+    );
+    if let Some(max) = max {
+        snafu::ensure!(
+            args.len() <= max,
+            TooManyParams {
+                cmd: builtin.name(),
+                args,
+                expected: max,
+            }
+        );
+    }
+    Ok(())
+}
+
+// the output meant for the terminal and the wait status of a builtin
+// running on its own thread
+type ThreadExit = futures::sync::oneshot::Receiver<Result<(Vec<u8>, i32)>>;
+
+#[must_use = "streams do nothing unless polled"]
+pub struct Execution {
+    builtin: &'static dyn Builtin,
+    args: Vec<String>,
+    stdin: Option<std::sync::Arc<std::os::fd::OwnedFd>>,
+    stdout: Option<std::sync::Arc<std::os::fd::OwnedFd>>,
+    stderr: Option<std::sync::Arc<std::os::fd::OwnedFd>>,
+    thread: bool,
+    started: bool,
+    exit: Option<ThreadExit>,
+    events:
+        Option<std::collections::VecDeque<tokio_pty_process_stream::Event>>,
+}
+
+impl Execution {
+    fn new(builtin: &'static dyn Builtin, args: &[String]) -> Self {
+        Self {
+            builtin,
+            args: args.to_vec(),
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            thread: false,
+            started: false,
+            exit: None,
+            events: None,
+        }
+    }
+
+    // reads input from the given file descriptor instead of the terminal
+    pub fn stdin(mut self, fd: std::sync::Arc<std::os::fd::OwnedFd>) -> Self {
+        self.stdin = Some(fd);
+        self
+    }
+
+    // sends output to the given file descriptor instead of producing
+    // output events
+    pub fn stdout(
+        mut self,
+        fd: std::sync::Arc<std::os::fd::OwnedFd>,
+    ) -> Self {
+        self.stdout = Some(fd);
+        self
+    }
+
+    // sends error messages to the given file descriptor instead of
+    // producing output events
+    pub fn stderr(
+        mut self,
+        fd: std::sync::Arc<std::os::fd::OwnedFd>,
+    ) -> Self {
+        self.stderr = Some(fd);
+        self
+    }
+
+    // runs the builtin on its own thread rather than on the one driving
+    // the shell. this is how builtins run in pipelines, where they need to
+    // run at the same time as the other commands and shouldn't block the
+    // shell while reading from or writing to a pipe. unlike in sh, they
+    // still run in the shell process, so (like the last command of a
+    // pipeline in zsh) they can change the state of the shell.
+    pub fn thread(mut self, thread: bool) -> Self {
+        self.thread = thread;
+        self
+    }
+
+    // runs the builtin and writes its output to wherever it was
+    // redirected. returns the output that wasn't redirected and the wait
+    // status.
+    fn execute(&self) -> Result<(Vec<u8>, i32)> {
+        let mut io = Io {
+            stdin: self.stdin.clone(),
+            ..Io::default()
+        };
+        let res = check_arity(self.builtin, &self.args)
+            .and_then(|()| self.builtin.run(&self.args, &mut io));
+        let status = match res {
+            Ok(status) => (status & 0xff) << 8,
+            // remind the user how the builtin is meant to be called
+            Err(
+                e @ (Error::NotEnoughParams { .. }
+                | Error::TooManyParams { .. }
+                | Error::InvalidOption { .. }),
+            ) => {
+                io.stderr.extend(
+                    format!("{}\nusage: {}\n", e, self.builtin.usage())
+                        .into_bytes(),
+                );
+                e.status() << 8
+            }
+            Err(e) => {
+                io.stderr.extend(format!("{e}\n").into_bytes());
+                e.status() << 8
+            }
+        };
+        for (fd, buf) in [
+            (&self.stdout, &mut io.stdout),
+            (&self.stderr, &mut io.stderr),
+        ] {
+            if let Some(fd) = fd {
+                let fd = fd.try_clone().context(WriteOutput)?;
+                std::fs::File::from(fd)
+                    .write_all(buf)
+                    .context(WriteOutput)?;
+                buf.clear();
+            }
+        }
+        let mut output = io.stdout;
+        output.append(&mut io.stderr);
+        Ok((output, status))
+    }
+
+    // anything which isn't redirected is sent back once the builtin
+    // finishes, so that it can still be reported as output events. this
+    // uses a thread rather than forking, since the child of a fork in a
+    // multithreaded process can't safely lock the shell's state or even
+    // allocate.
+    fn spawn(&mut self) -> ThreadExit {
+        // the thread gets our references to the pipes, so that the other
+        // ends of them see eof as soon as it finishes
+        let execution = Self {
+            stdin: self.stdin.take(),
+            stdout: self.stdout.take(),
+            stderr: self.stderr.take(),
+            ..Self::new(self.builtin, &self.args)
+        };
+        let (send, recv) = futures::sync::oneshot::channel();
+        std::thread::spawn(move || {
+            // the receiver only goes away if we stopped caring
+            let _ = send.send(execution.execute());
+        });
+        recv
+    }
+}
+
+impl futures::stream::Stream for Execution {
+    type Item = tokio_pty_process_stream::Event;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        if !self.started {
+            self.started = true;
+            if self.thread {
+                self.exit = Some(self.spawn());
+            }
+            return Ok(futures::Async::Ready(Some(
+                tokio_pty_process_stream::Event::CommandStart {
+                    cmd: self.builtin.name().to_string(),
+                    args: self.args.clone(),
+                },
+            )));
+        }
+
+        if self.events.is_none() {
+            let (output, status) = if let Some(exit) = &mut self.exit {
+                match exit.poll() {
+                    Ok(futures::Async::Ready(res)) => res?,
+                    Ok(futures::Async::NotReady) => {
+                        return Ok(futures::Async::NotReady)
+                    }
+                    // the builtin panicked, which would have taken down
+                    // the shell if it hadn't been on its own thread
+                    Err(futures::sync::oneshot::Canceled) => {
+                        panic!("{} panicked", self.builtin.name())
+                    }
+                }
+            } else {
+                self.execute()?
+            };
+
+            let mut events = std::collections::VecDeque::new();
+            if !output.is_empty() {
+                events.push_back(tokio_pty_process_stream::Event::Output {
+                    data: crlf(&output),
+                });
+            }
+            events.push_back(tokio_pty_process_stream::Event::CommandExit {
+                status: std::process::ExitStatus::from_raw(status),
+            });
+            self.events = Some(events);
+        }
+
+        Ok(futures::Async::Ready(
+            self.events.as_mut().unwrap().pop_front(),
+        ))
+    }
+}
+
+// builtins don't run under a pty, so we need to do the newline translation
+// that the pty would normally do for us
+fn crlf(output: &[u8]) -> Vec<u8> {
+    output.iter().fold(vec![], |mut acc, &c| {
+        if c == b'\n' {
+            acc.push(b'\r');
+        }
+        acc.push(c);
+        acc
+    })
+}
@@ -0,0 +1,441 @@
+use snafu::{OptionExt as _, ResultExt as _};
+use std::io::Write as _;
+use std::os::unix::process::CommandExt as _;
+
+pub struct Exec;
+
+impl super::Builtin for Exec {
+    fn name(&self) -> &'static str {
+        "exec"
+    }
+
+    fn usage(&self) -> &'static str {
+        "exec [cmd [arg...]]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "replace the shell with a command"
+    }
+
+    fn help(&self) -> &'static str {
+        "cmd must be an executable, not a builtin or function."
+    }
+
+    fn run(
+        &self,
+        args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        exec(args)
+    }
+}
+
+pub struct Umask;
+
+impl super::Builtin for Umask {
+    fn name(&self) -> &'static str {
+        "umask"
+    }
+
+    fn usage(&self) -> &'static str {
+        "umask [-S] [mode]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "show or set the file creation mask"
+    }
+
+    fn help(&self) -> &'static str {
+        "-S  show the mask as the permissions it allows, like u=rwx,g=rx,o=rx\n\
+         mode can be either an octal mask or a symbolic mode."
+    }
+
+    fn arity(&self) -> (usize, Option<usize>) {
+        (0, Some(2))
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        umask(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+pub struct Ulimit;
+
+impl super::Builtin for Ulimit {
+    fn name(&self) -> &'static str {
+        "ulimit"
+    }
+
+    fn usage(&self) -> &'static str {
+        "ulimit [-HS] [-a | -cdflmnstuv] [limit]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "show or set resource limits"
+    }
+
+    fn help(&self) -> &'static str {
+        "-H  use the hard limit\n\
+         -S  use the soft limit\n\
+         -a  show all limits\n\
+         -c  core file size, in blocks\n\
+         -d  data segment size, in kilobytes\n\
+         -f  file size, in blocks (the default)\n\
+         -l  locked memory size, in kilobytes\n\
+         -m  resident set size, in kilobytes\n\
+         -n  number of open files\n\
+         -s  stack size, in kilobytes\n\
+         -t  cpu time, in seconds\n\
+         -u  number of processes\n\
+         -v  virtual memory size, in kilobytes\n\
+         limit can be a number or `unlimited`. both limits are set unless -H\n\
+         or -S is given."
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        ulimit(args, &mut io.stdout).map(|()| 0)
+    }
+}
+
+// replaces the shell with the given command, so this only returns if that
+// fails. like in sh, exec with no command does nothing.
+fn exec(args: &[String]) -> super::Result<i32> {
+    let Some((cmd, args)) = args.split_first() else {
+        return Ok(0);
+    };
+    // builtins and functions can't replace the shell process
+    let Some((path, _)) = crate::resolver::resolve_executable(cmd) else {
+        return super::NotFound {
+            cmd: "exec",
+            name: cmd,
+        }
+        .fail();
+    };
+    let e = crate::process::command(cmd, Some(&path)).args(args).exec();
+    Err(e).context(super::Exec { cmd })
+}
+
+fn umask(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    let (symbolic, mode) = match args.first().map(String::as_str) {
+        Some("-S") => (true, args.get(1)),
+        Some(option) if option.starts_with('-') => {
+            return super::InvalidOption {
+                cmd: "umask",
+                option,
+            }
+            .fail()
+        }
+        _ => {
+            snafu::ensure!(
+                args.len() <= 1,
+                super::TooManyParams {
+                    cmd: "umask",
+                    args,
+                    expected: 1_usize,
+                }
+            );
+            (false, args.first())
+        }
+    };
+
+    let current = get_umask();
+    if let Some(mode) = mode {
+        let mask = parse_umask(mode, current)
+            .context(super::InvalidMode { mode })?;
+        nix::sys::stat::umask(nix::sys::stat::Mode::from_bits_truncate(mask));
+    } else if symbolic {
+        let perms = !current & 0o777;
+        let clauses: Vec<_> = ["u", "g", "o"]
+            .iter()
+            .enumerate()
+            .map(|(i, who)| {
+                let bits = perms >> (6 - 3 * i);
+                let mut clause = format!("{who}=");
+                for (bit, c) in [(0o4, 'r'), (0o2, 'w'), (0o1, 'x')] {
+                    if bits & bit != 0 {
+                        clause.push(c);
+                    }
+                }
+                clause
+            })
+            .collect();
+        writeln!(output, "{}", clauses.join(",")).unwrap();
+    } else {
+        writeln!(output, "{current:04o}").unwrap();
+    }
+    Ok(())
+}
+
+// the umask can only be read by setting it, so it has to be put back
+fn get_umask() -> nix::libc::mode_t {
+    let mask = nix::sys::stat::umask(nix::sys::stat::Mode::empty());
+    nix::sys::stat::umask(mask);
+    mask.bits()
+}
+
+// accepts either an octal mask or a symbolic mode like `u=rwx,go-w`, which
+// describes the permissions to allow rather than the ones to mask
+fn parse_umask(
+    mode: &str,
+    current: nix::libc::mode_t,
+) -> Option<nix::libc::mode_t> {
+    if mode.starts_with(|c: char| c.is_ascii_digit()) {
+        return nix::libc::mode_t::from_str_radix(mode, 8)
+            .ok()
+            .filter(|mask| *mask <= 0o777);
+    }
+
+    let mut perms = !current & 0o777;
+    for clause in mode.split(',') {
+        let mut chars = clause.chars().peekable();
+        let mut who = 0;
+        while let Some(c) = chars.peek() {
+            who |= match c {
+                'u' => 0o700,
+                'g' => 0o070,
+                'o' => 0o007,
+                'a' => 0o777,
+                _ => break,
+            };
+            chars.next();
+        }
+        if who == 0 {
+            who = 0o777;
+        }
+        // a clause needs at least one operator, but can have several, like
+        // `u+r-w`
+        chars.peek()?;
+        while let Some(op) = chars.next() {
+            let mut bits = 0;
+            while let Some(c) = chars.peek() {
+                bits |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' => 0o111,
+                    _ => break,
+                };
+                chars.next();
+            }
+            match op {
+                '+' => perms |= bits & who,
+                '-' => perms &= !(bits & who),
+                '=' => perms = (perms & !who) | (bits & who),
+                _ => return None,
+            }
+        }
+    }
+    Some(!perms & 0o777)
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = nix::libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type Resource = nix::libc::c_int;
+
+struct Limit {
+    option: char,
+    name: &'static str,
+    resource: Resource,
+    // limits are displayed and set in multiples of this many of the units
+    // the kernel uses
+    unit: nix::libc::rlim_t,
+}
+
+const LIMITS: &[Limit] = &[
+    Limit {
+        option: 'c',
+        name: "core file size (blocks)",
+        resource: nix::libc::RLIMIT_CORE,
+        unit: 512,
+    },
+    Limit {
+        option: 'd',
+        name: "data seg size (kbytes)",
+        resource: nix::libc::RLIMIT_DATA,
+        unit: 1024,
+    },
+    Limit {
+        option: 'f',
+        name: "file size (blocks)",
+        resource: nix::libc::RLIMIT_FSIZE,
+        unit: 512,
+    },
+    Limit {
+        option: 'l',
+        name: "max locked memory (kbytes)",
+        resource: nix::libc::RLIMIT_MEMLOCK,
+        unit: 1024,
+    },
+    Limit {
+        option: 'm',
+        name: "max memory size (kbytes)",
+        resource: nix::libc::RLIMIT_RSS,
+        unit: 1024,
+    },
+    Limit {
+        option: 'n',
+        name: "open files",
+        resource: nix::libc::RLIMIT_NOFILE,
+        unit: 1,
+    },
+    Limit {
+        option: 's',
+        name: "stack size (kbytes)",
+        resource: nix::libc::RLIMIT_STACK,
+        unit: 1024,
+    },
+    Limit {
+        option: 't',
+        name: "cpu time (seconds)",
+        resource: nix::libc::RLIMIT_CPU,
+        unit: 1,
+    },
+    Limit {
+        option: 'u',
+        name: "max user processes",
+        resource: nix::libc::RLIMIT_NPROC,
+        unit: 1,
+    },
+    Limit {
+        option: 'v',
+        name: "virtual memory (kbytes)",
+        resource: nix::libc::RLIMIT_AS,
+        unit: 1024,
+    },
+];
+
+// like in sh, the soft limit is shown by default, and both limits are set
+// unless -H or -S is given. the file size limit is the default resource.
+fn ulimit(args: &[String], output: &mut Vec<u8>) -> super::Result<()> {
+    let mut hard = false;
+    let mut soft = false;
+    let mut all = false;
+    let mut limit = None;
+    let mut value = None;
+    for arg in args {
+        if !arg.starts_with('-') || arg.len() == 1 {
+            snafu::ensure!(
+                value.is_none(),
+                super::TooManyParams {
+                    cmd: "ulimit",
+                    args,
+                    expected: 1_usize,
+                }
+            );
+            value = Some(arg.as_str());
+            continue;
+        }
+        for c in arg.chars().skip(1) {
+            match c {
+                'H' => hard = true,
+                'S' => soft = true,
+                'a' => all = true,
+                _ => {
+                    limit = Some(
+                        LIMITS
+                            .iter()
+                            .find(|limit| limit.option == c)
+                            .context(super::InvalidOption {
+                                cmd: "ulimit",
+                                option: arg,
+                            })?,
+                    );
+                }
+            }
+        }
+    }
+
+    if all {
+        for limit in LIMITS {
+            let (cur, max) = get_rlimit(limit)?;
+            writeln!(
+                output,
+                "{:<28} (-{}) {}",
+                limit.name,
+                limit.option,
+                format_rlimit(if hard { max } else { cur }, limit.unit)
+            )
+            .unwrap();
+        }
+        return Ok(());
+    }
+
+    let limit = limit.unwrap_or(&LIMITS[2]);
+    let (cur, max) = get_rlimit(limit)?;
+    let Some(value) = value else {
+        writeln!(
+            output,
+            "{}",
+            format_rlimit(if hard && !soft { max } else { cur }, limit.unit)
+        )
+        .unwrap();
+        return Ok(());
+    };
+
+    let new = if value == "unlimited" {
+        nix::libc::RLIM_INFINITY
+    } else {
+        value
+            .parse::<nix::libc::rlim_t>()
+            .ok()
+            .and_then(|value| value.checked_mul(limit.unit))
+            .context(super::InvalidNumber {
+                cmd: "ulimit",
+                arg: value,
+            })?
+    };
+    let (cur, max) = match (hard, soft) {
+        (true, false) => (cur, new),
+        (false, true) => (new, max),
+        _ => (new, new),
+    };
+    set_rlimit(limit, cur, max)
+}
+
+fn format_rlimit(
+    value: nix::libc::rlim_t,
+    unit: nix::libc::rlim_t,
+) -> String {
+    if value == nix::libc::RLIM_INFINITY {
+        "unlimited".to_string()
+    } else {
+        (value / unit).to_string()
+    }
+}
+
+fn get_rlimit(
+    limit: &Limit,
+) -> super::Result<(nix::libc::rlim_t, nix::libc::rlim_t)> {
+    let mut rlimit = nix::libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // nix doesn't wrap getrlimit, but this is safe since rlimit is a valid
+    // pointer for the duration of the call
+    if unsafe { nix::libc::getrlimit(limit.resource, &raw mut rlimit) } != 0 {
+        return Err(std::io::Error::last_os_error()).context(super::Rlimit {
+            action: "get",
+            name: limit.name,
+        });
+    }
+    Ok((rlimit.rlim_cur, rlimit.rlim_max))
+}
+
+fn set_rlimit(
+    limit: &Limit,
+    cur: nix::libc::rlim_t,
+    max: nix::libc::rlim_t,
+) -> super::Result<()> {
+    let rlimit = nix::libc::rlimit {
+        rlim_cur: cur,
+        rlim_max: max,
+    };
+    if unsafe { nix::libc::setrlimit(limit.resource, &raw const rlimit) } != 0
+    {
+        return Err(std::io::Error::last_os_error()).context(super::Rlimit {
+            action: "set",
+            name: limit.name,
+        });
+    }
+    Ok(())
+}
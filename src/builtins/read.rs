@@ -0,0 +1,214 @@
+use snafu::ResultExt as _;
+use std::io::{Read as _, Write as _};
+
+pub struct Read;
+
+impl super::Builtin for Read {
+    fn name(&self) -> &'static str {
+        "read"
+    }
+
+    fn usage(&self) -> &'static str {
+        "read [-r] [-p prompt] [name...]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "read a line of input into variables"
+    }
+
+    fn help(&self) -> &'static str {
+        "-r         don't treat backslashes as escape characters\n\
+         -p prompt  print prompt first, if reading from a terminal\n\
+         the line is split on $IFS, and the last name gets the rest of the\n\
+         line. with no names, the whole line is stored in $REPLY."
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        read(args, io)
+    }
+}
+
+// like in sh, the status is 1 if the end of the input was reached before a
+// newline, but the variables are still assigned whatever was read
+fn read(args: &[String], io: &super::Io) -> super::Result<i32> {
+    let mut raw = false;
+    let mut prompt = None;
+    let mut args = args.iter();
+    let mut names = vec![];
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            names.extend(args.by_ref());
+            break;
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            names.push(arg);
+            names.extend(args.by_ref());
+            break;
+        }
+        for (i, c) in arg.char_indices().skip(1) {
+            match c {
+                'r' => raw = true,
+                'p' => {
+                    // the prompt is either the rest of the option or the
+                    // next argument
+                    let rest = &arg[i + 1..];
+                    prompt = if rest.is_empty() {
+                        args.next().map(String::as_str)
+                    } else {
+                        Some(rest)
+                    };
+                    snafu::ensure!(
+                        prompt.is_some(),
+                        super::InvalidOption {
+                            cmd: "read",
+                            option: arg
+                        }
+                    );
+                    break;
+                }
+                _ => {
+                    return super::InvalidOption {
+                        cmd: "read",
+                        option: arg,
+                    }
+                    .fail()
+                }
+            }
+        }
+    }
+    for name in &names {
+        snafu::ensure!(
+            crate::parser::is_name(name),
+            super::InvalidName {
+                cmd: "read",
+                name: name.as_str()
+            }
+        );
+    }
+
+    let mut input = io.stdin().context(super::ReadInput)?;
+    // the prompt is only useful when reading from the terminal, and it has
+    // to be shown before blocking on input, so it can't go through io
+    if let Some(prompt) = prompt {
+        if nix::unistd::isatty(std::os::fd::AsRawFd::as_raw_fd(&input))
+            .unwrap_or(false)
+        {
+            let mut stderr = std::io::stderr();
+            stderr
+                .write_all(prompt.as_bytes())
+                .and_then(|()| stderr.flush())
+                .context(super::WriteOutput)?;
+        }
+    }
+    let (line, eof) = read_line(&mut input, raw)?;
+    let chars = unescape(&line, raw);
+
+    if names.is_empty() {
+        let value: String = chars.iter().map(|(c, _)| c).collect();
+        crate::env::set_var("REPLY", &value);
+    } else {
+        let ifs =
+            crate::env::var("IFS").unwrap_or_else(|| " \t\n".to_string());
+        let fields = split(&chars, &ifs, names.len());
+        for (i, name) in names.iter().enumerate() {
+            crate::env::set_var(
+                name,
+                fields.get(i).map_or("", String::as_str),
+            );
+        }
+    }
+    Ok(i32::from(eof))
+}
+
+// input is read a byte at a time, so that nothing past the end of the line
+// is consumed and left unavailable to the next command
+fn read_line(
+    input: &mut std::fs::File,
+    raw: bool,
+) -> super::Result<(Vec<u8>, bool)> {
+    let mut line = vec![];
+    let mut buf = [0; 1];
+    loop {
+        match input.read(&mut buf) {
+            Ok(0) => return Ok((line, true)),
+            Ok(_) => {
+                if buf[0] == b'\n' {
+                    // without -r, a backslash at the end of the line
+                    // continues it, unless it is itself escaped
+                    let trailing = line
+                        .iter()
+                        .rev()
+                        .take_while(|&&c| c == b'\\')
+                        .count();
+                    if raw || trailing % 2 == 0 {
+                        return Ok((line, false));
+                    }
+                }
+                line.push(buf[0]);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e).context(super::ReadInput),
+        }
+    }
+}
+
+// returns each character along with whether it was escaped, since escaped
+// characters are never treated as field separators
+fn unescape(line: &[u8], raw: bool) -> Vec<(char, bool)> {
+    let line = String::from_utf8_lossy(line);
+    if raw {
+        return line.chars().map(|c| (c, false)).collect();
+    }
+    let mut chars = vec![];
+    let mut iter = line.chars();
+    while let Some(c) = iter.next() {
+        if c == '\\' {
+            match iter.next() {
+                // line continuation
+                Some('\n') => {}
+                Some(c) => chars.push((c, true)),
+                None => {}
+            }
+        } else {
+            chars.push((c, false));
+        }
+    }
+    chars
+}
+
+// like in sh, whitespace in $IFS is trimmed and runs of it separate fields,
+// while each other character in $IFS separates exactly one field. the last
+// field gets the rest of the line.
+fn split(chars: &[(char, bool)], ifs: &str, count: usize) -> Vec<String> {
+    let is_ifs = |&(c, escaped): &(char, bool)| !escaped && ifs.contains(c);
+    let is_space = |ch: &(char, bool)| is_ifs(ch) && ch.0.is_whitespace();
+
+    let start = chars.iter().take_while(|ch| is_space(ch)).count();
+    let end = chars.len()
+        - chars[start..]
+            .iter()
+            .rev()
+            .take_while(|ch| is_space(ch))
+            .count();
+    let chars = &chars[start..end];
+
+    let mut fields = vec![];
+    let mut pos = 0;
+    while pos < chars.len() {
+        if fields.len() == count - 1 {
+            fields.push(chars[pos..].iter().map(|(c, _)| c).collect());
+            break;
+        }
+        let len = chars[pos..].iter().take_while(|ch| !is_ifs(ch)).count();
+        fields.push(chars[pos..pos + len].iter().map(|(c, _)| c).collect());
+        pos += len;
+        // a separator is any amount of whitespace, optionally around a
+        // single non-whitespace separator character
+        pos += chars[pos..].iter().take_while(|ch| is_space(ch)).count();
+        if chars.get(pos).is_some_and(|ch| is_ifs(ch) && !is_space(ch)) {
+            pos += 1;
+            pos += chars[pos..].iter().take_while(|ch| is_space(ch)).count();
+        }
+    }
+    fields
+}
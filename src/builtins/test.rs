@@ -0,0 +1,271 @@
+use std::convert::TryFrom as _;
+use std::os::unix::fs::{FileTypeExt as _, MetadataExt as _};
+
+pub struct Test;
+
+impl super::Builtin for Test {
+    fn name(&self) -> &'static str {
+        "test"
+    }
+
+    fn usage(&self) -> &'static str {
+        "test [expr]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "evaluate a conditional expression"
+    }
+
+    fn help(&self) -> &'static str {
+        "-e file, -f file, -d file, ...  test the type or permissions of file\n\
+         -n str, -z str                  test whether str is nonempty or empty\n\
+         a = b, a != b, a < b, a > b     compare strings\n\
+         a -eq b, a -lt b, ...           compare integers\n\
+         a -nt b, a -ot b, a -ef b       compare files\n\
+         ! expr, expr -a expr, expr -o expr, ( expr )"
+    }
+
+    fn run(
+        &self,
+        args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        test(self.name(), args)
+    }
+}
+
+pub struct Bracket;
+
+impl super::Builtin for Bracket {
+    fn name(&self) -> &'static str {
+        "["
+    }
+
+    fn usage(&self) -> &'static str {
+        "[ [expr] ]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "evaluate a conditional expression"
+    }
+
+    fn help(&self) -> &'static str {
+        "the same as `test`, except that the last argument must be `]`."
+    }
+
+    fn run(
+        &self,
+        args: &[String],
+        _io: &mut super::Io,
+    ) -> super::Result<i32> {
+        let Some((last, args)) = args.split_last() else {
+            return error(self.name(), "missing `]`");
+        };
+        if last != "]" {
+            return error(self.name(), "missing `]`");
+        }
+        test(self.name(), args)
+    }
+}
+
+const UNARY: &[&str] = &[
+    "-b", "-c", "-d", "-e", "-f", "-g", "-G", "-h", "-k", "-L", "-n", "-O",
+    "-p", "-r", "-s", "-S", "-t", "-u", "-v", "-w", "-x", "-z",
+];
+
+const BINARY: &[&str] = &[
+    "=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge",
+    "-nt", "-ot", "-ef",
+];
+
+fn error<T>(cmd: &str, message: &str) -> super::Result<T> {
+    super::Test { cmd, message }.fail()
+}
+
+// like in sh, the status is 0 if the expression is true, 1 if it is false,
+// and 2 if it is invalid
+fn test(cmd: &str, args: &[String]) -> super::Result<i32> {
+    if args.is_empty() {
+        return Ok(1);
+    }
+    let mut parser = Parser { cmd, args, pos: 0 };
+    let value = parser.or()?;
+    if let Some(arg) = args.get(parser.pos) {
+        return error(cmd, &format!("unexpected argument `{arg}`"));
+    }
+    Ok(i32::from(!value))
+}
+
+// expressions are evaluated as they are parsed. operators take precedence
+// over treating their arguments as plain strings, which resolves the
+// ambiguity in things like `test -n` or `[ "(" = "(" ]` the same way sh
+// does.
+struct Parser<'a> {
+    cmd: &'a str,
+    args: &'a [String],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self, offset: usize) -> Option<&'a str> {
+        self.args.get(self.pos + offset).map(String::as_str)
+    }
+
+    fn remaining(&self) -> usize {
+        self.args.len() - self.pos
+    }
+
+    fn or(&mut self) -> super::Result<bool> {
+        let mut value = self.and()?;
+        while self.peek(0) == Some("-o") && self.remaining() > 1 {
+            self.pos += 1;
+            // both sides are always parsed, even if the result is known
+            value |= self.and()?;
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> super::Result<bool> {
+        let mut value = self.not()?;
+        while self.peek(0) == Some("-a") && self.remaining() > 1 {
+            self.pos += 1;
+            value &= self.not()?;
+        }
+        Ok(value)
+    }
+
+    fn not(&mut self) -> super::Result<bool> {
+        // a binary operator after `!` means that it is being compared as a
+        // string
+        if self.peek(0) == Some("!")
+            && self.remaining() > 1
+            && !(self.remaining() == 3
+                && self.peek(1).is_some_and(|op| BINARY.contains(&op)))
+        {
+            self.pos += 1;
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> super::Result<bool> {
+        let Some(arg) = self.peek(0) else {
+            return error(self.cmd, "argument expected");
+        };
+        if let (Some(op), Some(rhs)) = (self.peek(1), self.peek(2)) {
+            if BINARY.contains(&op) {
+                self.pos += 3;
+                return binary(self.cmd, arg, op, rhs);
+            }
+        }
+        if arg == "(" && self.remaining() > 1 {
+            self.pos += 1;
+            let value = self.or()?;
+            if self.peek(0) != Some(")") {
+                return error(self.cmd, "missing `)`");
+            }
+            self.pos += 1;
+            return Ok(value);
+        }
+        if UNARY.contains(&arg) {
+            if let Some(operand) = self.peek(1) {
+                self.pos += 2;
+                return unary(self.cmd, arg, operand);
+            }
+        }
+        self.pos += 1;
+        Ok(!arg.is_empty())
+    }
+}
+
+fn unary(cmd: &str, op: &str, operand: &str) -> super::Result<bool> {
+    match op {
+        "-n" => return Ok(!operand.is_empty()),
+        "-z" => return Ok(operand.is_empty()),
+        "-v" => return Ok(crate::env::var(operand).is_some()),
+        "-t" => {
+            let fd = integer(cmd, operand)?;
+            return Ok(i32::try_from(fd)
+                .is_ok_and(|fd| nix::unistd::isatty(fd).unwrap_or(false)));
+        }
+        _ => {}
+    }
+
+    let path = std::path::Path::new(operand);
+    // -h and -L are the only tests which don't follow symlinks
+    if op == "-h" || op == "-L" {
+        return Ok(std::fs::symlink_metadata(path)
+            .is_ok_and(|metadata| metadata.file_type().is_symlink()));
+    }
+    let access = |mode| nix::unistd::access(path, mode).is_ok();
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return Ok(false);
+    };
+    let file_type = metadata.file_type();
+    Ok(match op {
+        "-b" => file_type.is_block_device(),
+        "-c" => file_type.is_char_device(),
+        "-d" => file_type.is_dir(),
+        "-e" => true,
+        "-f" => file_type.is_file(),
+        "-g" => metadata.mode() & 0o2000 != 0,
+        "-G" => metadata.gid() == nix::unistd::getegid().as_raw(),
+        "-k" => metadata.mode() & 0o1000 != 0,
+        "-O" => metadata.uid() == nix::unistd::geteuid().as_raw(),
+        "-p" => file_type.is_fifo(),
+        "-r" => access(nix::unistd::AccessFlags::R_OK),
+        "-s" => metadata.len() > 0,
+        "-S" => file_type.is_socket(),
+        "-u" => metadata.mode() & 0o4000 != 0,
+        "-w" => access(nix::unistd::AccessFlags::W_OK),
+        "-x" => access(nix::unistd::AccessFlags::X_OK),
+        _ => unreachable!(),
+    })
+}
+
+fn binary(cmd: &str, lhs: &str, op: &str, rhs: &str) -> super::Result<bool> {
+    Ok(match op {
+        "=" | "==" => lhs == rhs,
+        "!=" => lhs != rhs,
+        "<" => lhs < rhs,
+        ">" => lhs > rhs,
+        "-eq" => integer(cmd, lhs)? == integer(cmd, rhs)?,
+        "-ne" => integer(cmd, lhs)? != integer(cmd, rhs)?,
+        "-lt" => integer(cmd, lhs)? < integer(cmd, rhs)?,
+        "-le" => integer(cmd, lhs)? <= integer(cmd, rhs)?,
+        "-gt" => integer(cmd, lhs)? > integer(cmd, rhs)?,
+        "-ge" => integer(cmd, lhs)? >= integer(cmd, rhs)?,
+        "-nt" | "-ot" => {
+            let modified = |path| {
+                std::fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+            };
+            match (modified(lhs), modified(rhs)) {
+                (Ok(lhs), Ok(rhs)) => {
+                    if op == "-nt" {
+                        lhs > rhs
+                    } else {
+                        lhs < rhs
+                    }
+                }
+                // a file which exists is newer than one which doesn't
+                (Ok(_), Err(_)) => op == "-nt",
+                (Err(_), Ok(_)) => op == "-ot",
+                (Err(_), Err(_)) => false,
+            }
+        }
+        "-ef" => match (std::fs::metadata(lhs), std::fs::metadata(rhs)) {
+            (Ok(lhs), Ok(rhs)) => {
+                lhs.dev() == rhs.dev() && lhs.ino() == rhs.ino()
+            }
+            _ => false,
+        },
+        _ => unreachable!(),
+    })
+}
+
+fn integer(cmd: &str, arg: &str) -> super::Result<i64> {
+    arg.trim().parse().or_else(|_| {
+        error(cmd, &format!("integer expression expected, got `{arg}`"))
+    })
+}
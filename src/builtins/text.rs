@@ -0,0 +1,538 @@
+use std::convert::TryFrom as _;
+
+pub struct Echo;
+
+impl super::Builtin for Echo {
+    fn name(&self) -> &'static str {
+        "echo"
+    }
+
+    fn usage(&self) -> &'static str {
+        "echo [-neE] [arg...]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "print arguments"
+    }
+
+    fn help(&self) -> &'static str {
+        "-n  don't print a trailing newline\n\
+         -e  interpret backslash escapes\n\
+         -E  don't interpret backslash escapes (the default)"
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        echo(args, &mut io.stdout);
+        Ok(0)
+    }
+}
+
+pub struct Printf;
+
+impl super::Builtin for Printf {
+    fn name(&self) -> &'static str {
+        "printf"
+    }
+
+    fn usage(&self) -> &'static str {
+        "printf format [arg...]"
+    }
+
+    fn summary(&self) -> &'static str {
+        "print formatted output"
+    }
+
+    fn help(&self) -> &'static str {
+        "format supports the conversions %s %b %c %d %i %o %u %x %X %f %e %g\n\
+         and %%, along with flags, widths and precisions. it is reused until\n\
+         all args have been printed."
+    }
+
+    fn arity(&self) -> (usize, Option<usize>) {
+        (1, None)
+    }
+
+    fn run(&self, args: &[String], io: &mut super::Io) -> super::Result<i32> {
+        printf(args, io)
+    }
+}
+
+// like in bash, escape sequences are only interpreted with -e, and
+// arguments are only treated as options if they consist entirely of valid
+// option letters
+fn echo(args: &[String], output: &mut Vec<u8>) {
+    let mut newline = true;
+    let mut escapes = false;
+    let mut args = args;
+    while let Some(arg) = args.first() {
+        let Some(letters) = arg.strip_prefix('-') else {
+            break;
+        };
+        if letters.is_empty() || !letters.chars().all(|c| "neE".contains(c)) {
+            break;
+        }
+        for c in letters.chars() {
+            match c {
+                'n' => newline = false,
+                'e' => escapes = true,
+                'E' => escapes = false,
+                _ => unreachable!(),
+            }
+        }
+        args = &args[1..];
+    }
+
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            output.push(b' ');
+        }
+        if escapes {
+            let (unescaped, stop) = unescape(arg, true);
+            output.extend(unescaped);
+            if stop {
+                return;
+            }
+        } else {
+            output.extend(arg.as_bytes());
+        }
+    }
+    if newline {
+        output.push(b'\n');
+    }
+}
+
+// interprets backslash escapes, returning whether `\c` (which stops all
+// further output) was seen. octal escapes are written as `\0nnn` by echo
+// and %b, but as `\nnn` in printf formats.
+fn unescape(s: &str, zero_octal: bool) -> (Vec<u8>, bool) {
+    let mut out = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            out.extend(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let Some(c) = chars.next() else {
+            out.push(b'\\');
+            break;
+        };
+        match c {
+            'a' => out.push(0x07),
+            'b' => out.push(0x08),
+            'c' => return (out, true),
+            'e' | 'E' => out.push(0x1b),
+            'f' => out.push(0x0c),
+            'n' => out.push(b'\n'),
+            'r' => out.push(b'\r'),
+            't' => out.push(b'\t'),
+            'v' => out.push(0x0b),
+            '\\' => out.push(b'\\'),
+            '0'..='7' => {
+                let mut digits = String::new();
+                if !(zero_octal && c == '0') {
+                    digits.push(c);
+                }
+                while digits.len() < 3 {
+                    match chars.peek() {
+                        Some(&d @ '0'..='7') => {
+                            digits.push(d);
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                let value = u32::from_str_radix(&digits, 8).unwrap_or(0);
+                // values over 0o377 wrap, like in sh
+                out.push((value & 0xff) as u8);
+            }
+            'x' | 'u' | 'U' => {
+                let max = match c {
+                    'x' => 2,
+                    'u' => 4,
+                    _ => 8,
+                };
+                let mut digits = String::new();
+                while digits.len() < max {
+                    match chars.peek() {
+                        Some(&d) if d.is_ascii_hexdigit() => {
+                            digits.push(d);
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                if digits.is_empty() {
+                    out.push(b'\\');
+                    out.push(c as u8);
+                    continue;
+                }
+                let value = u32::from_str_radix(&digits, 16).unwrap_or(0);
+                if c == 'x' {
+                    out.push((value & 0xff) as u8);
+                } else {
+                    let c = std::char::from_u32(value).unwrap_or('\u{fffd}');
+                    let mut buf = [0; 4];
+                    out.extend(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+            c => {
+                out.push(b'\\');
+                let mut buf = [0; 4];
+                out.extend(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+    (out, false)
+}
+
+// a single conversion specification, like `%-10.3s`. the flags are
+// independent of each other, so they don't fit in an enum.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alt: bool,
+    zero: bool,
+    width: Option<usize>,
+    precision: Option<usize>,
+    conversion: char,
+}
+
+// the format is reused until all of the arguments have been consumed. like
+// in sh, missing arguments are treated as empty strings or zero, and
+// invalid numbers are reported but don't stop the output.
+fn printf(args: &[String], io: &mut super::Io) -> super::Result<i32> {
+    let format = &args[0];
+    let mut args = args[1..].iter().map(String::as_str).peekable();
+    let mut status = 0;
+    loop {
+        let before = args.len();
+        let stop = format_once(format, &mut args, io, &mut status)?;
+        if stop || args.peek().is_none() || args.len() == before {
+            break;
+        }
+    }
+    Ok(status)
+}
+
+// returns true if `\c` was seen
+fn format_once<'a>(
+    format: &str,
+    args: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+    io: &mut super::Io,
+    status: &mut i32,
+) -> super::Result<bool> {
+    let mut chars = format.char_indices().peekable();
+    let mut literal = String::new();
+    while let Some((start, c)) = chars.next() {
+        if c != '%' {
+            literal.push(c);
+            continue;
+        }
+        if chars.peek().map(|&(_, c)| c) == Some('%') {
+            chars.next();
+            literal.push('%');
+            continue;
+        }
+        let (unescaped, stop) = unescape(&literal, false);
+        io.stdout.extend(unescaped);
+        literal.clear();
+        if stop {
+            return Ok(true);
+        }
+
+        let mut spec = Spec::default();
+        while let Some(&(_, c)) = chars.peek() {
+            match c {
+                '-' => spec.left = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '#' => spec.alt = true,
+                '0' => spec.zero = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        spec.width = format_number(&mut chars, args, io, status);
+        if chars.peek().map(|&(_, c)| c) == Some('.') {
+            chars.next();
+            spec.precision = Some(
+                format_number(&mut chars, args, io, status).unwrap_or(0),
+            );
+        }
+        let Some((end, conversion)) = chars.next() else {
+            return super::InvalidFormat {
+                format: &format[start..],
+            }
+            .fail();
+        };
+        spec.conversion = conversion;
+        let arg = args.next().unwrap_or("");
+        let formatted = match conversion {
+            's' => {
+                let s = spec.precision.map_or_else(
+                    || arg.to_string(),
+                    |precision| arg.chars().take(precision).collect(),
+                );
+                pad(&spec, "", &s)
+            }
+            'b' => {
+                let (unescaped, stop) = unescape(arg, true);
+                let s = String::from_utf8_lossy(&unescaped).into_owned();
+                io.stdout.extend(pad(&spec, "", &s).into_bytes());
+                if stop {
+                    return Ok(true);
+                }
+                continue;
+            }
+            'c' => pad(&spec, "", &arg.chars().take(1).collect::<String>()),
+            'd' | 'i' => {
+                let n = parse_integer(arg, io, status);
+                let sign = if n < 0 {
+                    "-"
+                } else if spec.plus {
+                    "+"
+                } else if spec.space {
+                    " "
+                } else {
+                    ""
+                };
+                let digits = n.unsigned_abs().to_string();
+                pad(&spec, sign, &min_digits(&spec, digits))
+            }
+            'o' | 'u' | 'x' | 'X' => {
+                // negative numbers wrap, like in c
+                #[allow(clippy::cast_sign_loss)]
+                let n = parse_integer(arg, io, status) as u64;
+                let (digits, prefix) = match conversion {
+                    'o' => (format!("{n:o}"), "0"),
+                    'u' => (n.to_string(), ""),
+                    'x' => (format!("{n:x}"), "0x"),
+                    _ => (format!("{n:X}"), "0X"),
+                };
+                let mut digits = min_digits(&spec, digits);
+                let prefix = if !spec.alt || n == 0 {
+                    ""
+                } else if conversion == 'o' {
+                    if !digits.starts_with('0') {
+                        digits.insert(0, '0');
+                    }
+                    ""
+                } else {
+                    prefix
+                };
+                pad(&spec, prefix, &digits)
+            }
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' => {
+                let n = parse_float(arg, io, status);
+                let sign = if n.is_sign_negative() && n != 0.0 {
+                    "-"
+                } else if spec.plus {
+                    "+"
+                } else if spec.space {
+                    " "
+                } else {
+                    ""
+                };
+                let mut digits = format_float(&spec, n.abs());
+                if conversion.is_ascii_uppercase() {
+                    digits = digits.to_uppercase();
+                }
+                pad(&spec, sign, &digits)
+            }
+            _ => {
+                return super::InvalidFormat {
+                    format: &format[start..end + conversion.len_utf8()],
+                }
+                .fail();
+            }
+        };
+        io.stdout.extend(formatted.into_bytes());
+    }
+    let (unescaped, stop) = unescape(&literal, false);
+    io.stdout.extend(unescaped);
+    Ok(stop)
+}
+
+// a width or precision, which can be given as `*` to take it from the
+// arguments
+fn format_number<'a>(
+    chars: &mut std::iter::Peekable<std::str::CharIndices>,
+    args: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+    io: &mut super::Io,
+    status: &mut i32,
+) -> Option<usize> {
+    if chars.peek().map(|&(_, c)| c) == Some('*') {
+        chars.next();
+        let n = parse_integer(args.next().unwrap_or(""), io, status);
+        return usize::try_from(n).ok();
+    }
+    let mut digits = String::new();
+    while let Some(&(_, c)) = chars.peek() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        digits.push(c);
+        chars.next();
+    }
+    digits.parse().ok()
+}
+
+// numbers can be given in decimal, octal (`010`), hex (`0x10`), or as the
+// character code of a quoted character (`'a`)
+fn parse_integer(arg: &str, io: &mut super::Io, status: &mut i32) -> i64 {
+    let trimmed = arg.trim_start();
+    if trimmed.is_empty() {
+        return 0;
+    }
+    if let Some(c) = trimmed
+        .strip_prefix('\'')
+        .or_else(|| trimmed.strip_prefix('"'))
+    {
+        return c.chars().next().map_or(0, |c| i64::from(u32::from(c)));
+    }
+    let (negative, digits) = match trimmed.as_bytes()[0] {
+        b'-' => (true, &trimmed[1..]),
+        b'+' => (false, &trimmed[1..]),
+        _ => (false, trimmed),
+    };
+    // like in parse_number in arith.rs, the prefixes are checked in order
+    #[allow(clippy::option_if_let_else)]
+    let parsed = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    };
+    parsed.map_or_else(
+        |_| {
+            invalid_number(arg, io, status);
+            0
+        },
+        |n| if negative { -n } else { n },
+    )
+}
+
+fn parse_float(arg: &str, io: &mut super::Io, status: &mut i32) -> f64 {
+    let trimmed = arg.trim();
+    if trimmed.is_empty() {
+        return 0.0;
+    }
+    if let Some(c) = trimmed
+        .strip_prefix('\'')
+        .or_else(|| trimmed.strip_prefix('"'))
+    {
+        return c.chars().next().map_or(0.0, |c| f64::from(u32::from(c)));
+    }
+    trimmed.parse().unwrap_or_else(|_| {
+        #[allow(clippy::cast_precision_loss)]
+        let n = parse_integer(arg, io, status) as f64;
+        n
+    })
+}
+
+fn invalid_number(arg: &str, io: &mut super::Io, status: &mut i32) {
+    io.stderr
+        .extend(format!("printf: {arg}: invalid number\n").into_bytes());
+    *status = 1;
+}
+
+// the precision of an integer conversion is the minimum number of digits
+fn min_digits(spec: &Spec, digits: String) -> String {
+    match spec.precision {
+        Some(precision) if digits.len() < precision => {
+            format!("{}{}", "0".repeat(precision - digits.len()), digits)
+        }
+        // a precision of zero means that zero prints as nothing
+        Some(0) if digits == "0" => String::new(),
+        _ => digits,
+    }
+}
+
+fn format_float(spec: &Spec, n: f64) -> String {
+    if !n.is_finite() {
+        return if n.is_nan() { "nan" } else { "inf" }.to_string();
+    }
+    let precision = spec.precision.unwrap_or(6);
+    match spec.conversion.to_ascii_lowercase() {
+        'f' => format!("{n:.precision$}"),
+        'e' => exponential(n, precision),
+        _ => {
+            // %g uses whichever of %e and %f is shorter for the given
+            // number of significant digits
+            let precision = precision.max(1);
+            let e = exponential(n, precision - 1);
+            let exponent: i64 =
+                e[e.find('e').unwrap() + 1..].parse().unwrap_or(0);
+            let mut s = if exponent < -4
+                || exponent >= i64::try_from(precision).unwrap_or(i64::MAX)
+            {
+                e
+            } else {
+                let decimals = usize::try_from(
+                    i64::try_from(precision).unwrap_or(0) - 1 - exponent,
+                )
+                .unwrap_or(0);
+                format!("{n:.decimals$}")
+            };
+            if !spec.alt {
+                s = strip_trailing_zeros(&s);
+            }
+            s
+        }
+    }
+}
+
+// formats like c's %e, which always has a sign and at least two digits in
+// the exponent
+fn exponential(n: f64, precision: usize) -> String {
+    let s = format!("{n:.precision$e}");
+    let (mantissa, exponent) = s.split_at(s.find('e').unwrap());
+    let exponent: i64 = exponent[1..].parse().unwrap_or(0);
+    format!(
+        "{}e{}{:02}",
+        mantissa,
+        if exponent < 0 { '-' } else { '+' },
+        exponent.abs()
+    )
+}
+
+fn strip_trailing_zeros(s: &str) -> String {
+    let (mantissa, exponent) = s.split_at(s.find('e').unwrap_or(s.len()));
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        mantissa
+    };
+    format!("{mantissa}{exponent}")
+}
+
+// applies the field width. zero padding goes between the sign or prefix and
+// the digits, and only applies to numbers.
+fn pad(spec: &Spec, prefix: &str, body: &str) -> String {
+    let len = prefix.chars().count() + body.chars().count();
+    let width = spec.width.unwrap_or(0);
+    if len >= width {
+        return format!("{prefix}{body}");
+    }
+    let fill = width - len;
+    let numeric = !matches!(spec.conversion, 's' | 'b' | 'c');
+    // like in c, zero padding is ignored for integers with a precision
+    let zero = spec.zero
+        && numeric
+        && !(spec.precision.is_some() && "diouxX".contains(spec.conversion));
+    if spec.left {
+        format!("{}{}{}", prefix, body, " ".repeat(fill))
+    } else if zero {
+        format!("{}{}{}", prefix, "0".repeat(fill), body)
+    } else {
+        format!("{}{}{}", " ".repeat(fill), prefix, body)
+    }
+}
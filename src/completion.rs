@@ -0,0 +1,148 @@
+// tab completion of the word before the cursor. the first word of a
+// command completes to command names, arguments to `z` complete to
+// directories from the frecency database, and anything else completes to
+// file names. this only looks at the text of the line rather than parsing
+// it, so quoting in the word being completed isn't understood.
+
+// characters which end a word, as well as whitespace
+const WORD_BREAKS: &str = ";&|()<>";
+
+pub struct Completion {
+    // byte offset of the start of the word being completed
+    pub start: usize,
+    // what the word could be replaced with, already quoted
+    pub candidates: Vec<String>,
+    // whether the candidates start with the word, so that their common
+    // prefix can be inserted. frecency matches can match anywhere in the
+    // path, so they don't.
+    pub extends: bool,
+}
+
+impl Completion {
+    // the text to replace the word with, if there is anything better than
+    // what is already there. a single candidate is followed by a space
+    // unless it is a directory, so that completion can continue into it.
+    pub fn replacement(&self, word: &str) -> Option<String> {
+        match self.candidates.as_slice() {
+            [] => None,
+            [candidate] if candidate.ends_with('/') => {
+                Some(candidate.clone())
+            }
+            [candidate] => Some(format!("{candidate} ")),
+            [first, rest @ ..] if self.extends => {
+                let prefix = rest.iter().fold(first.as_str(), |prefix, c| {
+                    let len = prefix
+                        .char_indices()
+                        .zip(c.chars())
+                        .find(|((_, a), b)| a != b)
+                        .map_or_else(
+                            || prefix.len().min(c.len()),
+                            |((idx, _), _)| idx,
+                        );
+                    &prefix[..len]
+                });
+                if prefix.len() > word.len() {
+                    Some(prefix.to_string())
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn complete(line: &str, cursor: usize) -> Completion {
+    let before = &line[..cursor];
+    let start = before
+        .rfind(|c: char| c.is_whitespace() || WORD_BREAKS.contains(c))
+        .map_or(0, |idx| {
+            idx + before[idx..].chars().next().map_or(1, char::len_utf8)
+        });
+    let word = &before[start..];
+
+    // the words of the current command before the one being completed
+    let command_start = before[..start]
+        .rfind(|c: char| ";&|(\n".contains(c))
+        .map_or(0, |idx| idx + 1);
+    let words: Vec<_> =
+        before[command_start..start].split_whitespace().collect();
+
+    let (candidates, extends) = if words.is_empty() && !word.contains('/') {
+        (commands(word), true)
+    } else if words.first() == Some(&"z") && !word.starts_with('-') {
+        (directories(word), false)
+    } else {
+        (files(word), true)
+    };
+    Completion {
+        start,
+        candidates,
+        extends,
+    }
+}
+
+fn commands(prefix: &str) -> Vec<String> {
+    let mut names: Vec<_> = crate::builtins::all()
+        .iter()
+        .map(|builtin| builtin.name().to_string())
+        .chain(crate::env::function_names())
+        .chain(crate::aliases::all().into_iter().map(|(name, _)| name))
+        .chain(crate::executables::names())
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+// directories from the frecency database which match the fragment, best
+// first
+fn directories(fragment: &str) -> Vec<String> {
+    crate::frecency::matches(&[fragment.to_string()])
+        .into_iter()
+        .map(|entry| crate::parser::quote(&entry.path.to_string_lossy()))
+        .collect()
+}
+
+// entries in the directory part of the word whose names start with the
+// rest of it. hidden files are only included if the word asks for them,
+// like with globs.
+fn files(word: &str) -> Vec<String> {
+    let (dir, prefix) = word
+        .rfind('/')
+        .map_or(("", word), |idx| (&word[..=idx], &word[idx + 1..]));
+    let path = if dir.is_empty() {
+        std::path::PathBuf::from(".")
+    } else if let Some(rest) = dir.strip_prefix('~') {
+        let home = std::env::var_os("HOME").unwrap_or_default();
+        std::path::Path::new(&home).join(rest.trim_start_matches('/'))
+    } else {
+        std::path::PathBuf::from(dir)
+    };
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return vec![];
+    };
+    let mut candidates: Vec<_> = entries
+        .filter_map(std::result::Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(prefix)
+                || (name.starts_with('.') && !prefix.starts_with('.'))
+            {
+                return None;
+            }
+            // follows symlinks, so that links to directories can be
+            // completed into
+            let is_dir = entry.path().is_dir();
+            Some(format!(
+                "{}{}{}",
+                dir,
+                crate::parser::quote(&name),
+                if is_dir { "/" } else { "" }
+            ))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
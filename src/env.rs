@@ -0,0 +1,234 @@
+// global shell state: variables, functions, positional parameters, the
+// directory stack, and the status of the last command
+
+use std::os::unix::process::ExitStatusExt as _;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Return,
+    Break(usize),
+    Continue(usize),
+    // unlike the others, this isn't cleared once evaluation finishes, so
+    // that whatever is running the shell can see it
+    Exit,
+}
+
+struct Frame {
+    args: Vec<String>,
+    // previous values of variables declared with `local` in this frame
+    saved: std::collections::HashMap<String, Option<String>>,
+    loops: usize,
+}
+
+#[derive(Default)]
+struct Env {
+    vars: std::collections::HashMap<String, String>,
+    // variables marked with `export` which may not have a value yet
+    exported: std::collections::HashSet<String>,
+    functions: std::collections::HashMap<
+        String,
+        std::sync::Arc<crate::ast::Command>,
+    >,
+    frames: Vec<Frame>,
+    // number of loops currently running outside of any function
+    loops: usize,
+    // directories saved by `pushd`, most recent first. the current
+    // directory is always implicitly at the top of the stack.
+    dirs: Vec<std::path::PathBuf>,
+    status: i32,
+    control: Option<Control>,
+}
+
+static ENV: std::sync::LazyLock<std::sync::Mutex<Env>> =
+    std::sync::LazyLock::new(|| std::sync::Mutex::new(Env::default()));
+
+fn env() -> std::sync::MutexGuard<'static, Env> {
+    ENV.lock().unwrap()
+}
+
+// shell variables shadow the process environment, but assigning to a
+// variable that was already exported, either by the shell's parent or with
+// `export`, also updates the exported value, so that child processes see
+// it
+pub fn var(name: &str) -> Option<String> {
+    if let Some(value) = env().vars.get(name) {
+        return Some(value.clone());
+    }
+    std::env::var(name).ok()
+}
+
+pub fn set_var(name: &str, value: &str) {
+    let mut env = env();
+    if env.exported.contains(name) || std::env::var_os(name).is_some() {
+        std::env::set_var(name, value);
+    }
+    env.vars.insert(name.to_string(), value.to_string());
+}
+
+// marks the variable as exported, assigning the value first if given. like
+// in sh, a variable without a value is only exported once it is assigned.
+pub fn export(name: &str, value: Option<&str>) {
+    env().exported.insert(name.to_string());
+    if let Some(value) = value.map(ToString::to_string).or_else(|| var(name))
+    {
+        set_var(name, &value);
+    }
+}
+
+// the variables which child processes see, sorted by name
+pub fn exported_vars() -> Vec<(String, String)> {
+    let mut vars: Vec<_> = std::env::vars().collect();
+    vars.sort();
+    vars
+}
+
+fn restore_var(name: &str, value: Option<String>) {
+    if let Some(value) = value {
+        set_var(name, &value);
+    } else {
+        env().vars.remove(name);
+        std::env::remove_var(name);
+    }
+}
+
+// returns false if not currently running a function
+pub fn set_local(name: &str, value: Option<&str>) -> bool {
+    let prev = var(name);
+    if let Some(frame) = env().frames.last_mut() {
+        frame.saved.entry(name.to_string()).or_insert(prev);
+    } else {
+        return false;
+    }
+    if let Some(value) = value {
+        set_var(name, value);
+    }
+    true
+}
+
+pub fn function(name: &str) -> Option<std::sync::Arc<crate::ast::Command>> {
+    env().functions.get(name).cloned()
+}
+
+pub fn function_names() -> Vec<String> {
+    env().functions.keys().cloned().collect()
+}
+
+pub fn set_function(name: &str, body: std::sync::Arc<crate::ast::Command>) {
+    env().functions.insert(name.to_string(), body);
+}
+
+pub fn push_frame(args: &[String]) {
+    env().frames.push(Frame {
+        args: args.to_vec(),
+        saved: std::collections::HashMap::new(),
+        loops: 0,
+    });
+}
+
+pub fn pop_frame() {
+    let frame = env().frames.pop();
+    if let Some(frame) = frame {
+        for (name, value) in frame.saved {
+            restore_var(&name, value);
+        }
+    }
+}
+
+pub fn depth() -> usize {
+    env().frames.len()
+}
+
+impl Env {
+    // loops in the caller of a function can't be broken out of from within
+    // the function
+    fn loops_mut(&mut self) -> &mut usize {
+        if let Some(frame) = self.frames.last_mut() {
+            &mut frame.loops
+        } else {
+            &mut self.loops
+        }
+    }
+}
+
+pub fn enter_loop() {
+    *env().loops_mut() += 1;
+}
+
+pub fn leave_loop() {
+    *env().loops_mut() -= 1;
+}
+
+pub fn loops() -> usize {
+    *env().loops_mut()
+}
+
+// positional parameters of the currently running function (the shell
+// itself doesn't take any arguments)
+pub fn args() -> Vec<String> {
+    env()
+        .frames
+        .last()
+        .map(|frame| frame.args.clone())
+        .unwrap_or_default()
+}
+
+// the full directory stack, starting with the current directory, as
+// displayed by `dirs` (and available for displaying in the prompt)
+pub fn dir_stack() -> Vec<std::path::PathBuf> {
+    let mut dirs: Vec<_> = std::env::current_dir().into_iter().collect();
+    dirs.extend(env().dirs.iter().cloned());
+    dirs
+}
+
+// abbreviates $HOME as ~
+pub fn tilde_dir(dir: &std::path::Path) -> String {
+    if let Some(home) = std::env::var_os("HOME") {
+        if let Ok(rest) = dir.strip_prefix(&home) {
+            if rest.as_os_str().is_empty() {
+                return "~".to_string();
+            }
+            return format!("~/{}", rest.display());
+        }
+    }
+    dir.display().to_string()
+}
+
+// the saved directories, not including the current directory
+pub fn saved_dirs() -> Vec<std::path::PathBuf> {
+    env().dirs.clone()
+}
+
+pub fn set_saved_dirs(dirs: Vec<std::path::PathBuf>) {
+    env().dirs = dirs;
+}
+
+pub fn status() -> i32 {
+    env().status
+}
+
+pub fn set_status(status: i32) {
+    env().status = status;
+}
+
+pub fn control() -> Option<Control> {
+    env().control
+}
+
+pub fn set_control(control: Control) {
+    env().control = Some(control);
+}
+
+pub fn take_control() -> Option<Control> {
+    env().control.take()
+}
+
+pub fn exit_requested() -> bool {
+    env().control == Some(Control::Exit)
+}
+
+// converts an exit status into the number that `$?` should expand to
+pub fn status_code(status: std::process::ExitStatus) -> i32 {
+    status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0))
+}
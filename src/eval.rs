@@ -1,18 +1,35 @@
+use futures::future::Future as _;
+use futures::stream::Stream as _;
 use snafu::futures01::StreamExt as _;
-use snafu::ResultExt as _;
+use snafu::{OptionExt as _, ResultExt as _};
+use std::convert::TryFrom as _;
+use std::fmt::Write as _;
+use std::io::{Read as _, Write as _};
+use std::os::fd::AsRawFd as _;
+
+// keeps runaway recursion from overflowing the stack
+const MAX_FUNCTION_DEPTH: usize = 100;
 
 #[derive(Debug, snafu::Snafu)]
 pub enum Error {
-    #[snafu(display("failed to parse command line '{}': {}", line, source))]
+    #[snafu(display(
+        "failed to parse command line '{}': {} ({})",
+        line,
+        source,
+        source.location()
+    ))]
     Parser {
         line: String,
         source: crate::parser::Error,
     },
 
-    #[snafu(display("failed to find command `{}`: {}", cmd, source))]
+    #[snafu(display("failed to expand alias: {}", source))]
+    Alias { source: crate::aliases::Error },
+
+    #[snafu(display("command not found: {}", cmd))]
     Command {
         cmd: String,
-        source: tokio_pty_process_stream::Error,
+        suggestions: Vec<String>,
     },
 
     #[snafu(display("failed to run builtin command `{}`: {}", cmd, source))]
@@ -24,79 +41,1719 @@ pub enum Error {
     #[snafu(display("failed to run executable `{}`: {}", cmd, source))]
     ProcessExecution {
         cmd: String,
-        source: tokio_pty_process_stream::Error,
+        source: crate::process::Error,
+    },
+
+    #[snafu(display("failed to run command substitution: {}", source))]
+    #[allow(clippy::use_self)]
+    CommandSubstitution { source: Box<Error> },
+
+    #[snafu(display(
+        "process substitution `{}(...)` can only be used in command \
+         arguments",
+        match kind {
+            crate::ast::ProcessSubstKind::Read => "<",
+            crate::ast::ProcessSubstKind::Write => ">",
+        }
+    ))]
+    ProcessSubstitutionContext { kind: crate::ast::ProcessSubstKind },
+
+    #[snafu(display(
+        "failed to evaluate arithmetic expression `{}`: {}",
+        expr,
+        source
+    ))]
+    Arithmetic {
+        expr: String,
+        source: crate::arith::Error,
+    },
+
+    #[snafu(display("failed to open {}: {}", path, source))]
+    OpenRedirect {
+        path: String,
+        source: std::io::Error,
     },
+
+    #[snafu(display("{}: ambiguous redirect", word))]
+    AmbiguousRedirect { word: String },
+
+    #[snafu(display("{}: bad file descriptor", fd))]
+    BadFd { fd: u32 },
+
+    #[snafu(display("failed to create pipe: {}", source))]
+    CreatePipe { source: std::io::Error },
+
+    #[snafu(display("failed to read command output: {}", source))]
+    ReadOutput { source: std::io::Error },
+
+    #[snafu(display(
+        "failed to call function `{}`: maximum nesting depth ({}) exceeded",
+        name,
+        depth
+    ))]
+    FunctionNesting { name: String, depth: usize },
 }
 
-#[allow(dead_code)]
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    // the status of a command which failed to run
+    fn status(&self) -> i32 {
+        match self {
+            Self::Command { .. } => 127,
+            Self::ProcessExecution {
+                source: crate::process::Error::SpawnProcess { .. },
+                ..
+            } => 126,
+            _ => 1,
+        }
+    }
+
+    // extra help to display under the error message
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Self::Command { suggestions, .. } if !suggestions.is_empty() => {
+                let suggestions: Vec<_> =
+                    suggestions.iter().map(|s| format!("`{s}`")).collect();
+                Some(format!("did you mean {}?", suggestions.join(" or ")))
+            }
+            Self::CommandSubstitution { source } => source.hint(),
+            _ => None,
+        }
+    }
+}
+
+// the first command which wasn't found while running the current command
+// line, and the similar names which were suggested for it
+static NOT_FOUND: std::sync::Mutex<Option<(String, Vec<String>)>> =
+    std::sync::Mutex::new(None);
+
+// if $NBSH_CORRECT is set, a command line which ran a mistyped command name
+// can be rerun with the closest match instead
+pub fn correction(line: &str) -> Option<Correction> {
+    let (cmd, suggestions) = NOT_FOUND.lock().unwrap().take()?;
+    if crate::env::var("NBSH_CORRECT")
+        .unwrap_or_default()
+        .is_empty()
+    {
+        return None;
+    }
+    let to = suggestions.first()?;
+    Some(Correction {
+        from: cmd.clone(),
+        to: to.clone(),
+        line: replace_command(line, &cmd, to)?,
+    })
+}
+
+pub struct Correction {
+    pub from: String,
+    pub to: String,
+    pub line: String,
+}
+
+impl Correction {
+    pub fn prompt(&self) -> String {
+        format!("nbsh: correct `{}` to `{}`? [y/N] ", self.from, self.to)
+    }
+}
+
+// replaces every occurrence of `from` in `line` that is in command
+// position, so that a typo repeated in several commands is only corrected
+// once
+fn replace_command(line: &str, from: &str, to: &str) -> Option<String> {
+    let mut corrected = String::new();
+    let mut prev = 0;
+    for (start, _) in line.match_indices(from) {
+        let end = start + from.len();
+        let is_command = line[..start]
+            .trim_end_matches([' ', '\t'])
+            .chars()
+            .next_back()
+            .is_none_or(|c| ";&|(`{\n".contains(c))
+            && line[end..]
+                .chars()
+                .next()
+                .is_none_or(|c| c.is_whitespace() || ";&|)`}".contains(c));
+        if is_command && start >= prev {
+            corrected.push_str(&line[prev..start]);
+            corrected.push_str(to);
+            prev = end;
+        }
+    }
+    if prev == 0 {
+        return None;
+    }
+    corrected.push_str(&line[prev..]);
+    Some(corrected)
+}
+
+type EventStream = Box<
+    dyn futures::stream::Stream<
+            Item = tokio_pty_process_stream::Event,
+            Error = Error,
+        > + Send,
+>;
+
+type Event = tokio_pty_process_stream::Event;
+
+type Thunk = Box<dyn FnOnce() -> Result<EventStream> + Send>;
+
 pub fn eval(line: &str) -> Eval {
     Eval::new(line)
 }
 
+// $PS1, expanded like the body of a here-document, so that it can use
+// things like `$DIRSTACK` or command substitutions. defaults to `$ `.
+pub fn prompt() -> String {
+    let Some(ps1) = crate::env::var("PS1") else {
+        return "$ ".to_string();
+    };
+    // showing the prompt shouldn't affect `$?`
+    let status = crate::env::status();
+    let prompt = crate::parser::parse_expansions(&ps1)
+        .ok()
+        .and_then(|word| expand_assignment(&word).ok())
+        .unwrap_or_else(|| "$ ".to_string());
+    crate::env::set_status(status);
+    prompt
+}
+
+#[must_use = "streams do nothing unless polled"]
 pub struct Eval {
     line: String,
-    stream: Option<
-        Box<
-            dyn futures::stream::Stream<
-                    Item = tokio_pty_process_stream::Event,
-                    Error = Error,
-                > + Send,
-        >,
-    >,
+    program: Option<crate::ast::Program>,
+    io: Io,
+    stream: Option<EventStream>,
     manage_screen: bool,
 }
 
 impl Eval {
     pub fn new(line: &str) -> Self {
+        NOT_FOUND.lock().unwrap().take();
         Self {
             line: line.to_string(),
+            program: None,
+            io: Io::default(),
+            stream: None,
+            manage_screen: true,
+        }
+    }
+
+    // for running an already parsed program at the top level
+    pub fn program(program: crate::ast::Program) -> Self {
+        NOT_FOUND.lock().unwrap().take();
+        Self {
+            line: String::new(),
+            program: Some(program),
+            io: Io::default(),
             stream: None,
             manage_screen: true,
         }
     }
 
+    // for running already parsed commands, like command substitutions
+    fn nested(program: &crate::ast::Program, io: Io) -> Self {
+        Self {
+            line: String::new(),
+            program: Some(program.clone()),
+            io,
+            stream: None,
+            manage_screen: false,
+        }
+    }
+
     pub fn set_raw(mut self, raw: bool) -> Self {
         self.manage_screen = raw;
         self
     }
 }
 
-#[must_use = "streams do nothing unless polled"]
 impl futures::stream::Stream for Eval {
     type Item = tokio_pty_process_stream::Event;
     type Error = Error;
 
     fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
         if self.stream.is_none() {
-            let line = self.line.as_ref();
-            let (cmd, args) =
-                crate::parser::parse(line).context(Parser { line })?;
-            let builtin_stream = crate::builtins::Builtin::new(&cmd, &args);
-            let stream: Box<
-                dyn futures::stream::Stream<
-                        Item = tokio_pty_process_stream::Event,
-                        Error = Error,
-                    > + Send,
-            > = if let Ok(s) = builtin_stream {
-                Box::new(s.context(BuiltinExecution { cmd }))
+            let program = if let Some(program) = self.program.take() {
+                program
+            } else {
+                let line = self.line.as_ref();
+                crate::parser::parse(line).context(Parser { line })?
+            };
+            self.stream = Some(program_stream(&program, &self.io));
+        }
+
+        let res = self.stream.as_mut().unwrap().poll();
+        match &res {
+            Ok(futures::Async::Ready(None))
+                // a `return` outside of a function has nothing to return
+                // from
+                if !crate::env::exit_requested() => {
+                    crate::env::take_control();
+                }
+            Err(e) => {
+                if !crate::env::exit_requested() {
+                    crate::env::take_control();
+                }
+                crate::env::set_status(e.status());
+            }
+            _ => {}
+        }
+        res
+    }
+}
+
+// where the input and output of commands should go. by default, commands
+// run on a pty and their output is reported via output events.
+#[derive(Clone, Default)]
+struct Io {
+    stdin: Option<std::sync::Arc<std::os::fd::OwnedFd>>,
+    stdout: Option<std::sync::Arc<std::os::fd::OwnedFd>>,
+    stderr: Option<std::sync::Arc<std::os::fd::OwnedFd>>,
+    // redirections of any other descriptors, which only external commands
+    // can use
+    fds: Vec<(u32, std::sync::Arc<std::os::fd::OwnedFd>)>,
+    // set for simple commands in a pipeline with more than one command.
+    // builtins run on their own thread then, but anything nested (like the
+    // body of a function) still runs on the shell's.
+    thread: bool,
+}
+
+impl Io {
+    fn get(&self, fd: u32) -> Option<std::sync::Arc<std::os::fd::OwnedFd>> {
+        match fd {
+            0 => self.stdin.clone(),
+            1 => self.stdout.clone(),
+            2 => self.stderr.clone(),
+            _ => self
+                .fds
+                .iter()
+                .find(|(n, _)| *n == fd)
+                .map(|(_, fd)| fd.clone()),
+        }
+    }
+
+    fn set(
+        &mut self,
+        fd: u32,
+        to: Option<std::sync::Arc<std::os::fd::OwnedFd>>,
+    ) {
+        match fd {
+            0 => self.stdin = to,
+            1 => self.stdout = to,
+            2 => self.stderr = to,
+            _ => {
+                self.fds.retain(|(n, _)| *n != fd);
+                if let Some(to) = to {
+                    self.fds.push((fd, to));
+                }
+            }
+        }
+    }
+
+    fn is_redirected(&self) -> bool {
+        self.stdin.is_some()
+            || self.stdout.is_some()
+            || self.stderr.is_some()
+            || !self.fds.is_empty()
+    }
+}
+
+// runs a list of streams one after another, only creating each one once
+// the previous one has finished. this is what lets things like `$?` and
+// `&&` see the results of earlier commands. stops early if a builtin like
+// `return` requested it.
+struct Sequence {
+    current: Option<EventStream>,
+    thunks: std::collections::VecDeque<Thunk>,
+}
+
+impl Sequence {
+    fn new(thunks: Vec<Thunk>) -> Self {
+        Self {
+            current: None,
+            thunks: thunks.into(),
+        }
+    }
+}
+
+impl futures::stream::Stream for Sequence {
+    type Item = tokio_pty_process_stream::Event;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(stream) = &mut self.current {
+                if let Some(event) = futures::try_ready!(stream.poll()) {
+                    return Ok(futures::Async::Ready(Some(event)));
+                }
+                self.current = None;
+            }
+            if crate::env::control().is_some() {
+                return Ok(futures::Async::Ready(None));
+            }
+            if let Some(thunk) = self.thunks.pop_front() {
+                self.current = Some(thunk()?);
+            } else {
+                return Ok(futures::Async::Ready(None));
+            }
+        }
+    }
+}
+
+fn lazy<F>(f: F) -> EventStream
+where
+    F: FnOnce() -> Result<EventStream> + Send + 'static,
+{
+    Box::new(Sequence::new(vec![Box::new(f)]))
+}
+
+fn empty() -> EventStream {
+    Box::new(futures::stream::empty())
+}
+
+fn program_stream(program: &crate::ast::Program, io: &Io) -> EventStream {
+    Box::new(Sequence::new(
+        program
+            .lists
+            .iter()
+            .cloned()
+            .map(|list| -> Thunk {
+                let io = io.clone();
+                Box::new(move || Ok(and_or(&list, &io)))
+            })
+            .collect(),
+    ))
+}
+
+fn and_or(list: &crate::ast::AndOrList, io: &Io) -> EventStream {
+    let first = list.first.clone();
+    let first_io = io.clone();
+    let mut thunks: Vec<Thunk> =
+        vec![Box::new(move || Ok(pipeline(&first, &first_io)))];
+    for (connector, next) in list.rest.iter().cloned() {
+        let io = io.clone();
+        thunks.push(Box::new(move || {
+            let succeeded = crate::env::status() == 0;
+            if succeeded == (connector == crate::ast::Connector::And) {
+                Ok(pipeline(&next, &io))
+            } else {
+                Ok(empty())
+            }
+        }));
+    }
+    Box::new(Sequence::new(thunks))
+}
+
+fn pipeline(pipeline: &crate::ast::Pipeline, io: &Io) -> EventStream {
+    let commands = pipeline.commands.clone();
+    let io = io.clone();
+    let stream = lazy(move || {
+        if let [cmd] = commands.as_slice() {
+            return Ok(command(cmd, &io));
+        }
+        Ok(Box::new(Pipeline::new(&commands, &io)?))
+    });
+    if !pipeline.negated {
+        return stream;
+    }
+    let negate: Thunk = Box::new(|| {
+        let status = crate::env::status();
+        crate::env::set_status(i32::from(status == 0));
+        Ok(empty())
+    });
+    Box::new(Sequence::new(vec![Box::new(move || Ok(stream)), negate]))
+}
+
+// runs the commands of a pipeline at the same time, with the output of
+// each one going to the input of the next. the status is the status of the
+// last command, once all of them have finished.
+#[must_use = "streams do nothing unless polled"]
+struct Pipeline {
+    // each one is dropped as soon as it finishes, which closes its ends of
+    // the pipes
+    streams: Vec<Option<EventStream>>,
+    status: Option<i32>,
+}
+
+impl Pipeline {
+    fn new(commands: &[crate::ast::Command], io: &Io) -> Result<Self> {
+        let mut streams = vec![];
+        let mut stdin = io.stdin.clone();
+        for (i, cmd) in commands.iter().enumerate() {
+            let mut io = Io {
+                stdin: stdin.take(),
+                thread: matches!(cmd, crate::ast::Command::Simple(_)),
+                ..io.clone()
+            };
+            if i + 1 < commands.len() {
+                let (read, write) = std::io::pipe().context(CreatePipe)?;
+                io.stdout = Some(std::sync::Arc::new(write.into()));
+                stdin = Some(std::sync::Arc::new(read.into()));
+            }
+            streams.push(Some(command(cmd, &io)));
+        }
+        Ok(Self {
+            streams,
+            status: None,
+        })
+    }
+}
+
+impl futures::stream::Stream for Pipeline {
+    type Item = Event;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        let last = self.streams.len() - 1;
+        for (i, slot) in self.streams.iter_mut().enumerate() {
+            while let Some(stream) = slot {
+                // a command which can't be run doesn't stop the rest of
+                // the pipeline, since each one reports its own errors
+                match stream.poll()? {
+                    futures::Async::Ready(Some(event)) => {
+                        return Ok(futures::Async::Ready(Some(event)));
+                    }
+                    futures::Async::Ready(None) => {
+                        *slot = None;
+                        if i == last {
+                            self.status = Some(crate::env::status());
+                        }
+                    }
+                    futures::Async::NotReady => break,
+                }
+            }
+        }
+
+        if self.streams.iter().all(Option::is_none) {
+            if let Some(status) = self.status.take() {
+                crate::env::set_status(status);
+            }
+            return Ok(futures::Async::Ready(None));
+        }
+        Ok(futures::Async::NotReady)
+    }
+}
+
+fn command(cmd: &crate::ast::Command, io: &Io) -> EventStream {
+    Box::new(Recover {
+        stream: command_stream(cmd, io),
+        io: io.clone(),
+    })
+}
+
+fn command_stream(cmd: &crate::ast::Command, io: &Io) -> EventStream {
+    match cmd {
+        crate::ast::Command::Simple(cmd) => {
+            let cmd = cmd.clone();
+            let io = io.clone();
+            lazy(move || simple_command(&cmd, &io))
+        }
+        crate::ast::Command::BraceGroup(program) => {
+            program_stream(program, io)
+        }
+        crate::ast::Command::If(if_) => if_stream(
+            if_.conditionals.clone(),
+            if_.else_body.clone(),
+            io.clone(),
+        ),
+        crate::ast::Command::Loop(loop_) => {
+            Box::new(WhileLoop::new(loop_, io))
+        }
+        crate::ast::Command::For(for_) => Box::new(ForLoop::new(for_, io)),
+        crate::ast::Command::Case(case) => {
+            let case = case.clone();
+            let io = io.clone();
+            lazy(move || case_stream(&case, &io))
+        }
+        crate::ast::Command::Arithmetic(expr) => {
+            let expr = expr.clone();
+            let io = io.clone();
+            lazy(move || {
+                // `((expr))` is equivalent to `let "expr"`
+                let expr = expand_assignment(&expr)?;
+                let builtin = crate::builtins::run("let", &[expr])
+                    .context(BuiltinExecution { cmd: "let" })?;
+                let builtin = builtin_io(builtin, &io);
+                Ok(track_status(Box::new(
+                    builtin.context(BuiltinExecution { cmd: "let" }),
+                )))
+            })
+        }
+        crate::ast::Command::FunctionDef(def) => {
+            let def = def.clone();
+            lazy(move || {
+                crate::env::set_function(&def.name, def.body);
+                crate::env::set_status(0);
+                Ok(empty())
+            })
+        }
+    }
+}
+
+// like in other shells, a command which can't be run (because a
+// redirection failed, for instance) just fails, rather than stopping the
+// list, condition or function body it is part of
+#[must_use = "streams do nothing unless polled"]
+struct Recover {
+    stream: EventStream,
+    io: Io,
+}
+
+impl futures::stream::Stream for Recover {
+    type Item = Event;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        match self.stream.poll() {
+            Err(e) => {
+                self.stream = report(&e, &self.io);
+                self.stream.poll()
+            }
+            res => res,
+        }
+    }
+}
+
+fn if_stream(
+    mut conditionals: Vec<crate::ast::Conditional>,
+    else_body: Option<crate::ast::Program>,
+    io: Io,
+) -> EventStream {
+    if conditionals.is_empty() {
+        return else_body.map_or_else(
+            || {
+                lazy(|| {
+                    crate::env::set_status(0);
+                    Ok(empty())
+                })
+            },
+            |body| program_stream(&body, &io),
+        );
+    }
+    let conditional = conditionals.remove(0);
+    let condition = program_stream(&conditional.condition, &io);
+    Box::new(Sequence::new(vec![
+        Box::new(move || Ok(condition)),
+        Box::new(move || {
+            if crate::env::status() == 0 {
+                Ok(program_stream(&conditional.body, &io))
+            } else {
+                Ok(if_stream(conditionals, else_body, io))
+            }
+        }),
+    ]))
+}
+
+fn case_stream(case: &crate::ast::Case, io: &Io) -> Result<EventStream> {
+    let word = expand_assignment(&case.word)?;
+    for item in &case.items {
+        for pattern in &item.patterns {
+            if crate::pattern::matches(&expand_pattern(pattern)?, &word) {
+                return Ok(program_stream(&item.body, io));
+            }
+        }
+    }
+    crate::env::set_status(0);
+    Ok(empty())
+}
+
+// what a loop should do after one of its child streams finishes, based on
+// whether `break`, `continue`, or `return` was run
+enum LoopAction {
+    Next,
+    Continue,
+    Exit,
+}
+
+fn loop_action() -> LoopAction {
+    match crate::env::control() {
+        None => LoopAction::Next,
+        Some(crate::env::Control::Break(n)) => {
+            crate::env::take_control();
+            if n > 1 {
+                crate::env::set_control(crate::env::Control::Break(n - 1));
+            }
+            LoopAction::Exit
+        }
+        Some(crate::env::Control::Continue(n)) => {
+            crate::env::take_control();
+            if n > 1 {
+                crate::env::set_control(crate::env::Control::Continue(n - 1));
+                LoopAction::Exit
+            } else {
+                LoopAction::Continue
+            }
+        }
+        Some(crate::env::Control::Return | crate::env::Control::Exit) => {
+            LoopAction::Exit
+        }
+    }
+}
+
+// keeps track of whether `break` and `continue` are valid
+struct LoopGuard;
+
+impl LoopGuard {
+    fn new() -> Self {
+        crate::env::enter_loop();
+        Self
+    }
+}
+
+impl Drop for LoopGuard {
+    fn drop(&mut self) {
+        crate::env::leave_loop();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoopPhase {
+    Condition,
+    Body,
+}
+
+#[must_use = "streams do nothing unless polled"]
+struct WhileLoop {
+    // must be dropped before the guard
+    stream: Option<EventStream>,
+    guard: Option<LoopGuard>,
+    loop_: crate::ast::Loop,
+    io: Io,
+    phase: LoopPhase,
+    status: i32,
+    done: bool,
+}
+
+impl WhileLoop {
+    fn new(loop_: &crate::ast::Loop, io: &Io) -> Self {
+        Self {
+            stream: None,
+            guard: None,
+            loop_: loop_.clone(),
+            io: io.clone(),
+            phase: LoopPhase::Condition,
+            status: 0,
+            done: false,
+        }
+    }
+
+    fn finish(&mut self) {
+        self.stream = None;
+        self.guard = None;
+        self.done = true;
+    }
+}
+
+impl futures::stream::Stream for WhileLoop {
+    type Item = Event;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        if self.done {
+            return Ok(futures::Async::Ready(None));
+        }
+        if self.guard.is_none() {
+            self.guard = Some(LoopGuard::new());
+        }
+
+        loop {
+            if self.stream.is_none() {
+                self.stream = Some(match self.phase {
+                    LoopPhase::Condition => {
+                        program_stream(&self.loop_.condition, &self.io)
+                    }
+                    LoopPhase::Body => {
+                        program_stream(&self.loop_.body, &self.io)
+                    }
+                });
+            }
+            if let Some(event) =
+                futures::try_ready!(self.stream.as_mut().unwrap().poll())
+            {
+                return Ok(futures::Async::Ready(Some(event)));
+            }
+            self.stream = None;
+
+            match loop_action() {
+                LoopAction::Next => {}
+                LoopAction::Continue => {
+                    self.status = crate::env::status();
+                    self.phase = LoopPhase::Condition;
+                    continue;
+                }
+                LoopAction::Exit => {
+                    self.finish();
+                    return Ok(futures::Async::Ready(None));
+                }
+            }
+
+            match self.phase {
+                LoopPhase::Condition => {
+                    let succeeded = crate::env::status() == 0;
+                    if succeeded
+                        == (self.loop_.kind == crate::ast::LoopKind::While)
+                    {
+                        self.phase = LoopPhase::Body;
+                    } else {
+                        // the status of a loop is the status of the last
+                        // command in the body, not the condition
+                        crate::env::set_status(self.status);
+                        self.finish();
+                        return Ok(futures::Async::Ready(None));
+                    }
+                }
+                LoopPhase::Body => {
+                    self.status = crate::env::status();
+                    self.phase = LoopPhase::Condition;
+                }
+            }
+        }
+    }
+}
+
+#[must_use = "streams do nothing unless polled"]
+struct ForLoop {
+    // must be dropped before the guard
+    stream: Option<EventStream>,
+    guard: Option<LoopGuard>,
+    for_: crate::ast::For,
+    io: Io,
+    items: Option<std::collections::VecDeque<String>>,
+    done: bool,
+}
+
+impl ForLoop {
+    fn new(for_: &crate::ast::For, io: &Io) -> Self {
+        Self {
+            stream: None,
+            guard: None,
+            for_: for_.clone(),
+            io: io.clone(),
+            items: None,
+            done: false,
+        }
+    }
+
+    fn finish(&mut self) {
+        self.stream = None;
+        self.guard = None;
+        self.done = true;
+    }
+}
+
+impl futures::stream::Stream for ForLoop {
+    type Item = Event;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        if self.done {
+            return Ok(futures::Async::Ready(None));
+        }
+        if self.items.is_none() {
+            self.items = Some(match &self.for_.words {
+                Some(words) => expand_words(words)?.into(),
+                None => crate::env::args().into(),
+            });
+            self.guard = Some(LoopGuard::new());
+            crate::env::set_status(0);
+        }
+
+        loop {
+            if let Some(stream) = &mut self.stream {
+                if let Some(event) = futures::try_ready!(stream.poll()) {
+                    return Ok(futures::Async::Ready(Some(event)));
+                }
+                self.stream = None;
+                if matches!(loop_action(), LoopAction::Exit) {
+                    self.finish();
+                    return Ok(futures::Async::Ready(None));
+                }
+            }
+
+            if let Some(item) = self.items.as_mut().unwrap().pop_front() {
+                crate::env::set_var(&self.for_.name, &item);
+                self.stream = Some(program_stream(&self.for_.body, &self.io));
+            } else {
+                self.finish();
+                return Ok(futures::Async::Ready(None));
+            }
+        }
+    }
+}
+
+fn simple_command(
+    cmd: &crate::ast::SimpleCommand,
+    io: &Io,
+) -> Result<EventStream> {
+    let cmd = crate::aliases::expand(cmd).context(Alias)?;
+    let assignments = cmd
+        .assignments
+        .iter()
+        .map(|assignment| {
+            Ok((
+                assignment.name.clone(),
+                expand_assignment(&assignment.value)?,
+            ))
+        })
+        .collect::<Result<Vec<(String, String)>>>()?;
+    let mut helpers = vec![];
+    let mut args = vec![];
+    for word in &cmd.words {
+        let mut fields = Fields {
+            split: true,
+            helpers: Some(&mut helpers),
+            ..Fields::default()
+        };
+        for part in &word.parts {
+            fields.push_part(part, false)?;
+        }
+        fields.finish();
+        args.extend(fields.done);
+    }
+    let mut io = io.clone();
+    // like in sh, a redirection which fails is reported to wherever stderr
+    // was redirected by the ones before it
+    if let Err(e) = redirect(&cmd.redirects, &mut io) {
+        return Ok(report(&e, &io));
+    }
+    let thread = std::mem::take(&mut io.thread);
+    let io = &io;
+
+    if args.is_empty() {
+        for (name, value) in &assignments {
+            crate::env::set_var(name, value);
+        }
+        // the status is left alone if it was set by a command
+        // substitution
+        if !cmd.assignments.iter().any(|a| has_command_subst(&a.value)) {
+            crate::env::set_status(0);
+        }
+        return Ok(empty());
+    }
+    let mut cmd = args.remove(0);
+    // `command name` runs name while skipping any function with that name
+    let mut bypass_functions = false;
+    while cmd == "command"
+        && args.first().is_some_and(|arg| !arg.starts_with('-'))
+    {
+        cmd = args.remove(0);
+        bypass_functions = true;
+    }
+    let fds: Vec<_> = helpers
+        .iter()
+        .filter_map(|helper| helper.fd.clone())
+        .collect();
+
+    let resolution = if bypass_functions {
+        crate::resolver::resolve_without_functions(&cmd)
+    } else {
+        crate::resolver::resolve_command(&cmd)
+    };
+    let mut script = match resolution {
+        Some(crate::resolver::Resolution::Builtin) => {
+            crate::builtins::script(&cmd, &args).ok().flatten()
+        }
+        _ => None,
+    };
+    let mut stream: EventStream = match resolution {
+        Some(crate::resolver::Resolution::Function(body)) => {
+            Box::new(FunctionCall::new(&cmd, &args, assignments, body, io))
+        }
+        // scripts run by `source` behave like function calls, so that
+        // `return` and the positional parameters work. if the script can't
+        // be loaded, running the builtin reports the error.
+        Some(crate::resolver::Resolution::Builtin) if script.is_some() => {
+            let script = script.take().unwrap();
+            let body = std::sync::Arc::new(crate::ast::Command::BraceGroup(
+                script.program,
+            ));
+            Box::new(FunctionCall::new(
+                &cmd,
+                &script.args,
+                assignments,
+                body,
+                io,
+            ))
+        }
+        Some(crate::resolver::Resolution::Builtin) => {
+            let builtin = crate::builtins::run(&cmd, &args)
+                .context(BuiltinExecution { cmd: &cmd })?;
+            for (name, value) in &assignments {
+                crate::env::set_var(name, value);
+            }
+            let builtin = builtin_io(builtin, io).thread(thread);
+            Box::new(builtin.context(BuiltinExecution { cmd }))
+        }
+        Some(crate::resolver::Resolution::Executable { path, .. }) => {
+            if io.is_redirected() {
+                let mut process =
+                    crate::process::PipedProcess::new(&cmd, &args)
+                        .path(&path)
+                        .env(&assignments)
+                        .inherit_fds(&fds);
+                if let Some(fd) = &io.stdin {
+                    process = process.stdin(fd.clone());
+                }
+                if let Some(fd) = &io.stdout {
+                    process = process.stdout(fd.clone());
+                }
+                if let Some(fd) = &io.stderr {
+                    process = process.stderr(fd.clone());
+                }
+                for (n, fd) in &io.fds {
+                    let n = std::os::fd::RawFd::try_from(*n)
+                        .ok()
+                        .context(BadFd { fd: *n })?;
+                    process = process.redirect(n, fd.clone());
+                }
+                Box::new(process.context(ProcessExecution { cmd }))
             } else {
                 let input = crate::async_stdin::Stdin::new();
-                let process = tokio_pty_process_stream::ResizingProcess::new(
-                    tokio_pty_process_stream::Process::new(
-                        &cmd, &args, input,
-                    ),
-                );
+                let process =
+                    crate::process::Process::new(&cmd, &args, input)
+                        .path(&path)
+                        .env(&assignments)
+                        .inherit_fds(&fds);
                 Box::new(process.context(ProcessExecution { cmd }))
-            };
-            self.stream = Some(stream);
+            }
+        }
+        // aliases were already expanded. like in other shells, this is
+        // just a failed command rather than something which stops the rest
+        // of the command line.
+        Some(crate::resolver::Resolution::Alias(_)) | None => {
+            let suggestions = crate::resolver::suggestions(&cmd);
+            NOT_FOUND
+                .lock()
+                .unwrap()
+                .get_or_insert_with(|| (cmd.clone(), suggestions.clone()));
+            return Ok(report(&Error::Command { cmd, suggestions }, io));
+        }
+    };
+    if !helpers.is_empty() {
+        stream = Box::new(WithProcessSubsts::new(stream, helpers));
+    }
+    Ok(track_status(stream))
+}
+
+// applies the redirections of a simple command in order
+fn redirect(redirects: &[crate::ast::Redirect], io: &mut Io) -> Result<()> {
+    for redirect in redirects {
+        match redirect {
+            crate::ast::Redirect::Input(redirect) => {
+                let file = open_redirect(
+                    &redirect.target,
+                    std::fs::OpenOptions::new().read(true),
+                )?;
+                io.set(redirect.fd, Some(file));
+            }
+            crate::ast::Redirect::Output(redirect) => {
+                let file = open_redirect(
+                    &redirect.target,
+                    std::fs::OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(true),
+                )?;
+                io.set(redirect.fd, Some(file));
+            }
+            crate::ast::Redirect::Append(redirect) => {
+                let file = open_redirect(
+                    &redirect.target,
+                    std::fs::OpenOptions::new().append(true).create(true),
+                )?;
+                io.set(redirect.fd, Some(file));
+            }
+            crate::ast::Redirect::Duplicate(dup) => {
+                let source = io.get(dup.source);
+                // stdin, stdout and stderr default to the terminal, but
+                // anything else has to have been redirected already
+                snafu::ensure!(
+                    source.is_some() || dup.source <= 2,
+                    BadFd { fd: dup.source }
+                );
+                io.set(dup.fd, source);
+            }
+            crate::ast::Redirect::Heredoc(heredoc) => {
+                let body = expand_assignment(&heredoc.body)?;
+                io.stdin = Some(heredoc_input(body)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+// builtins use the same redirections as external commands, but run in the
+// shell process
+fn builtin_io(
+    mut builtin: crate::builtins::Execution,
+    io: &Io,
+) -> crate::builtins::Execution {
+    if let Some(fd) = &io.stdin {
+        builtin = builtin.stdin(fd.clone());
+    }
+    if let Some(fd) = &io.stdout {
+        builtin = builtin.stdout(fd.clone());
+    }
+    if let Some(fd) = &io.stderr {
+        builtin = builtin.stderr(fd.clone());
+    }
+    builtin
+}
+
+fn open_redirect(
+    target: &crate::ast::Word,
+    options: &std::fs::OpenOptions,
+) -> Result<std::sync::Arc<std::os::fd::OwnedFd>> {
+    let mut fields = expand(target)?;
+    let path = match fields.len() {
+        1 => fields.remove(0),
+        _ => {
+            return AmbiguousRedirect {
+                word: target.to_string(),
+            }
+            .fail()
+        }
+    };
+    let file = options.open(&path).context(OpenRedirect { path })?;
+    Ok(std::sync::Arc::new(file.into()))
+}
+
+fn track_status(stream: EventStream) -> EventStream {
+    Box::new(stream.inspect(|event| {
+        if let tokio_pty_process_stream::Event::CommandExit { status } = event
+        {
+            crate::env::set_status(crate::env::status_code(*status));
+        }
+    }))
+}
+
+// reports an error from a command which couldn't be run and sets the
+// status accordingly. the message goes wherever stderr was redirected, and
+// is otherwise reported as output.
+fn report(e: &Error, io: &Io) -> EventStream {
+    crate::env::set_status(e.status());
+    let mut message = format!("{e}\n");
+    if let Some(hint) = e.hint() {
+        writeln!(message, "{hint}").unwrap();
+    }
+    if let Some(fd) = &io.stderr {
+        // nothing useful to do if this fails
+        let _ = fd
+            .try_clone()
+            .map(|fd| std::fs::File::from(fd).write_all(message.as_bytes()));
+        return empty();
+    }
+    Box::new(futures::stream::once(Ok(Event::Output {
+        data: message.replace('\n', "\r\n").into_bytes(),
+    })))
+}
+
+// the command on the other end of a process substitution
+struct ProcessSubst {
+    // the end of the pipe which is passed to the main command
+    fd: Option<std::sync::Arc<std::os::fd::OwnedFd>>,
+    stream: Option<Eval>,
+}
+
+fn process_substitution(
+    kind: crate::ast::ProcessSubstKind,
+    program: &crate::ast::Program,
+) -> Result<ProcessSubst> {
+    let (read, write) = std::io::pipe().context(CreatePipe)?;
+    let (fd, io) = match kind {
+        crate::ast::ProcessSubstKind::Read => (
+            read.into(),
+            Io {
+                stdout: Some(std::sync::Arc::new(write.into())),
+                ..Io::default()
+            },
+        ),
+        crate::ast::ProcessSubstKind::Write => (
+            write.into(),
+            Io {
+                stdin: Some(std::sync::Arc::new(read.into())),
+                ..Io::default()
+            },
+        ),
+    };
+    Ok(ProcessSubst {
+        fd: Some(std::sync::Arc::new(fd)),
+        stream: Some(Eval::nested(program, io)),
+    })
+}
+
+// runs the commands for any process substitutions alongside the main
+// command. the main command's exit is only reported once all of them have
+// finished too.
+#[must_use = "streams do nothing unless polled"]
+struct WithProcessSubsts {
+    stream: Option<EventStream>,
+    helpers: Vec<ProcessSubst>,
+    exit: Option<Event>,
+    status: Option<i32>,
+}
+
+impl WithProcessSubsts {
+    fn new(stream: EventStream, helpers: Vec<ProcessSubst>) -> Self {
+        Self {
+            stream: Some(stream),
+            helpers,
+            exit: None,
+            status: None,
+        }
+    }
+
+    fn finish_main(&mut self) {
+        self.stream = None;
+        // the helpers update the status as they run, but the status of
+        // the whole thing should be the status of the main command
+        self.status = Some(crate::env::status());
+        // closing our ends of the pipes is what lets the helpers see eof
+        // or a broken pipe
+        for helper in &mut self.helpers {
+            helper.fd = None;
+        }
+    }
+
+    fn poll_helpers(&mut self) -> Option<Event> {
+        for helper in &mut self.helpers {
+            while let Some(stream) = &mut helper.stream {
+                match stream.poll() {
+                    // the helpers aren't commands of their own as far as
+                    // the ui is concerned, but their error messages should
+                    // still be seen
+                    Ok(futures::Async::Ready(Some(Event::Output {
+                        data,
+                    }))) => {
+                        return Some(Event::Output { data });
+                    }
+                    Ok(futures::Async::Ready(Some(_))) => {}
+                    Ok(futures::Async::Ready(None)) => {
+                        helper.stream = None;
+                        leave_subshell();
+                    }
+                    Ok(futures::Async::NotReady) => break,
+                    Err(e) => {
+                        helper.stream = None;
+                        leave_subshell();
+                        return Some(Event::Output {
+                            data: format!("{e}\r\n").into_bytes(),
+                        });
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+impl futures::stream::Stream for WithProcessSubsts {
+    type Item = Event;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        if let Some(stream) = &mut self.stream {
+            match stream.poll()? {
+                futures::Async::Ready(Some(
+                    event @ Event::CommandExit { .. },
+                )) => {
+                    self.exit = Some(event);
+                    self.finish_main();
+                }
+                futures::Async::Ready(Some(event)) => {
+                    return Ok(futures::Async::Ready(Some(event)));
+                }
+                futures::Async::Ready(None) => self.finish_main(),
+                futures::Async::NotReady => {}
+            }
+        }
+
+        if let Some(event) = self.poll_helpers() {
+            return Ok(futures::Async::Ready(Some(event)));
+        }
+
+        if self.stream.is_none()
+            && self.helpers.iter().all(|helper| helper.stream.is_none())
+        {
+            if let Some(status) = self.status.take() {
+                crate::env::set_status(status);
+            }
+            return Ok(futures::Async::Ready(self.exit.take()));
+        }
+        Ok(futures::Async::NotReady)
+    }
+}
+#[must_use = "streams do nothing unless polled"]
+struct FunctionCall {
+    name: String,
+    args: Vec<String>,
+    assignments: Vec<(String, String)>,
+    body: std::sync::Arc<crate::ast::Command>,
+    io: Io,
+    stream: Option<EventStream>,
+    in_frame: bool,
+}
+
+impl FunctionCall {
+    fn new(
+        name: &str,
+        args: &[String],
+        assignments: Vec<(String, String)>,
+        body: std::sync::Arc<crate::ast::Command>,
+        io: &Io,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            args: args.to_vec(),
+            assignments,
+            body,
+            io: io.clone(),
+            stream: None,
+            in_frame: false,
+        }
+    }
+
+    fn finish(&mut self) {
+        // nested calls need to pop their frames first
+        self.stream = None;
+        if self.in_frame {
+            self.in_frame = false;
+            crate::env::pop_frame();
+            if crate::env::control() == Some(crate::env::Control::Return) {
+                crate::env::take_control();
+            }
+        }
+    }
+}
+
+impl futures::stream::Stream for FunctionCall {
+    type Item = tokio_pty_process_stream::Event;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        if self.stream.is_none() {
+            snafu::ensure!(
+                crate::env::depth() < MAX_FUNCTION_DEPTH,
+                FunctionNesting {
+                    name: self.name.clone(),
+                    depth: MAX_FUNCTION_DEPTH,
+                }
+            );
+            crate::env::push_frame(&self.args);
+            self.in_frame = true;
+            // prefix assignments only last for the duration of the call
+            for (name, value) in &self.assignments {
+                crate::env::set_local(name, Some(value));
+            }
+            self.stream = Some(command(&self.body, &self.io));
+        }
+
+        let res = self.stream.as_mut().unwrap().poll();
+        if let Ok(futures::Async::Ready(None)) | Err(_) = res {
+            self.finish();
         }
+        res
+    }
+}
+
+impl Drop for FunctionCall {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+// accumulates the fields that a word expands into
+#[derive(Default)]
+struct Fields<'a> {
+    done: Vec<String>,
+    current: Option<String>,
+    split: bool,
+    pattern: bool,
+    // process substitutions only make sense for command arguments, since
+    // the pipes need to stay open until the command is done with them
+    helpers: Option<&'a mut Vec<ProcessSubst>>,
+}
+
+impl Fields<'_> {
+    fn push_str(&mut self, s: &str) {
+        self.current.get_or_insert_with(String::new).push_str(s);
+    }
 
-        if let Some(ref mut stream) = &mut self.stream {
-            stream.poll()
+    // quoted text has to be escaped when it will be used as a pattern, so
+    // that characters like `*` only match themselves
+    fn push_quoted(&mut self, s: &str) {
+        if self.pattern {
+            self.push_str(&crate::pattern::escape(s));
         } else {
-            unreachable!()
+            self.push_str(s);
+        }
+    }
+
+    // quoted empty strings still produce a field
+    fn mark(&mut self) {
+        self.current.get_or_insert_with(String::new);
+    }
+
+    fn finish(&mut self) {
+        if let Some(field) = self.current.take() {
+            self.done.push(field);
+        }
+    }
+
+    // starts a new field for each positional parameter in `$@`
+    fn separate(&mut self) {
+        if self.split {
+            self.finish();
+        } else {
+            self.push_str(" ");
+        }
+    }
+
+    // unquoted expansions are split on whitespace
+    fn push_split(&mut self, s: &str) {
+        if !self.split {
+            self.push_str(s);
+            return;
+        }
+        if s.starts_with(char::is_whitespace) {
+            self.finish();
+        }
+        let mut pieces = s.split_whitespace().peekable();
+        while let Some(piece) = pieces.next() {
+            self.push_str(piece);
+            if pieces.peek().is_some() {
+                self.finish();
+            }
         }
+        if s.ends_with(char::is_whitespace) {
+            self.finish();
+        }
+    }
+
+    fn push_part(
+        &mut self,
+        part: &crate::ast::WordPart,
+        quoted: bool,
+    ) -> Result<()> {
+        match part {
+            crate::ast::WordPart::Literal(s) => {
+                if quoted {
+                    self.push_quoted(s);
+                } else {
+                    self.push_str(s);
+                }
+            }
+            crate::ast::WordPart::SingleQuoted(s) => {
+                self.push_quoted(s);
+            }
+            crate::ast::WordPart::DoubleQuoted(parts) => {
+                // "$@" expands to nothing at all when there are no
+                // positional parameters
+                if parts.as_slice()
+                    != [crate::ast::WordPart::Param("@".to_string())]
+                {
+                    self.mark();
+                }
+                for part in parts {
+                    self.push_part(part, true)?;
+                }
+            }
+            crate::ast::WordPart::Param(name) => match name.as_ref() {
+                "@" if quoted => {
+                    for (i, arg) in crate::env::args().iter().enumerate() {
+                        if i > 0 {
+                            self.separate();
+                        }
+                        self.push_quoted(arg);
+                    }
+                }
+                "@" | "*" if !quoted => {
+                    for (i, arg) in crate::env::args().iter().enumerate() {
+                        if i > 0 {
+                            self.separate();
+                        }
+                        self.push_split(arg);
+                    }
+                }
+                _ => {
+                    let value = param(name).unwrap_or_default();
+                    if quoted {
+                        self.push_quoted(&value);
+                    } else {
+                        self.push_split(&value);
+                    }
+                }
+            },
+            crate::ast::WordPart::CommandSubst(program) => {
+                let output = command_substitution(program)?;
+                if quoted {
+                    self.push_quoted(&output);
+                } else {
+                    self.push_split(&output);
+                }
+            }
+            crate::ast::WordPart::ProcessSubst(kind, program) => {
+                let helpers = self
+                    .helpers
+                    .as_mut()
+                    .context(ProcessSubstitutionContext { kind: *kind })?;
+                let helper = process_substitution(*kind, program)?;
+                let path = format!(
+                    "/dev/fd/{}",
+                    helper.fd.as_ref().unwrap().as_raw_fd()
+                );
+                helpers.push(helper);
+                self.push_quoted(&path);
+            }
+            crate::ast::WordPart::Arithmetic(expr) => {
+                let expr = expand_assignment(expr)?;
+                let value = crate::arith::eval(&expr)
+                    .context(Arithmetic { expr })?
+                    .to_string();
+                if quoted {
+                    self.push_quoted(&value);
+                } else {
+                    self.push_split(&value);
+                }
+            }
+            crate::ast::WordPart::Tilde => {
+                self.push_quoted(
+                    &crate::env::var("HOME")
+                        .unwrap_or_else(|| "~".to_string()),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn param(name: &str) -> Option<String> {
+    match name {
+        "?" => Some(crate::env::status().to_string()),
+        "#" => Some(crate::env::args().len().to_string()),
+        "*" => Some(crate::env::args().join(" ")),
+        "$" => Some(std::process::id().to_string()),
+        "0" => Some("nbsh".to_string()),
+        // bash makes this an array, but without arrays it is the stack as
+        // `dirs` displays it
+        "DIRSTACK" => Some(
+            crate::env::dir_stack()
+                .iter()
+                .map(|dir| crate::env::tilde_dir(dir))
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        "!" | "-" => None,
+        _ => {
+            // `${00}` is a valid name which parses to 0
+            name.parse::<usize>().map_or_else(
+                |_| crate::env::var(name),
+                |n| {
+                    n.checked_sub(1)
+                        .and_then(|i| crate::env::args().get(i).cloned())
+                },
+            )
+        }
+    }
+}
+
+// returns a pipe which produces the body of a here-document
+fn heredoc_input(
+    body: String,
+) -> Result<std::sync::Arc<std::os::fd::OwnedFd>> {
+    let (read, mut write) = std::io::pipe().context(CreatePipe)?;
+    // write on a separate thread so that large bodies don't fill up the
+    // pipe and block. errors are ignored, since they just mean that the
+    // command exited without reading all of its input.
+    std::thread::spawn(move || {
+        let _ = write.write_all(body.as_bytes());
+    });
+    Ok(std::sync::Arc::new(read.into()))
+}
+
+// runs the command with its output going to a pipe, and returns everything
+// it wrote, minus any trailing newlines
+fn command_substitution(program: &crate::ast::Program) -> Result<String> {
+    let (mut read, write) = std::io::pipe().context(CreatePipe)?;
+    let io = Io {
+        stdout: Some(std::sync::Arc::new(write.into())),
+        ..Io::default()
+    };
+    // read on a separate thread so that commands with a lot of output
+    // don't fill up the pipe and block
+    let reader = std::thread::spawn(move || {
+        let mut output = vec![];
+        read.read_to_end(&mut output).map(|_| output)
+    });
+    // expansion happens synchronously, so the shell has to block here
+    // until the commands finish. they are run on their own thread rather
+    // than from inside the future which is doing the expansion (which may
+    // be the prompt), so that they are polled by a task of their own.
+    // blocking is safe since nothing else needs the shell's thread in the
+    // meantime: the command being expanded hasn't started yet, and the
+    // substituted commands never need the reactor, since their output goes
+    // to a pipe rather than a pty.
+    let program = program.clone();
+    let runner = std::thread::spawn(move || {
+        let res = Eval::nested(&program, io)
+            .for_each(|event| {
+                // stdout is going to the pipe, so any output events are
+                // things like error messages from builtins, which are
+                // meant for the terminal
+                if let Event::Output { data } = event {
+                    let stderr = std::io::stderr();
+                    let mut stderr = stderr.lock();
+                    // nothing useful to do if this fails
+                    let _ = stderr.write_all(&data);
+                    let _ = stderr.flush();
+                }
+                Ok(())
+            })
+            .wait();
+        leave_subshell();
+        res
+    });
+    // the runner only panics if evaluating panicked, which would have
+    // taken down the shell anyway
+    let res = runner.join().unwrap();
+    let output = reader.join().unwrap().context(ReadOutput)?;
+    res.map_err(Box::new).context(CommandSubstitution)?;
+
+    let output = String::from_utf8_lossy(&output);
+    Ok(output.trim_end_matches('\n').to_string())
+}
+
+// substitutions run as though they were in a subshell, so `exit` in one
+// only exits the substitution
+fn leave_subshell() {
+    if crate::env::exit_requested() {
+        crate::env::take_control();
+    }
+}
+
+fn has_command_subst(word: &crate::ast::Word) -> bool {
+    fn part_has_command_subst(part: &crate::ast::WordPart) -> bool {
+        match part {
+            crate::ast::WordPart::CommandSubst(_) => true,
+            crate::ast::WordPart::DoubleQuoted(parts) => {
+                parts.iter().any(part_has_command_subst)
+            }
+            crate::ast::WordPart::Arithmetic(expr) => {
+                expr.parts.iter().any(part_has_command_subst)
+            }
+            _ => false,
+        }
+    }
+    word.parts.iter().any(part_has_command_subst)
+}
+
+fn expand(word: &crate::ast::Word) -> Result<Vec<String>> {
+    let mut fields = Fields {
+        split: true,
+        ..Fields::default()
+    };
+    for part in &word.parts {
+        fields.push_part(part, false)?;
+    }
+    fields.finish();
+    Ok(fields.done)
+}
+
+fn expand_words(words: &[crate::ast::Word]) -> Result<Vec<String>> {
+    let mut expanded = vec![];
+    for word in words {
+        expanded.extend(expand(word)?);
+    }
+    Ok(expanded)
+}
+
+// assignment values are never split into multiple fields
+fn expand_assignment(word: &crate::ast::Word) -> Result<String> {
+    let mut fields = Fields::default();
+    for part in &word.parts {
+        fields.push_part(part, false)?;
+    }
+    Ok(fields.current.unwrap_or_default())
+}
+
+fn expand_pattern(word: &crate::ast::Word) -> Result<String> {
+    let mut fields = Fields {
+        pattern: true,
+        ..Fields::default()
+    };
+    for part in &word.parts {
+        fields.push_part(part, false)?;
+    }
+    Ok(fields.current.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::Future as _;
+    use futures::stream::Stream as _;
+
+    // the shell state is global, so only one command line runs at a time
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    // the output and status of a command line which only runs builtins
+    fn run(line: &str) -> (String, i32) {
+        let _lock = LOCK
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let events = super::eval(line).collect().wait().unwrap();
+        let mut output = String::new();
+        for event in events {
+            if let super::Event::Output { data } = event {
+                output.push_str(&String::from_utf8_lossy(&data));
+            }
+        }
+        (output.replace("\r\n", "\n"), crate::env::status())
+    }
+
+    #[test]
+    fn command_not_found() {
+        assert_eq!(
+            run("nosuchcmd; echo $?"),
+            ("command not found: nosuchcmd\n127\n".to_string(), 0)
+        );
+        assert_eq!(run("nosuchcmd 2>/dev/null").1, 127);
+        assert_eq!(
+            run("nosuchcmd 2>/dev/null || echo fallback").0,
+            "fallback\n"
+        );
+    }
+
+    #[test]
+    fn failed_commands_set_the_status() {
+        assert_eq!(
+            run("if nosuchcmd 2>/dev/null; then echo yes; else echo no; fi")
+                .0,
+            "no\n"
+        );
+        assert_eq!(
+            run("if cat </nonexistent; then echo yes; else echo no; fi"),
+            (
+                "failed to open /nonexistent: No such file or directory \
+                 (os error 2)\nno\n"
+                    .to_string(),
+                0
+            )
+        );
+        assert_eq!(
+            run("cat 2>/dev/null </nonexistent || echo fallback"),
+            ("fallback\n".to_string(), 0)
+        );
+        assert_eq!(run("echo hi 2>/dev/null >&5; echo $?").0, "1\n");
+    }
+
+    #[test]
+    fn failed_commands_continue_function_bodies() {
+        assert_eq!(
+            run("f() { nosuchcmd 2>/dev/null; cat 2>/dev/null </nonexistent; \
+                 echo after $?; }; f; echo $?"),
+            ("after 1\n0\n".to_string(), 0)
+        );
     }
 }
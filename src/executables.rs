@@ -0,0 +1,126 @@
+// an index of the executables in $PATH, so that things like command lookup
+// and suggestions don't have to search the filesystem each time. the index
+// is rebuilt when $PATH changes or when one of the directories in it is
+// modified.
+
+use std::os::unix::fs::PermissionsExt as _;
+
+// how often to check whether the directories in $PATH have been modified
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+struct Dir {
+    path: std::path::PathBuf,
+    mtime: Option<std::time::SystemTime>,
+}
+
+#[derive(Default)]
+struct Index {
+    // the value of $PATH the index was built from
+    path_var: String,
+    // relative entries in $PATH depend on the current directory
+    cwd: Option<std::path::PathBuf>,
+    dirs: Vec<Dir>,
+    executables: std::collections::HashMap<String, std::path::PathBuf>,
+    checked: Option<std::time::Instant>,
+}
+
+impl Index {
+    fn build(path_var: &str) -> Self {
+        let cwd = if path_var.split(':').any(|dir| !dir.starts_with('/')) {
+            std::env::current_dir().ok()
+        } else {
+            None
+        };
+        let mut dirs = vec![];
+        let mut executables = std::collections::HashMap::new();
+        for dir in path_var.split(':') {
+            // an empty entry means the current directory
+            let dir = if dir.is_empty() { "." } else { dir };
+            let path = cwd.as_ref().map_or_else(
+                || std::path::PathBuf::from(dir),
+                |cwd| cwd.join(dir),
+            );
+            let mtime = mtime(&path);
+            if let Ok(entries) = std::fs::read_dir(&path) {
+                for entry in entries.filter_map(std::result::Result::ok) {
+                    let Ok(name) = entry.file_name().into_string() else {
+                        continue;
+                    };
+                    // earlier directories in $PATH take precedence
+                    if executables.contains_key(&name) {
+                        continue;
+                    }
+                    let path = entry.path();
+                    if is_executable(&path) {
+                        executables.insert(name, path);
+                    }
+                }
+            }
+            dirs.push(Dir { path, mtime });
+        }
+        Self {
+            path_var: path_var.to_string(),
+            cwd,
+            dirs,
+            executables,
+            checked: Some(std::time::Instant::now()),
+        }
+    }
+
+    fn is_stale(&mut self, path_var: &str) -> bool {
+        if self.checked.is_none() || self.path_var != path_var {
+            return true;
+        }
+        if self.cwd.is_some() && self.cwd != std::env::current_dir().ok() {
+            return true;
+        }
+        let now = std::time::Instant::now();
+        if self.checked.is_some_and(|checked| {
+            now.duration_since(checked) < CHECK_INTERVAL
+        }) {
+            return false;
+        }
+        self.checked = Some(now);
+        self.dirs.iter().any(|dir| mtime(&dir.path) != dir.mtime)
+    }
+}
+
+static INDEX: std::sync::LazyLock<std::sync::Mutex<Index>> =
+    std::sync::LazyLock::new(|| std::sync::Mutex::new(Index::default()));
+
+// returns the index, rebuilding it first if it is out of date
+fn index() -> std::sync::MutexGuard<'static, Index> {
+    let path_var = crate::env::var("PATH").unwrap_or_default();
+    let mut index = INDEX.lock().unwrap();
+    if index.is_stale(&path_var) {
+        *index = Index::build(&path_var);
+    }
+    index
+}
+
+// the location of the executable that running `name` would find in $PATH
+pub fn get(name: &str) -> Option<std::path::PathBuf> {
+    index().executables.get(name).cloned()
+}
+
+pub fn names() -> Vec<String> {
+    index().executables.keys().cloned().collect()
+}
+
+// brings the index up to date, so that the work happens while waiting for
+// input rather than when a command is run
+pub fn refresh() {
+    drop(index());
+}
+
+pub fn is_executable(path: &std::path::Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|metadata| {
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    })
+}
+
+fn mtime(path: &std::path::Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
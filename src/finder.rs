@@ -0,0 +1,517 @@
+// a fuzzy finder, like fzf, which narrows down a list of candidates as a
+// query is typed. readline feeds it keys and decides what to do with the
+// selection - this only keeps track of the query, the matches and which
+// one is selected, and knows how to draw them below the query.
+
+use std::fmt::Write as _;
+
+// the most matches shown at once
+const MAX_HEIGHT: usize = 10;
+
+// walking the filesystem stops after this many entries, so that a finder
+// started somewhere huge (like /) still opens quickly
+const MAX_WALK: usize = 100_000;
+
+// scores for each matching character. the total is reduced by one for
+// each unmatched character between the first and last match.
+const SCORE_MATCH: i64 = 16;
+const BONUS_BOUNDARY: i64 = 10;
+const BONUS_CONSECUTIVE: i64 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    History,
+    File,
+    Directory,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::History => "history",
+            Self::File => "files",
+            Self::Directory => "directories",
+        }
+    }
+}
+
+struct Match {
+    idx: usize,
+    score: i64,
+    // char positions of the matching characters, for highlighting
+    positions: Vec<usize>,
+}
+
+pub struct Finder {
+    kind: Kind,
+    // in order of preference, which breaks ties between equal scores
+    candidates: Vec<String>,
+    query: String,
+    // best first
+    matches: Vec<Match>,
+    selected: usize,
+    // the first match which is visible
+    scroll: usize,
+}
+
+impl Finder {
+    pub fn new(kind: Kind, candidates: Vec<String>) -> Self {
+        let mut finder = Self {
+            kind,
+            candidates,
+            query: String::new(),
+            matches: vec![],
+            selected: 0,
+            scroll: 0,
+        };
+        finder.update();
+        finder
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn set_candidates(&mut self, candidates: Vec<String>) {
+        self.candidates = candidates;
+        self.update();
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        self.query.push_str(s);
+        // adding to the query can only remove matches
+        let query = &self.query;
+        let candidates = &self.candidates;
+        self.matches = std::mem::take(&mut self.matches)
+            .into_iter()
+            .filter_map(|m| {
+                score(query, &candidates[m.idx]).map(|(score, positions)| {
+                    Match {
+                        idx: m.idx,
+                        score,
+                        positions,
+                    }
+                })
+            })
+            .collect();
+        self.sort();
+    }
+
+    pub fn pop(&mut self) {
+        if self.query.pop().is_some() {
+            self.update();
+        }
+    }
+
+    pub fn backward_kill_word(&mut self) {
+        let len = self.query.trim_end().rfind(char::is_whitespace).map_or(
+            0,
+            |idx| {
+                idx + self.query[idx..]
+                    .chars()
+                    .next()
+                    .map_or(1, char::len_utf8)
+            },
+        );
+        if len != self.query.len() {
+            self.query.truncate(len);
+            self.update();
+        }
+    }
+
+    pub fn clear_query(&mut self) {
+        if !self.query.is_empty() {
+            self.query.clear();
+            self.update();
+        }
+    }
+
+    // towards better matches
+    pub fn up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn down(&mut self) {
+        if self.selected + 1 < self.matches.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        self.matches
+            .get(self.selected)
+            .map(|m| self.candidates[m.idx].as_str())
+    }
+
+    // returns the query line followed by the info line and the matches,
+    // leaving the cursor at the end of the query. the terminal cursor is
+    // expected to be at the start of the line the query goes on, and the
+    // finder is erased again by clearing from there.
+    pub fn render(&mut self, info: &str, cols: u16, rows: u16) -> String {
+        let width = usize::from(cols).max(3);
+        let height =
+            MAX_HEIGHT.min(usize::from(rows).saturating_sub(3)).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+
+        let mut buf = String::new();
+        buf.push_str("\r\x1b[J> ");
+        buf.push_str(&self.query.replace('\n', " "));
+        let mut lines = 1;
+        write!(
+            buf,
+            "\r\n\x1b[2m  {}/{} {}\x1b[m",
+            self.matches.len(),
+            self.candidates.len(),
+            truncate(info, width - 2)
+        )
+        .unwrap();
+        for (i, m) in self
+            .matches
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(height)
+        {
+            buf.push_str("\r\n");
+            let selected = i == self.selected;
+            buf.push_str(if selected { "\x1b[7m> " } else { "  " });
+            let line = display(&self.candidates[m.idx]);
+            for (pos, c) in line.chars().take(width - 2).enumerate() {
+                if m.positions.contains(&pos) {
+                    buf.push_str("\x1b[1m");
+                    buf.push(c);
+                    buf.push_str("\x1b[22m");
+                } else {
+                    buf.push(c);
+                }
+            }
+            if selected {
+                buf.push_str("\x1b[m");
+            }
+            lines += 1;
+        }
+        write!(
+            buf,
+            "\x1b[{lines}A\r\x1b[{}C",
+            self.query.chars().count() + 2
+        )
+        .unwrap();
+        buf
+    }
+
+    fn update(&mut self) {
+        self.matches = self
+            .candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, candidate)| {
+                score(&self.query, candidate).map(|(score, positions)| {
+                    Match {
+                        idx,
+                        score,
+                        positions,
+                    }
+                })
+            })
+            .collect();
+        self.sort();
+    }
+
+    fn sort(&mut self) {
+        self.matches
+            .sort_by(|a, b| b.score.cmp(&a.score).then(a.idx.cmp(&b.idx)));
+        self.selected = 0;
+        self.scroll = 0;
+    }
+}
+
+// scores how well the query matches the candidate, if it matches at all.
+// every character of the query has to appear in the candidate in order,
+// and matches score higher when the characters are consecutive, start
+// words or path components, or are close together. matching ignores case
+// unless the query contains an uppercase letter. also returns the char
+// positions of the characters which matched.
+pub fn score(query: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+    let ignore_case = !query.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if ignore_case {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        }
+    };
+    let query: Vec<_> = query.chars().map(fold).collect();
+    if query.is_empty() {
+        return Some((0, vec![]));
+    }
+    let chars: Vec<_> = candidate.chars().collect();
+
+    // find where the first complete match ends...
+    let mut qi = 0;
+    let mut end = None;
+    for (i, c) in chars.iter().enumerate() {
+        if fold(*c) == query[qi] {
+            qi += 1;
+            if qi == query.len() {
+                end = Some(i);
+                break;
+            }
+        }
+    }
+    let end = end?;
+
+    // ...and then search backwards from there for the latest start, which
+    // gives the shortest match ending there
+    let mut qi = query.len();
+    let mut start = end;
+    for i in (0..=end).rev() {
+        if fold(chars[i]) == query[qi - 1] {
+            qi -= 1;
+            if qi == 0 {
+                start = i;
+                break;
+            }
+        }
+    }
+
+    let mut score = 0;
+    let mut positions = vec![];
+    let mut qi = 0;
+    for i in start..=end {
+        if qi < query.len() && fold(chars[i]) == query[qi] {
+            score += SCORE_MATCH;
+            if i == 0
+                || " /-_.:=".contains(chars[i - 1])
+                || (chars[i - 1].is_lowercase() && chars[i].is_uppercase())
+            {
+                score += BONUS_BOUNDARY;
+            }
+            if positions.last().is_some_and(|last| last + 1 == i) {
+                score += BONUS_CONSECUTIVE;
+            }
+            positions.push(i);
+            qi += 1;
+        } else {
+            score -= 1;
+        }
+    }
+    Some((score, positions))
+}
+
+// history lines, most recent first, without duplicates
+pub fn history(filter: crate::history::Filter) -> Vec<String> {
+    let query = crate::history::Query::new().filter(filter);
+    let mut seen = std::collections::HashSet::new();
+    crate::history::search(&query)
+        .into_iter()
+        .rev()
+        .filter_map(|(_, entry)| {
+            if seen.insert(entry.line.clone()) {
+                Some(entry.line)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// paths under the given directory, relative to it, shallowest first.
+// hidden files and directories are skipped, like with globs.
+pub fn files(dir: &std::path::Path) -> Vec<String> {
+    walk(dir, false)
+}
+
+// directories from the frecency database, best first, followed by the
+// directories under the given one
+pub fn directories(dir: &std::path::Path) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    let mut candidates = vec![];
+    for entry in crate::frecency::matches(&[]) {
+        if entry.path != dir && seen.insert(entry.path.clone()) {
+            candidates.push(entry.path.to_string_lossy().into_owned());
+        }
+    }
+    for path in walk(dir, true) {
+        if seen.insert(dir.join(&path)) {
+            candidates.push(path);
+        }
+    }
+    candidates
+}
+
+fn walk(dir: &std::path::Path, dirs_only: bool) -> Vec<String> {
+    let mut paths = vec![];
+    let mut seen = 0;
+    let mut queue = std::collections::VecDeque::new();
+    queue.push_back(std::path::PathBuf::new());
+    while let Some(rel) = queue.pop_front() {
+        let Ok(entries) = std::fs::read_dir(dir.join(&rel)) else {
+            continue;
+        };
+        let mut entries: Vec<_> = entries
+            .filter_map(std::result::Result::ok)
+            .filter(|entry| {
+                !entry.file_name().to_string_lossy().starts_with('.')
+            })
+            .collect();
+        entries.sort_by_key(std::fs::DirEntry::file_name);
+        for entry in entries {
+            seen += 1;
+            if seen > MAX_WALK {
+                return paths;
+            }
+            // symlinks aren't followed, to avoid loops
+            let is_dir = entry.file_type().is_ok_and(|ty| ty.is_dir());
+            let path = rel.join(entry.file_name());
+            if is_dir || !dirs_only {
+                paths.push(path.to_string_lossy().into_owned());
+            }
+            if is_dir {
+                queue.push_back(path);
+            }
+        }
+    }
+    paths
+}
+
+// candidates are shown on a single line each
+fn display(candidate: &str) -> String {
+    candidate
+        .chars()
+        .map(|c| {
+            if c == '\n' {
+                '↵'
+            } else if c.is_control() {
+                ' '
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+fn truncate(s: &str, width: usize) -> String {
+    s.chars().take(width).collect()
+}
+
+// the terminal size as (cols, rows), falling back to 80x24 if it can't be
+// determined
+pub fn terminal_size() -> (u16, u16) {
+    crossterm::terminal::size()
+        .ok()
+        .filter(|(cols, rows)| *cols > 0 && *rows > 0)
+        .unwrap_or((80, 24))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(query: &str, candidate: &str) -> Option<Vec<usize>> {
+        score(query, candidate).map(|(_, positions)| positions)
+    }
+
+    // all of the matches, best first, by moving the selection through them
+    fn matches(finder: &mut Finder) -> Vec<String> {
+        for _ in 0..finder.matches.len() {
+            finder.up();
+        }
+        let mut matches = vec![];
+        while let Some(selected) = finder.selected() {
+            if matches.last().map(String::as_str) == Some(selected) {
+                break;
+            }
+            matches.push(selected.to_string());
+            finder.down();
+        }
+        matches
+    }
+
+    #[test]
+    fn scores() {
+        assert_eq!(score("", "anything"), Some((0, vec![])));
+        assert_eq!(score("abc", "abc"), Some((74, vec![0, 1, 2])));
+        assert_eq!(score("ac", "abc"), Some((41, vec![0, 2])));
+        assert_eq!(score("abd", "abc"), None);
+        assert_eq!(score("ba", "abc"), None);
+        // the shortest match is used
+        assert_eq!(positions("ab", "a_xab"), Some(vec![3, 4]));
+        // chars, not bytes
+        assert_eq!(positions("éb", "aébé"), Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(score("readme", "README.md").is_some());
+        assert!(score("README", "readme.md").is_none());
+        assert!(score("Read", "README.md").is_none());
+        assert!(score("Read", "ReadMe.md").is_some());
+    }
+
+    #[test]
+    fn boundaries_and_gaps() {
+        let total = |query, candidate| score(query, candidate).unwrap().0;
+        // the start of a word or path component
+        assert!(total("fb", "foo/bar") > total("fb", "xfxbx"));
+        assert!(total("fb", "fooBar") > total("fb", "foobar"));
+        assert!(total("fb", "foo-bar") > total("fb", "foo-xbar"));
+        // consecutive characters
+        assert!(total("ma", "xmain") > total("ma", "xmxa"));
+        // closer together
+        assert!(total("ab", "axb") > total("ab", "axxxb"));
+    }
+
+    #[test]
+    fn ordering() {
+        let mut finder = Finder::new(
+            Kind::File,
+            vec![
+                "src/main.rs".to_string(),
+                "tests/fixtures/index.d".to_string(),
+                "bin/xfind".to_string(),
+                "src/finder.rs".to_string(),
+            ],
+        );
+        // everything matches an empty query, in the original order
+        assert_eq!(matches(&mut finder)[0], "src/main.rs");
+        assert_eq!(matches(&mut finder).len(), 4);
+        finder.push_str("find");
+        assert_eq!(
+            matches(&mut finder),
+            vec!["src/finder.rs", "bin/xfind", "tests/fixtures/index.d"]
+        );
+        // moving up stops at the best match
+        finder.up();
+        finder.up();
+        finder.up();
+        assert_eq!(finder.selected(), Some("src/finder.rs"));
+        finder.push_str("er");
+        assert_eq!(matches(&mut finder), vec!["src/finder.rs"]);
+        finder.push_str("x");
+        assert_eq!(finder.selected(), None);
+        finder.pop();
+        finder.pop();
+        finder.pop();
+        assert_eq!(matches(&mut finder).len(), 3);
+        finder.clear_query();
+        assert_eq!(matches(&mut finder).len(), 4);
+    }
+
+    #[test]
+    fn ties_keep_candidate_order() {
+        let mut finder = Finder::new(
+            Kind::History,
+            vec!["make b".to_string(), "make a".to_string()],
+        );
+        finder.push_str("make");
+        assert_eq!(matches(&mut finder), vec!["make b", "make a"]);
+        finder.push_str(" a");
+        assert_eq!(matches(&mut finder), vec!["make a"]);
+        finder.backward_kill_word();
+        assert_eq!(matches(&mut finder), vec!["make b", "make a"]);
+    }
+}
@@ -0,0 +1,287 @@
+// a database of visited directories, ranked by how often and how recently
+// they were visited, for jumping to them with `z`. the format is compatible
+// with z.sh: one `path|rank|time` line per directory. it is reread and
+// locked for each update so that multiple shells can share it.
+
+use std::fmt::Write as _;
+
+// once the total rank reaches this, all ranks are scaled down so that
+// directories which are no longer used eventually drop out
+const MAX_TOTAL_RANK: f64 = 9000.0;
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: std::path::PathBuf,
+    rank: f64,
+    time: u64,
+}
+
+impl Entry {
+    // recent visits count for more than old ones
+    pub fn score(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.time);
+        if age < 60 * 60 {
+            self.rank * 4.0
+        } else if age < 24 * 60 * 60 {
+            self.rank * 2.0
+        } else if age < 7 * 24 * 60 * 60 {
+            self.rank / 2.0
+        } else {
+            self.rank / 4.0
+        }
+    }
+}
+
+fn path() -> Option<std::path::PathBuf> {
+    std::env::var_os("HOME")
+        .map(|home| std::path::Path::new(&home).join(".nbsh_z"))
+}
+
+pub fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+fn load() -> Vec<Entry> {
+    let Some(path) = path() else {
+        return vec![];
+    };
+    let Ok(mut file) = std::fs::File::open(path) else {
+        return vec![];
+    };
+    let mut contents = String::new();
+    if file.lock_shared().is_err()
+        || std::io::Read::read_to_string(&mut file, &mut contents).is_err()
+    {
+        return vec![];
+    }
+    parse(&contents)
+}
+
+fn parse(contents: &str) -> Vec<Entry> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            // paths can contain |, so parse from the end
+            let mut fields = line.rsplitn(3, '|');
+            let time = fields.next()?.parse().ok()?;
+            let rank = fields.next()?.parse().ok()?;
+            let path = fields.next()?.into();
+            Some(Entry { path, rank, time })
+        })
+        .collect()
+}
+
+// applies the change to the database, and saves it if the change returns
+// true. this rewrites the file in place while holding a lock, so that
+// concurrent updates from other shells aren't lost.
+fn update(
+    change: impl FnOnce(&mut Vec<Entry>) -> bool,
+) -> std::io::Result<()> {
+    let Some(path) = path() else {
+        return Ok(());
+    };
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    file.lock()?;
+    let mut contents = String::new();
+    std::io::Read::read_to_string(&mut file, &mut contents)?;
+    let mut entries = parse(&contents);
+    if !change(&mut entries) {
+        return Ok(());
+    }
+    let mut contents = String::new();
+    for entry in &entries {
+        writeln!(
+            contents,
+            "{}|{}|{}",
+            entry.path.display(),
+            entry.rank,
+            entry.time
+        )
+        .unwrap();
+    }
+    file.set_len(0)?;
+    std::io::Seek::rewind(&mut file)?;
+    std::io::Write::write_all(&mut file, contents.as_bytes())
+}
+
+// records a visit to the given directory
+pub fn add(dir: &std::path::Path) {
+    // the home directory is easy enough to get to already
+    if std::env::var_os("HOME").is_some_and(|home| dir == home) {
+        return;
+    }
+    let now = now();
+    // nothing useful to do if this fails
+    let _ = update(|entries| {
+        if let Some(entry) =
+            entries.iter_mut().find(|entry| entry.path == dir)
+        {
+            entry.rank += 1.0;
+            entry.time = now;
+        } else {
+            entries.push(Entry {
+                path: dir.to_path_buf(),
+                rank: 1.0,
+                time: now,
+            });
+        }
+        let total: f64 = entries.iter().map(|entry| entry.rank).sum();
+        if total > MAX_TOTAL_RANK {
+            for entry in entries.iter_mut() {
+                entry.rank *= 0.99;
+            }
+            entries.retain(|entry| entry.rank >= 1.0);
+        }
+        true
+    });
+}
+
+// removes a directory from the database, returning whether it was there
+pub fn remove(dir: &std::path::Path) -> bool {
+    let mut removed = false;
+    // nothing useful to do if this fails
+    let _ = update(|entries| {
+        let len = entries.len();
+        entries.retain(|entry| entry.path != dir);
+        removed = entries.len() != len;
+        removed
+    });
+    removed
+}
+
+// directories which contain each of the given fragments in order, best
+// first. matching is case sensitive unless that finds nothing. directories
+// which no longer exist are skipped.
+pub fn matches(fragments: &[String]) -> Vec<Entry> {
+    let entries: Vec<_> = load()
+        .into_iter()
+        .filter(|entry| entry.path.is_dir())
+        .collect();
+    let mut found: Vec<_> = entries
+        .iter()
+        .filter(|entry| is_match(&entry.path, fragments, false))
+        .cloned()
+        .collect();
+    if found.is_empty() {
+        found = entries
+            .into_iter()
+            .filter(|entry| is_match(&entry.path, fragments, true))
+            .collect();
+    }
+    let now = now();
+    found.sort_by(|a, b| b.score(now).total_cmp(&a.score(now)));
+    found
+}
+
+fn is_match(
+    path: &std::path::Path,
+    fragments: &[String],
+    ignore_case: bool,
+) -> bool {
+    let mut path = path.to_string_lossy().into_owned();
+    if ignore_case {
+        path = path.to_lowercase();
+    }
+    let mut rest = path.as_str();
+    for fragment in fragments {
+        let fragment = if ignore_case {
+            fragment.to_lowercase()
+        } else {
+            fragment.clone()
+        };
+        match rest.find(&fragment) {
+            Some(idx) => rest = &rest[idx + fragment.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+// the expected values are exactly what parsing them or scaling by powers of
+// two produces, so comparing them directly is fine
+#[allow(clippy::float_cmp)]
+mod tests {
+    use super::*;
+
+    fn entry(rank: f64, time: u64) -> Entry {
+        Entry {
+            path: "/src".into(),
+            rank,
+            time,
+        }
+    }
+
+    #[test]
+    fn parse_lines() {
+        let entries = parse(
+            "/home/user/src|12.5|1570000000\n\
+             /tmp/a|b|3|1570000001\n\
+             /no/rank|1570000002\n\
+             /bad/time|1|soon\n\
+             \n\
+             /usr/lib|0.99|1570000003\n",
+        );
+        let parsed: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                (entry.path.to_str().unwrap(), entry.rank, entry.time)
+            })
+            .collect();
+        assert_eq!(
+            parsed,
+            vec![
+                ("/home/user/src", 12.5, 1_570_000_000),
+                // paths can contain the separator
+                ("/tmp/a|b", 3.0, 1_570_000_001),
+                ("/usr/lib", 0.99, 1_570_000_003),
+            ]
+        );
+    }
+
+    #[test]
+    fn fragments_match_in_order() {
+        let path = std::path::Path::new("/home/user/src/nbsh");
+        let fragments = |fragments: &[&str]| -> Vec<String> {
+            fragments.iter().map(ToString::to_string).collect()
+        };
+        assert!(is_match(path, &fragments(&["nbsh"]), false));
+        assert!(is_match(path, &fragments(&["src", "sh"]), false));
+        assert!(is_match(path, &fragments(&[]), false));
+        assert!(!is_match(path, &fragments(&["nbsh", "src"]), false));
+        // each fragment has to match after the end of the previous one
+        assert!(!is_match(path, &fragments(&["nbsh", "sh"]), false));
+        assert!(!is_match(path, &fragments(&["SRC"]), false));
+        assert!(is_match(path, &fragments(&["SRC", "Nb"]), true));
+    }
+
+    #[test]
+    fn recent_visits_score_higher() {
+        let now = 1_000_000_000;
+        let hour = 60 * 60;
+        let day = 24 * hour;
+        assert_eq!(entry(2.0, now).score(now), 8.0);
+        assert_eq!(entry(2.0, now - hour + 1).score(now), 8.0);
+        assert_eq!(entry(2.0, now - hour).score(now), 4.0);
+        assert_eq!(entry(2.0, now - day).score(now), 1.0);
+        assert_eq!(entry(2.0, now - 7 * day).score(now), 0.5);
+        // clocks can go backwards
+        assert_eq!(entry(2.0, now + hour).score(now), 8.0);
+        // an old directory needs many more visits to beat a recent one
+        assert!(
+            entry(20.0, now - 30 * day).score(now)
+                > entry(1.0, now).score(now)
+        );
+        assert!(
+            entry(10.0, now - 30 * day).score(now)
+                < entry(1.0, now).score(now)
+        );
+    }
+}
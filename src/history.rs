@@ -0,0 +1,649 @@
+// the lines which have been run, along with when and where they were run
+// and how they exited. the history is saved in ~/.nbsh_history as one json
+// object per line. records are appended under a lock once their line has
+// finished running, so that multiple shells can share the file.
+
+use snafu::OptionExt as _;
+use std::convert::TryFrom as _;
+
+#[derive(Debug, snafu::Snafu)]
+pub enum Error {
+    #[snafu(display("{}: event not found", event))]
+    EventNotFound { event: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    pub line: String,
+    // seconds since the epoch
+    pub start: u64,
+    // in milliseconds. None if the line is still running, or if the shell
+    // exited before it finished.
+    pub duration: Option<u64>,
+    pub cwd: std::path::PathBuf,
+    pub status: Option<i32>,
+    pub host: String,
+    pub session: String,
+    // counts up within a session, to tell apart entries which were run
+    // at the same time
+    pub id: u64,
+}
+
+impl Entry {
+    fn is(&self, other: &Self) -> bool {
+        self.session == other.session && self.id == other.id
+    }
+}
+
+struct History {
+    entries: Vec<Entry>,
+    // the line which is currently running, and when it started
+    running: Option<(Entry, std::time::Instant)>,
+}
+
+static HISTORY: std::sync::LazyLock<std::sync::Mutex<History>> =
+    std::sync::LazyLock::new(|| {
+        std::sync::Mutex::new(History {
+            entries: load(),
+            running: None,
+        })
+    });
+
+fn history() -> std::sync::MutexGuard<'static, History> {
+    HISTORY.lock().unwrap()
+}
+
+// identifies the lines run by this shell
+static SESSION: std::sync::LazyLock<String> =
+    std::sync::LazyLock::new(|| {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.subsec_nanos());
+        format!("{:x}-{:x}", std::process::id(), nanos)
+    });
+
+static NEXT_ID: std::sync::atomic::AtomicU64 =
+    std::sync::atomic::AtomicU64::new(0);
+
+static HOST: std::sync::LazyLock<String> = std::sync::LazyLock::new(|| {
+    let mut buf = [0; 256];
+    nix::unistd::gethostname(&mut buf)
+        .map(|host| host.to_string_lossy().into_owned())
+        .unwrap_or_default()
+});
+
+pub fn session() -> String {
+    SESSION.clone()
+}
+
+fn path() -> Option<std::path::PathBuf> {
+    // tests shouldn't read or write the real history
+    if cfg!(test) {
+        return None;
+    }
+    std::env::var_os("HOME")
+        .map(|home| std::path::Path::new(&home).join(".nbsh_history"))
+}
+
+// lines which can't be parsed are skipped, since they may have been
+// written by a newer version
+fn load() -> Vec<Entry> {
+    let Some(file) = path().and_then(|path| std::fs::File::open(path).ok())
+    else {
+        return vec![];
+    };
+    if file.lock_shared().is_err() {
+        return vec![];
+    }
+    std::io::BufRead::lines(std::io::BufReader::new(&file))
+        .map_while(std::result::Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect()
+}
+
+fn append(entry: &Entry) -> std::io::Result<()> {
+    let Some(path) = path() else {
+        return Ok(());
+    };
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.lock()?;
+    std::io::Write::write_all(&mut file, line.as_bytes())
+}
+
+// removes the records that match from the file. this rewrites the file in
+// place while holding the lock, so that concurrent appends aren't lost.
+fn rewrite(remove: impl Fn(&Entry) -> bool) -> std::io::Result<()> {
+    let Some(path) = path() else {
+        return Ok(());
+    };
+    let mut file = match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    file.lock()?;
+    let mut contents = String::new();
+    std::io::Read::read_to_string(&mut file, &mut contents)?;
+    let mut kept = String::new();
+    for line in contents.lines() {
+        let removed = serde_json::from_str(line)
+            .is_ok_and(|entry: Entry| remove(&entry));
+        if !removed {
+            kept.push_str(line);
+            kept.push('\n');
+        }
+    }
+    file.set_len(0)?;
+    std::io::Seek::rewind(&mut file)?;
+    std::io::Write::write_all(&mut file, kept.as_bytes())
+}
+
+// records a line which is about to be run. it is saved once finish is
+// called.
+pub fn add(line: &str) {
+    if line.trim().is_empty() {
+        return;
+    }
+    let entry = Entry {
+        line: line.to_string(),
+        start: crate::frecency::now(),
+        duration: None,
+        cwd: std::env::current_dir().unwrap_or_default(),
+        status: None,
+        host: HOST.clone(),
+        session: session(),
+        id: NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+    };
+    let mut history = history();
+    history.entries.push(entry.clone());
+    history.running = Some((entry, std::time::Instant::now()));
+}
+
+// records how the line that was most recently added exited
+pub fn finish(status: i32) {
+    let mut history = history();
+    let Some((mut entry, started)) = history.running.take() else {
+        return;
+    };
+    entry.duration =
+        Some(u64::try_from(started.elapsed().as_millis()).unwrap_or(0));
+    entry.status = Some(status);
+    // the line may have been removed by `history` while it was running
+    if let Some(existing) =
+        history.entries.iter_mut().rev().find(|e| e.is(&entry))
+    {
+        *existing = entry.clone();
+    }
+    drop(history);
+    // nothing useful to do if this fails
+    let _ = append(&entry);
+}
+
+pub fn len() -> usize {
+    history().entries.len()
+}
+
+pub fn get(idx: usize) -> Option<String> {
+    history().entries.get(idx).map(|entry| entry.line.clone())
+}
+
+// removes everything, along with the lines this shell saved. the lines
+// saved by other shells are left alone, since they share the file.
+pub fn clear() {
+    history().entries.clear();
+    let session = session();
+    let _ = rewrite(|entry| entry.session == session);
+}
+
+// returns false if there is no such entry
+pub fn remove(idx: usize) -> bool {
+    let mut history = history();
+    if idx >= history.entries.len() {
+        return false;
+    }
+    let entry = history.entries.remove(idx);
+    drop(history);
+    let _ = rewrite(|e| e.is(&entry));
+    true
+}
+
+// limits which entries are considered when moving through or searching the
+// history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    #[default]
+    Global,
+    // lines run by this shell
+    Session,
+    // lines run in the current directory, or anywhere in the current
+    // repository
+    Directory,
+    // lines which exited unsuccessfully
+    Failed,
+}
+
+impl Filter {
+    const ALL: &'static [Self] =
+        &[Self::Global, Self::Session, Self::Directory, Self::Failed];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::Session => "session",
+            Self::Directory => "directory",
+            Self::Failed => "failed",
+        }
+    }
+
+    // cycles through the filters in order
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|f| *f == self).unwrap();
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    // the filter to start with, from $NBSH_HISTORY_FILTER
+    pub fn from_env() -> Self {
+        crate::env::var("NBSH_HISTORY_FILTER")
+            .and_then(|name| {
+                Self::ALL.iter().copied().find(|f| f.name() == name)
+            })
+            .unwrap_or_default()
+    }
+}
+
+// which entries to look for. an empty query matches everything.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    prefix: Option<String>,
+    contains: Option<String>,
+    exclude: Option<String>,
+    session: Option<String>,
+    // the directory which entries must have been run in, and whether
+    // subdirectories count too
+    directory: Option<(std::path::PathBuf, bool)>,
+    failed: bool,
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.to_string());
+        self
+    }
+
+    pub fn contains(mut self, text: &str) -> Self {
+        self.contains = Some(text.to_string());
+        self
+    }
+
+    // skips entries for exactly this line, like the one being edited
+    pub fn exclude(mut self, line: &str) -> Self {
+        self.exclude = Some(line.to_string());
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Self {
+        match filter {
+            Filter::Global => {}
+            Filter::Session => self.session = Some(session()),
+            Filter::Directory => {
+                let cwd = std::env::current_dir().unwrap_or_default();
+                self.directory = Some(
+                    repository_root(&cwd)
+                        .map_or((cwd, false), |root| (root, true)),
+                );
+            }
+            Filter::Failed => self.failed = true,
+        }
+        self
+    }
+
+    fn matches(&self, entry: &Entry) -> bool {
+        self.prefix
+            .as_ref()
+            .is_none_or(|prefix| entry.line.starts_with(prefix))
+            && self
+                .contains
+                .as_ref()
+                .is_none_or(|text| entry.line.contains(text))
+            && self.exclude.as_ref().is_none_or(|line| entry.line != *line)
+            && self
+                .session
+                .as_ref()
+                .is_none_or(|session| entry.session == *session)
+            && self.directory.as_ref().is_none_or(|(dir, recursive)| {
+                if *recursive {
+                    entry.cwd.starts_with(dir)
+                } else {
+                    entry.cwd == *dir
+                }
+            })
+            && (!self.failed || entry.status.is_some_and(|s| s != 0))
+    }
+}
+
+// the closest directory containing dir (including dir itself) which is the
+// top of a git repository
+fn repository_root(dir: &std::path::Path) -> Option<std::path::PathBuf> {
+    dir.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(std::path::Path::to_path_buf)
+}
+
+// matching entries along with their indices, oldest first
+pub fn search(query: &Query) -> Vec<(usize, Entry)> {
+    history()
+        .entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| query.matches(entry))
+        .map(|(idx, entry)| (idx, entry.clone()))
+        .collect()
+}
+
+// the index of the closest matching entry before idx
+pub fn find_before(query: &Query, idx: usize) -> Option<usize> {
+    let history = history();
+    let end = idx.min(history.entries.len());
+    history.entries[..end]
+        .iter()
+        .rposition(|entry| query.matches(entry))
+}
+
+// the index of the closest matching entry after idx
+pub fn find_after(query: &Query, idx: usize) -> Option<usize> {
+    let history = history();
+    let start = (idx + 1).min(history.entries.len());
+    history.entries[start..]
+        .iter()
+        .position(|entry| query.matches(entry))
+        .map(|pos| start + pos)
+}
+
+// like in csh, `!!` is the previous line, `!$` is the last word of the
+// previous line, `!n` is line n (counting from 1, as `history` shows them),
+// `!-n` is the nth previous line and `!prefix` is the most recent line
+// starting with prefix. returns None if the line contains none of these.
+pub fn expand(line: &str) -> Result<Option<String>> {
+    let history = history();
+    let mut expanded = String::new();
+    let mut changed = false;
+    let mut single_quoted = false;
+    let mut double_quoted = false;
+    let mut prev = None;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if !single_quoted => {
+                expanded.push(c);
+                if let Some((_, c)) = chars.next() {
+                    expanded.push(c);
+                }
+                prev = None;
+                continue;
+            }
+            '\'' if !double_quoted => single_quoted = !single_quoted,
+            '"' if !single_quoted => double_quoted = !double_quoted,
+            // `$!` is a parameter, not a history reference
+            '!' if !single_quoted && prev != Some('$') => {
+                if let Some((replacement, len)) =
+                    event(&history.entries, &line[i + 1..])?
+                {
+                    expanded.push_str(&replacement);
+                    changed = true;
+                    for _ in 0..len {
+                        chars.next();
+                    }
+                    prev = None;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        expanded.push(c);
+        prev = Some(c);
+    }
+    Ok(if changed { Some(expanded) } else { None })
+}
+
+// returns the replacement for the event designator at the start of rest
+// (just after the `!`), along with how many characters it used
+fn event(history: &[Entry], rest: &str) -> Result<Option<(String, usize)>> {
+    let Some(first) = rest.chars().next() else {
+        return Ok(None);
+    };
+    let last = || {
+        history.last().map(|entry| entry.line.clone()).context(
+            EventNotFound {
+                event: format!("!{first}"),
+            },
+        )
+    };
+    match first {
+        // like in bash, a `!` on its own is left alone
+        c if c.is_whitespace() || "=(\"".contains(c) => Ok(None),
+        '!' => Ok(Some((last()?, 1))),
+        '$' => Ok(Some((last_word(&last()?), 1))),
+        _ => {
+            let len = rest
+                .chars()
+                .take_while(|c| {
+                    !c.is_whitespace() && !";&|()<>'\"`".contains(*c)
+                })
+                .count();
+            let designator: String = rest.chars().take(len).collect();
+            let not_found = || EventNotFound {
+                event: format!("!{designator}"),
+            };
+            // the forms are checked in order, which a chain of closures
+            // would obscure
+            #[allow(clippy::option_if_let_else)]
+            let idx = if let Some(n) = designator.strip_prefix('-') {
+                n.parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .and_then(|n| history.len().checked_sub(n))
+            } else if designator.starts_with(|c: char| c.is_ascii_digit()) {
+                designator
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| n.checked_sub(1))
+            } else {
+                history
+                    .iter()
+                    .rposition(|entry| entry.line.starts_with(&designator))
+            };
+            let entry =
+                idx.and_then(|idx| history.get(idx)).context(not_found())?;
+            Ok(Some((entry.line.clone(), len)))
+        }
+    }
+}
+
+// words are split on whitespace and operators, with quoting preserved
+fn last_word(line: &str) -> String {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) => {
+                if c == q {
+                    quote = None;
+                }
+                word.push(c);
+            }
+            None => {
+                if c == '\\' {
+                    word.push(c);
+                    if let Some(c) = chars.next() {
+                        word.push(c);
+                    }
+                } else if c == '\'' || c == '"' {
+                    quote = Some(c);
+                    word.push(c);
+                } else if c.is_whitespace() || ";&|<>()".contains(c) {
+                    if !word.is_empty() {
+                        words.push(std::mem::take(&mut word));
+                    }
+                } else {
+                    word.push(c);
+                }
+            }
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words.pop().unwrap_or_default()
+}
+
+// formats a time as a local date and time, like `2019-11-02 14:03:27`
+pub fn format_time(time: u64) -> String {
+    let Ok(time) = nix::libc::time_t::try_from(time) else {
+        return String::new();
+    };
+    // nix doesn't wrap localtime_r, but this is safe since both pointers
+    // are valid for the duration of the call
+    let mut tm = unsafe { std::mem::zeroed::<nix::libc::tm>() };
+    if unsafe { nix::libc::localtime_r(&raw const time, &raw mut tm) }
+        .is_null()
+    {
+        return String::new();
+    }
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::Future as _;
+    use futures::stream::Stream as _;
+
+    // the history is shared by every test
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    fn with_history(lines: &[&str], f: impl FnOnce()) {
+        let _lock = LOCK
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        super::clear();
+        for line in lines {
+            super::add(line);
+        }
+        f();
+    }
+
+    fn run(args: &[&str]) -> String {
+        let args: Vec<_> = args.iter().map(ToString::to_string).collect();
+        let events = crate::builtins::run("history", &args)
+            .unwrap()
+            .collect()
+            .wait()
+            .unwrap();
+        let mut output = vec![];
+        for event in events {
+            if let tokio_pty_process_stream::Event::Output { data } = event {
+                output.extend(data);
+            }
+        }
+        String::from_utf8(output).unwrap().replace("\r\n", "\n")
+    }
+
+    #[test]
+    fn expand() {
+        with_history(&["ls -l /tmp", "echo 'a b'", "cd src"], || {
+            assert_eq!(super::expand("echo hi").unwrap(), None);
+            assert_eq!(super::expand("!!").unwrap().unwrap(), "cd src");
+            assert_eq!(
+                super::expand("sudo !! && ls").unwrap().unwrap(),
+                "sudo cd src && ls"
+            );
+            assert_eq!(super::expand("ls !$").unwrap().unwrap(), "ls src");
+            assert_eq!(super::expand("!1").unwrap().unwrap(), "ls -l /tmp");
+            assert_eq!(super::expand("!-2").unwrap().unwrap(), "echo 'a b'");
+            assert_eq!(
+                super::expand("!ec; !l").unwrap().unwrap(),
+                "echo 'a b'; ls -l /tmp"
+            );
+            assert!(super::expand("!4").is_err());
+            assert!(super::expand("!0").is_err());
+            assert!(super::expand("!nosuch").is_err());
+        });
+    }
+
+    #[test]
+    fn expand_last_word() {
+        with_history(&["echo 'a b'"], || {
+            assert_eq!(
+                super::expand("echo !$").unwrap().unwrap(),
+                "echo 'a b'"
+            );
+        });
+        with_history(&["cat foo|grep bar>out"], || {
+            assert_eq!(super::expand("rm !$").unwrap().unwrap(), "rm out");
+        });
+    }
+
+    #[test]
+    fn expand_ignores_quoting_and_parameters() {
+        with_history(&["ls"], || {
+            assert_eq!(super::expand("echo '!!'").unwrap(), None);
+            assert_eq!(super::expand("echo \\!!").unwrap(), None);
+            assert_eq!(super::expand("echo $!").unwrap(), None);
+            assert_eq!(super::expand("echo ! x != y").unwrap(), None);
+            assert_eq!(
+                super::expand("echo \"!!\"").unwrap().unwrap(),
+                "echo \"ls\""
+            );
+        });
+        with_history(&[], || {
+            assert!(super::expand("!!").is_err());
+        });
+    }
+
+    #[test]
+    fn show_last_lines() {
+        with_history(&["one", "two", "three", "four"], || {
+            assert_eq!(run(&["2"]), "    3  three\n    4  four\n");
+            assert_eq!(run(&["10"]).lines().count(), 4);
+            assert_eq!(run(&["0"]), "");
+            assert_eq!(run(&["o"]), "    1  one\n    2  two\n    4  four\n");
+        });
+    }
+
+    #[test]
+    fn delete() {
+        with_history(&["one", "two", "three"], || {
+            assert_eq!(run(&["-d", "2"]), "");
+            assert_eq!(run(&[]), "    1  one\n    2  three\n");
+            assert!(run(&["-d", "3"]).starts_with("history: "));
+            assert!(run(&["-d", "0"]).starts_with("history: "));
+            assert_eq!(super::len(), 2);
+            assert_eq!(run(&["-c"]), "");
+            assert_eq!(super::len(), 0);
+        });
+    }
+}
@@ -1,5 +1,15 @@
 use futures::sink::Sink as _;
 use snafu::ResultExt as _;
+use std::io::Write as _;
+
+const STDIN: std::os::unix::io::RawFd = 0;
+
+// how long to wait for the rest of an escape sequence before deciding that
+// a lone escape byte was just the escape key
+const ESCAPE_TIMEOUT_MS: i32 = 50;
+
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
 
 #[derive(Debug, snafu::Snafu)]
 pub enum Error {
@@ -13,13 +23,27 @@ pub enum Error {
         source
     ))]
     TerminalInputReadingThread { source: std::io::Error },
+
+    #[snafu(display(
+        "failed to create a pipe to signal the input thread: {}",
+        source
+    ))]
+    QuitPipe { source: nix::Error },
+
+    #[snafu(display("failed to write to the terminal: {}", source))]
+    WriteToTerminal { source: std::io::Error },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Key(crossterm::input::KeyEvent),
+    Paste(String),
 }
 
 pub struct KeyReader {
-    events: Option<
-        tokio::sync::mpsc::UnboundedReceiver<crossterm::input::InputEvent>,
-    >,
-    quit: Option<tokio::sync::oneshot::Sender<()>>,
+    events: Option<tokio::sync::mpsc::UnboundedReceiver<Event>>,
+    quit: Option<std::os::unix::io::RawFd>,
+    thread: Option<std::thread::JoinHandle<()>>,
 }
 
 impl KeyReader {
@@ -27,40 +51,86 @@ impl KeyReader {
         Self {
             events: None,
             quit: None,
+            thread: None,
         }
     }
 }
 
 impl futures::stream::Stream for KeyReader {
-    type Item = crossterm::input::InputEvent;
+    type Item = Event;
     type Error = Error;
 
     fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
         if self.events.is_none() {
             let task = futures::task::current();
-            let reader = crossterm::input::input().read_sync();
             let (events_tx, events_rx) =
                 tokio::sync::mpsc::unbounded_channel();
             let mut events_tx = events_tx.wait();
-            let (quit_tx, mut quit_rx) = tokio::sync::oneshot::channel();
-            // TODO: this is pretty janky - it'd be better to build in more
-            // useful support to crossterm directly
-            std::thread::Builder::new()
+            let (quit_rx, quit_tx) = nix::unistd::pipe().context(QuitPipe)?;
+
+            // crossterm doesn't know anything about bracketed paste, so we
+            // have to read and decode terminal input ourselves
+            let thread = std::thread::Builder::new()
                 .spawn(move || {
-                    for event in reader {
-                        // unwrap is unpleasant, but so is figuring out how to
-                        // propagate the error back to the main thread
-                        events_tx.send(event).unwrap();
-                        task.notify();
-                        if quit_rx.try_recv().is_ok() {
-                            break;
+                    let mut decoder = Decoder::new();
+                    let mut buf = [0_u8; 4096];
+                    loop {
+                        let mut fds = [
+                            nix::poll::PollFd::new(
+                                STDIN,
+                                nix::poll::PollFlags::POLLIN,
+                            ),
+                            nix::poll::PollFd::new(
+                                quit_rx,
+                                nix::poll::PollFlags::POLLIN,
+                            ),
+                        ];
+                        let timeout = if decoder.is_pending() {
+                            ESCAPE_TIMEOUT_MS
+                        } else {
+                            -1
+                        };
+                        let events = match nix::poll::poll(&mut fds, timeout)
+                        {
+                            Ok(0) => decoder.flush(),
+                            Ok(_) => {
+                                if fds[1]
+                                    .revents()
+                                    .is_some_and(|r| !r.is_empty())
+                                {
+                                    break;
+                                }
+                                match nix::unistd::read(STDIN, &mut buf) {
+                                    Ok(0) => break,
+                                    Ok(n) => decoder.feed(&buf[..n]),
+                                    Err(nix::Error::Sys(
+                                        nix::errno::Errno::EINTR,
+                                    )) => continue,
+                                    Err(_) => break,
+                                }
+                            }
+                            Err(nix::Error::Sys(
+                                nix::errno::Errno::EINTR,
+                            )) => continue,
+                            Err(_) => break,
+                        };
+                        for event in events {
+                            // unwrap is unpleasant, but so is figuring out
+                            // how to propagate the error back to the main
+                            // thread
+                            events_tx.send(event).unwrap();
                         }
+                        task.notify();
                     }
+                    let _ = nix::unistd::close(quit_rx);
                 })
                 .context(TerminalInputReadingThread)?;
 
             self.events = Some(events_rx);
             self.quit = Some(quit_tx);
+            self.thread = Some(thread);
+
+            set_bracketed_paste(true).context(WriteToTerminal)?;
         }
 
         self.events.as_mut().unwrap().poll().context(ReadChannel)
@@ -70,10 +140,353 @@ impl futures::stream::Stream for KeyReader {
 impl Drop for KeyReader {
     fn drop(&mut self) {
         if let Some(quit_tx) = self.quit.take() {
-            // don't care if it fails to send, this can happen if the thread
-            // terminates due to seeing a newline before the keyreader goes
-            // out of scope
-            let _ = quit_tx.send(());
+            // don't care if these fail, there's nothing useful we can do
+            // about it at this point
+            let _ = set_bracketed_paste(false);
+            let _ = nix::unistd::write(quit_tx, b"q");
+            let _ = nix::unistd::close(quit_tx);
+        }
+        // wait for the thread to actually stop reading, so that it can't
+        // steal input intended for whatever runs next
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn set_bracketed_paste(enabled: bool) -> std::io::Result<()> {
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    stdout.write_all(if enabled {
+        b"\x1b[?2004h"
+    } else {
+        b"\x1b[?2004l"
+    })?;
+    stdout.flush()
+}
+
+enum Decoded {
+    Event(Event, usize),
+    PasteStart(usize),
+    Skip(usize),
+    Incomplete,
+}
+
+struct Decoder {
+    buf: Vec<u8>,
+    paste: Option<Vec<u8>>,
+}
+
+impl Decoder {
+    fn new() -> Self {
+        Self {
+            buf: vec![],
+            paste: None,
+        }
+    }
+
+    fn is_pending(&self) -> bool {
+        self.paste.is_none() && !self.buf.is_empty()
+    }
+
+    fn feed(&mut self, bytes: &[u8]) -> Vec<Event> {
+        self.buf.extend_from_slice(bytes);
+        self.decode(false)
+    }
+
+    fn flush(&mut self) -> Vec<Event> {
+        self.decode(true)
+    }
+
+    fn decode(&mut self, flush: bool) -> Vec<Event> {
+        let mut events = vec![];
+        loop {
+            if let Some(paste) = &mut self.paste {
+                if let Some(idx) = find(&self.buf, PASTE_END) {
+                    paste.extend(self.buf.drain(..idx));
+                    self.buf.drain(..PASTE_END.len());
+                    let text = String::from_utf8_lossy(paste)
+                        .replace("\r\n", "\n")
+                        .replace('\r', "\n");
+                    events.push(Event::Paste(text));
+                    self.paste = None;
+                    continue;
+                }
+                // hold back enough bytes to recognize a terminator that was
+                // split across reads
+                let keep = self.buf.len().min(PASTE_END.len() - 1);
+                paste.extend(self.buf.drain(..self.buf.len() - keep));
+                break;
+            }
+
+            if self.buf.is_empty() {
+                break;
+            }
+
+            match decode_key(&self.buf) {
+                Decoded::Event(event, len) => {
+                    events.push(event);
+                    self.buf.drain(..len);
+                }
+                Decoded::PasteStart(len) => {
+                    self.buf.drain(..len);
+                    self.paste = Some(vec![]);
+                }
+                Decoded::Skip(len) => {
+                    self.buf.drain(..len);
+                }
+                Decoded::Incomplete => {
+                    if !flush {
+                        break;
+                    }
+                    // nothing else showed up, so a leading escape was
+                    // actually just the escape key
+                    if self.buf[0] == b'\x1b' {
+                        events.push(Event::Key(
+                            crossterm::input::KeyEvent::Esc,
+                        ));
+                    }
+                    self.buf.drain(..1);
+                }
+            }
+        }
+        events
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn key(event: crossterm::input::KeyEvent, len: usize) -> Decoded {
+    Decoded::Event(Event::Key(event), len)
+}
+
+fn decode_key(buf: &[u8]) -> Decoded {
+    use crossterm::input::KeyEvent;
+
+    match buf[0] {
+        b'\x1b' => decode_escape(buf),
+        b'\r' | b'\n' => key(KeyEvent::Enter, 1),
+        b'\t' => key(KeyEvent::Tab, 1),
+        b'\x7f' => key(KeyEvent::Backspace, 1),
+        c @ b'\x01'..=b'\x1a' => {
+            key(KeyEvent::Ctrl(char::from(c - 0x01 + b'a')), 1)
+        }
+        c @ b'\x1c'..=b'\x1f' => {
+            key(KeyEvent::Ctrl(char::from(c - 0x1c + b'4')), 1)
         }
+        b'\0' => key(KeyEvent::Null, 1),
+        _ => match decode_char(buf) {
+            Some(Ok((c, len))) => key(KeyEvent::Char(c), len),
+            Some(Err(len)) => Decoded::Skip(len),
+            None => Decoded::Incomplete,
+        },
+    }
+}
+
+fn decode_escape(buf: &[u8]) -> Decoded {
+    use crossterm::input::KeyEvent;
+
+    if buf.len() < 2 {
+        return Decoded::Incomplete;
+    }
+
+    match buf[1] {
+        b'[' => decode_csi(buf),
+        b'O' => match buf.get(2) {
+            Some(c @ b'P'..=b'S') => key(KeyEvent::F(1 + c - b'P'), 3),
+            Some(b'A') => key(KeyEvent::Up, 3),
+            Some(b'B') => key(KeyEvent::Down, 3),
+            Some(b'C') => key(KeyEvent::Right, 3),
+            Some(b'D') => key(KeyEvent::Left, 3),
+            Some(b'H') => key(KeyEvent::Home, 3),
+            Some(b'F') => key(KeyEvent::End, 3),
+            Some(_) => Decoded::Skip(3),
+            None => Decoded::Incomplete,
+        },
+        b'\x1b' => key(KeyEvent::Esc, 1),
+        _ => match decode_char(&buf[1..]) {
+            Some(Ok((c, len))) => key(KeyEvent::Alt(c), len + 1),
+            Some(Err(len)) => Decoded::Skip(len + 1),
+            None => Decoded::Incomplete,
+        },
+    }
+}
+
+fn decode_csi(buf: &[u8]) -> Decoded {
+    use crossterm::input::KeyEvent;
+
+    let end = if let Some(end) =
+        buf[2..].iter().position(|c| (0x40..=0x7e).contains(c))
+    {
+        end + 2
+    } else {
+        return Decoded::Incomplete;
+    };
+    let len = end + 1;
+    if buf[..len] == *PASTE_START {
+        return Decoded::PasteStart(len);
+    }
+
+    let params: Vec<_> = std::str::from_utf8(&buf[2..end])
+        .unwrap_or("")
+        .split(';')
+        .map(|param| param.parse::<u8>().ok())
+        .collect();
+    let event = match (buf[end], params.as_slice()) {
+        (b'A', [None]) => KeyEvent::Up,
+        (b'B', [None]) => KeyEvent::Down,
+        (b'C', [None]) => KeyEvent::Right,
+        (b'D', [None]) => KeyEvent::Left,
+        (b'H', [None]) => KeyEvent::Home,
+        (b'F', [None]) => KeyEvent::End,
+        (b'Z', [None]) => KeyEvent::BackTab,
+        (b'A', [Some(1), Some(5)]) => KeyEvent::CtrlUp,
+        (b'B', [Some(1), Some(5)]) => KeyEvent::CtrlDown,
+        (b'C', [Some(1), Some(5)]) => KeyEvent::CtrlRight,
+        (b'D', [Some(1), Some(5)]) => KeyEvent::CtrlLeft,
+        (b'A', [Some(1), Some(2)]) => KeyEvent::ShiftUp,
+        (b'B', [Some(1), Some(2)]) => KeyEvent::ShiftDown,
+        (b'C', [Some(1), Some(2)]) => KeyEvent::ShiftRight,
+        (b'D', [Some(1), Some(2)]) => KeyEvent::ShiftLeft,
+        (b'~', [Some(n)]) => match *n {
+            1 | 7 => KeyEvent::Home,
+            2 => KeyEvent::Insert,
+            3 => KeyEvent::Delete,
+            4 | 8 => KeyEvent::End,
+            5 => KeyEvent::PageUp,
+            6 => KeyEvent::PageDown,
+            11..=15 => KeyEvent::F(n - 10),
+            17..=21 => KeyEvent::F(n - 11),
+            23..=24 => KeyEvent::F(n - 12),
+            _ => return Decoded::Skip(len),
+        },
+        _ => return Decoded::Skip(len),
+    };
+    key(event, len)
+}
+
+// returns None if more bytes are needed, and Err with the number of bytes
+// to skip if the input isn't valid utf8
+fn decode_char(
+    buf: &[u8],
+) -> Option<std::result::Result<(char, usize), usize>> {
+    let len = match buf[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Some(Err(1)),
+    };
+    if buf.len() < len {
+        return None;
+    }
+    Some(
+        std::str::from_utf8(&buf[..len])
+            .ok()
+            .and_then(|s| s.chars().next())
+            .map(|c| (c, len))
+            .ok_or(1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::input::KeyEvent;
+
+    fn keys(events: &[KeyEvent]) -> Vec<Event> {
+        events.iter().copied().map(Event::Key).collect()
+    }
+
+    #[test]
+    fn bracketed_paste() {
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            decoder.feed(b"a\x1b[200~echo 'x'\r\nls\x1b[201~b"),
+            vec![
+                Event::Key(KeyEvent::Char('a')),
+                Event::Paste("echo 'x'\nls".to_string()),
+                Event::Key(KeyEvent::Char('b')),
+            ]
+        );
+        assert!(!decoder.is_pending());
+
+        // control characters and escapes inside a paste are just text
+        assert_eq!(
+            decoder.feed(b"\x1b[200~\t\x1b[A\x03\x1b[201~"),
+            vec![Event::Paste("\t\x1b[A\x03".to_string())]
+        );
+    }
+
+    #[test]
+    fn split_bracketed_paste() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed(b"\x1b[20"), vec![]);
+        assert_eq!(decoder.feed(b"0~some "), vec![]);
+        // the paste isn't over yet, so escapes don't time out
+        assert!(!decoder.is_pending());
+        assert_eq!(decoder.flush(), vec![]);
+        assert_eq!(decoder.feed(b"text\x1b[2"), vec![]);
+        assert_eq!(
+            decoder.feed(b"01~\r"),
+            vec![
+                Event::Paste("some text".to_string()),
+                Event::Key(KeyEvent::Enter),
+            ]
+        );
+    }
+
+    #[test]
+    fn lone_escape() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed(b"\x1b"), vec![]);
+        assert!(decoder.is_pending());
+        assert_eq!(decoder.flush(), keys(&[KeyEvent::Esc]));
+        assert!(!decoder.is_pending());
+
+        // an escape followed by something which isn't the rest of a
+        // sequence is alt
+        assert_eq!(decoder.feed(b"\x1bf"), keys(&[KeyEvent::Alt('f')]));
+        // the second escape might still start a sequence
+        assert_eq!(decoder.feed(b"\x1b\x1b"), keys(&[KeyEvent::Esc]));
+        assert_eq!(decoder.flush(), keys(&[KeyEvent::Esc]));
+    }
+
+    #[test]
+    fn split_escape_sequences() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed(b"\x1b"), vec![]);
+        assert_eq!(decoder.feed(b"["), vec![]);
+        assert_eq!(decoder.feed(b"A"), keys(&[KeyEvent::Up]));
+
+        assert_eq!(decoder.feed(b"\x1b[1;"), vec![]);
+        assert_eq!(decoder.feed(b"5C\x1bO"), keys(&[KeyEvent::CtrlRight]));
+        assert_eq!(decoder.feed(b"P"), keys(&[KeyEvent::F(1)]));
+
+        assert_eq!(decoder.feed(b"\x1b[3"), vec![]);
+        assert_eq!(
+            decoder.feed(b"~x"),
+            keys(&[KeyEvent::Delete, KeyEvent::Char('x')])
+        );
+
+        // multibyte characters can be split too
+        assert_eq!(decoder.feed("é".as_bytes()[..1].as_ref()), vec![]);
+        assert_eq!(
+            decoder.feed("é".as_bytes()[1..].as_ref()),
+            keys(&[KeyEvent::Char('é')])
+        );
+    }
+
+    #[test]
+    fn unknown_sequences() {
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            decoder.feed(b"\x1b[99~\x1b[1;9Xa\xffb"),
+            keys(&[KeyEvent::Char('a'), KeyEvent::Char('b')])
+        );
     }
 }
@@ -0,0 +1,459 @@
+use crossterm::input::KeyEvent;
+
+#[derive(Debug, snafu::Snafu)]
+pub enum Error {
+    #[snafu(display("invalid key name '{}'", key))]
+    InvalidKey { key: String },
+
+    #[snafu(display("unknown readline action '{}'", action))]
+    UnknownAction { action: String },
+
+    #[snafu(display("no key sequence given"))]
+    EmptyKeySequence,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    AcceptLine,
+    BackwardChar,
+    BackwardDeleteChar,
+    BackwardKillWord,
+    BeginningOfLine,
+    CancelLine,
+    ClearScreen,
+    Complete,
+    CycleHistoryFilter,
+    DeleteChar,
+    DeleteCharOrEof,
+    EditAndExecuteCommand,
+    EditCommandLine,
+    EndOfLine,
+    ForwardChar,
+    ForwardSearchHistory,
+    FuzzyFindDirectory,
+    FuzzyFindFile,
+    FuzzyFindHistory,
+    HistorySearchBackward,
+    HistorySearchForward,
+    KillLine,
+    NextHistory,
+    PreviousHistory,
+    ReverseSearchHistory,
+    UnixLineDiscard,
+}
+
+impl Action {
+    pub const ALL: &'static [Self] = &[
+        Self::AcceptLine,
+        Self::BackwardChar,
+        Self::BackwardDeleteChar,
+        Self::BackwardKillWord,
+        Self::BeginningOfLine,
+        Self::CancelLine,
+        Self::ClearScreen,
+        Self::Complete,
+        Self::CycleHistoryFilter,
+        Self::DeleteChar,
+        Self::DeleteCharOrEof,
+        Self::EditAndExecuteCommand,
+        Self::EditCommandLine,
+        Self::EndOfLine,
+        Self::ForwardChar,
+        Self::ForwardSearchHistory,
+        Self::FuzzyFindDirectory,
+        Self::FuzzyFindFile,
+        Self::FuzzyFindHistory,
+        Self::HistorySearchBackward,
+        Self::HistorySearchForward,
+        Self::KillLine,
+        Self::NextHistory,
+        Self::PreviousHistory,
+        Self::ReverseSearchHistory,
+        Self::UnixLineDiscard,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::AcceptLine => "accept-line",
+            Self::BackwardChar => "backward-char",
+            Self::BackwardDeleteChar => "backward-delete-char",
+            Self::BackwardKillWord => "backward-kill-word",
+            Self::BeginningOfLine => "beginning-of-line",
+            Self::CancelLine => "cancel-line",
+            Self::ClearScreen => "clear-screen",
+            Self::Complete => "complete",
+            Self::CycleHistoryFilter => "cycle-history-filter",
+            Self::DeleteChar => "delete-char",
+            Self::DeleteCharOrEof => "delete-char-or-eof",
+            Self::EditAndExecuteCommand => "edit-and-execute-command",
+            Self::EditCommandLine => "edit-command-line",
+            Self::EndOfLine => "end-of-line",
+            Self::ForwardChar => "forward-char",
+            Self::ForwardSearchHistory => "forward-search-history",
+            Self::FuzzyFindDirectory => "fuzzy-find-directory",
+            Self::FuzzyFindFile => "fuzzy-find-file",
+            Self::FuzzyFindHistory => "fuzzy-find-history",
+            Self::HistorySearchBackward => "history-search-backward",
+            Self::HistorySearchForward => "history-search-forward",
+            Self::KillLine => "kill-line",
+            Self::NextHistory => "next-history",
+            Self::PreviousHistory => "previous-history",
+            Self::ReverseSearchHistory => "reverse-search-history",
+            Self::UnixLineDiscard => "unix-line-discard",
+        }
+    }
+}
+
+impl std::str::FromStr for Action {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name() == s)
+            .ok_or_else(|| Error::UnknownAction {
+                action: s.to_string(),
+            })
+    }
+}
+
+pub enum Lookup {
+    Action(Action),
+    Prefix,
+    Unbound,
+}
+
+pub struct Keymap {
+    bindings: std::collections::HashMap<Vec<KeyEvent>, Action>,
+}
+
+impl Keymap {
+    fn new() -> Self {
+        Self {
+            bindings: std::collections::HashMap::new(),
+        }
+    }
+
+    fn default_bindings() -> Self {
+        let mut keymap = Self::new();
+        for (key, action) in &[
+            (KeyEvent::Enter, Action::AcceptLine),
+            (KeyEvent::Ctrl('a'), Action::BeginningOfLine),
+            (KeyEvent::Home, Action::BeginningOfLine),
+            (KeyEvent::Ctrl('e'), Action::EndOfLine),
+            (KeyEvent::End, Action::EndOfLine),
+            (KeyEvent::Ctrl('b'), Action::BackwardChar),
+            (KeyEvent::Left, Action::BackwardChar),
+            (KeyEvent::Ctrl('f'), Action::ForwardChar),
+            (KeyEvent::Right, Action::ForwardChar),
+            (KeyEvent::Backspace, Action::BackwardDeleteChar),
+            (KeyEvent::Ctrl('h'), Action::BackwardDeleteChar),
+            (KeyEvent::Delete, Action::DeleteChar),
+            (KeyEvent::Ctrl('d'), Action::DeleteCharOrEof),
+            (KeyEvent::Ctrl('k'), Action::KillLine),
+            (KeyEvent::Ctrl('u'), Action::UnixLineDiscard),
+            (KeyEvent::Ctrl('w'), Action::BackwardKillWord),
+            (KeyEvent::Ctrl('c'), Action::CancelLine),
+            (KeyEvent::Ctrl('l'), Action::ClearScreen),
+            (KeyEvent::Tab, Action::Complete),
+            (KeyEvent::Up, Action::PreviousHistory),
+            (KeyEvent::Ctrl('p'), Action::PreviousHistory),
+            (KeyEvent::Down, Action::NextHistory),
+            (KeyEvent::Ctrl('n'), Action::NextHistory),
+            (KeyEvent::Ctrl('r'), Action::FuzzyFindHistory),
+            (KeyEvent::Ctrl('s'), Action::ForwardSearchHistory),
+            (KeyEvent::Ctrl('t'), Action::FuzzyFindFile),
+            (KeyEvent::Alt('c'), Action::FuzzyFindDirectory),
+            (KeyEvent::Alt('r'), Action::CycleHistoryFilter),
+            (KeyEvent::Alt('p'), Action::HistorySearchBackward),
+            (KeyEvent::Alt('n'), Action::HistorySearchForward),
+        ] {
+            keymap.bindings.insert(vec![*key], *action);
+        }
+        keymap.bindings.insert(
+            vec![KeyEvent::Ctrl('x'), KeyEvent::Ctrl('e')],
+            Action::EditAndExecuteCommand,
+        );
+        keymap
+    }
+
+    pub fn lookup(&self, keys: &[KeyEvent]) -> Lookup {
+        self.bindings.get(keys).map_or_else(
+            || {
+                if self.bindings.keys().any(|bound| {
+                    bound.len() > keys.len() && bound.starts_with(keys)
+                }) {
+                    Lookup::Prefix
+                } else {
+                    Lookup::Unbound
+                }
+            },
+            |action| Lookup::Action(*action),
+        )
+    }
+}
+
+static KEYMAP: std::sync::LazyLock<std::sync::Mutex<Keymap>> =
+    std::sync::LazyLock::new(|| {
+        std::sync::Mutex::new(Keymap::default_bindings())
+    });
+
+fn keymap() -> std::sync::MutexGuard<'static, Keymap> {
+    KEYMAP.lock().unwrap()
+}
+
+pub fn lookup(keys: &[KeyEvent]) -> Lookup {
+    keymap().lookup(keys)
+}
+
+pub fn bind(keys: Vec<KeyEvent>, action: Action) -> Result<()> {
+    snafu::ensure!(!keys.is_empty(), EmptyKeySequence);
+    keymap().bindings.insert(keys, action);
+    Ok(())
+}
+
+pub fn unbind(keys: &[KeyEvent]) -> bool {
+    keymap().bindings.remove(keys).is_some()
+}
+
+// sorted by action name, so that listings are stable
+pub fn bindings() -> Vec<(Vec<KeyEvent>, Action)> {
+    let mut bindings: Vec<_> = keymap()
+        .bindings
+        .iter()
+        .map(|(keys, action)| (keys.clone(), *action))
+        .collect();
+    bindings.sort_by(|(keys_a, action_a), (keys_b, action_b)| {
+        action_a
+            .name()
+            .cmp(action_b.name())
+            .then_with(|| format_keys(keys_a).cmp(&format_keys(keys_b)))
+    });
+    bindings
+}
+
+pub fn parse_keys(keys: &[String]) -> Result<Vec<KeyEvent>> {
+    keys.iter().map(|key| parse_key(key)).collect()
+}
+
+pub fn parse_key(key: &str) -> Result<KeyEvent> {
+    let invalid = || Error::InvalidKey {
+        key: key.to_string(),
+    };
+    let single_char = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+
+    let event = match key {
+        "Enter" => KeyEvent::Enter,
+        "Tab" => KeyEvent::Tab,
+        "BackTab" => KeyEvent::BackTab,
+        "Backspace" => KeyEvent::Backspace,
+        "Delete" => KeyEvent::Delete,
+        "Insert" => KeyEvent::Insert,
+        "Esc" => KeyEvent::Esc,
+        "Space" => KeyEvent::Char(' '),
+        "C-@" => KeyEvent::Null,
+        "Up" => KeyEvent::Up,
+        "Down" => KeyEvent::Down,
+        "Left" => KeyEvent::Left,
+        "Right" => KeyEvent::Right,
+        "Home" => KeyEvent::Home,
+        "End" => KeyEvent::End,
+        "PageUp" => KeyEvent::PageUp,
+        "PageDown" => KeyEvent::PageDown,
+        "C-Up" => KeyEvent::CtrlUp,
+        "C-Down" => KeyEvent::CtrlDown,
+        "C-Left" => KeyEvent::CtrlLeft,
+        "C-Right" => KeyEvent::CtrlRight,
+        "S-Up" => KeyEvent::ShiftUp,
+        "S-Down" => KeyEvent::ShiftDown,
+        "S-Left" => KeyEvent::ShiftLeft,
+        "S-Right" => KeyEvent::ShiftRight,
+        _ => {
+            if let Some(c) = key.strip_prefix("C-").and_then(single_char) {
+                KeyEvent::Ctrl(c.to_ascii_lowercase())
+            } else if let Some(c) =
+                key.strip_prefix("M-").and_then(single_char)
+            {
+                KeyEvent::Alt(c)
+            } else if let Some(n) = key
+                .strip_prefix('F')
+                .and_then(|n| n.parse::<u8>().ok())
+                .filter(|n| (1..=12).contains(n))
+            {
+                KeyEvent::F(n)
+            } else if let Some(c) = single_char(key) {
+                KeyEvent::Char(c)
+            } else {
+                return Err(invalid());
+            }
+        }
+    };
+    Ok(event)
+}
+
+pub fn format_keys(keys: &[KeyEvent]) -> String {
+    keys.iter()
+        .map(|key| format_key(*key))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn format_key(key: KeyEvent) -> String {
+    match key {
+        KeyEvent::Enter => "Enter".to_string(),
+        KeyEvent::Tab => "Tab".to_string(),
+        KeyEvent::BackTab => "BackTab".to_string(),
+        KeyEvent::Backspace => "Backspace".to_string(),
+        KeyEvent::Delete => "Delete".to_string(),
+        KeyEvent::Insert => "Insert".to_string(),
+        KeyEvent::Esc => "Esc".to_string(),
+        KeyEvent::Char(' ') => "Space".to_string(),
+        KeyEvent::Up => "Up".to_string(),
+        KeyEvent::Down => "Down".to_string(),
+        KeyEvent::Left => "Left".to_string(),
+        KeyEvent::Right => "Right".to_string(),
+        KeyEvent::Home => "Home".to_string(),
+        KeyEvent::End => "End".to_string(),
+        KeyEvent::PageUp => "PageUp".to_string(),
+        KeyEvent::PageDown => "PageDown".to_string(),
+        KeyEvent::CtrlUp => "C-Up".to_string(),
+        KeyEvent::CtrlDown => "C-Down".to_string(),
+        KeyEvent::CtrlLeft => "C-Left".to_string(),
+        KeyEvent::CtrlRight => "C-Right".to_string(),
+        KeyEvent::ShiftUp => "S-Up".to_string(),
+        KeyEvent::ShiftDown => "S-Down".to_string(),
+        KeyEvent::ShiftLeft => "S-Left".to_string(),
+        KeyEvent::ShiftRight => "S-Right".to_string(),
+        KeyEvent::F(n) => format!("F{n}"),
+        KeyEvent::Ctrl(c) => format!("C-{c}"),
+        KeyEvent::Alt(c) => format!("M-{c}"),
+        KeyEvent::Char(c) => c.to_string(),
+        KeyEvent::Null => "C-@".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::Future as _;
+    use futures::stream::Stream as _;
+
+    fn keys(keys: &str) -> Vec<KeyEvent> {
+        let keys: Vec<_> =
+            keys.split_whitespace().map(ToString::to_string).collect();
+        parse_keys(&keys).unwrap()
+    }
+
+    // runs the bind builtin, returning its output
+    fn bind_builtin(args: &[&str]) -> String {
+        let args: Vec<_> = args.iter().map(ToString::to_string).collect();
+        let events = crate::builtins::run("bind", &args)
+            .unwrap()
+            .collect()
+            .wait()
+            .unwrap();
+        let mut output = vec![];
+        for event in events {
+            if let tokio_pty_process_stream::Event::Output { data } = event {
+                output.extend(data);
+            }
+        }
+        String::from_utf8(output).unwrap().replace("\r\n", "\n")
+    }
+
+    #[test]
+    fn parse_and_format_keys() {
+        assert_eq!(
+            keys("C-x C-e"),
+            vec![KeyEvent::Ctrl('x'), KeyEvent::Ctrl('e')]
+        );
+        assert_eq!(
+            keys("M-x Space F12 C-Left x C-@"),
+            vec![
+                KeyEvent::Alt('x'),
+                KeyEvent::Char(' '),
+                KeyEvent::F(12),
+                KeyEvent::CtrlLeft,
+                KeyEvent::Char('x'),
+                KeyEvent::Null,
+            ]
+        );
+        // control keys don't have a case
+        assert_eq!(parse_key("C-A").unwrap(), KeyEvent::Ctrl('a'));
+        for key in &["", "C-", "C-ab", "M-", "F0", "F13", "Fx", "xy", "Ctrl"]
+        {
+            assert!(parse_key(key).is_err(), "{}", key);
+        }
+        for key in &["C-x C-e", "M-. Tab", "Esc Enter S-Up F1 é"] {
+            assert_eq!(format_keys(&keys(key)), *key);
+        }
+    }
+
+    #[test]
+    fn parse_actions() {
+        for action in Action::ALL {
+            assert_eq!(action.name().parse::<Action>().unwrap(), *action);
+        }
+        assert!("accept_line".parse::<Action>().is_err());
+        assert!("".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn lookup_sequences() {
+        let mut keymap = Keymap::default_bindings();
+        assert!(matches!(keymap.lookup(&keys("C-x")), Lookup::Prefix));
+        assert!(matches!(
+            keymap.lookup(&keys("C-x C-e")),
+            Lookup::Action(Action::EditAndExecuteCommand)
+        ));
+        assert!(matches!(keymap.lookup(&keys("C-x x")), Lookup::Unbound));
+        assert!(matches!(
+            keymap.lookup(&keys("C-a")),
+            Lookup::Action(Action::BeginningOfLine)
+        ));
+
+        // a key can be both bound and the start of a longer sequence, in
+        // which case the shorter binding wins
+        keymap.bindings.insert(keys("C-a C-a"), Action::EndOfLine);
+        assert!(matches!(
+            keymap.lookup(&keys("C-a")),
+            Lookup::Action(Action::BeginningOfLine)
+        ));
+        assert!(matches!(keymap.lookup(&keys("M-z M-z")), Lookup::Unbound));
+    }
+
+    #[test]
+    fn bind_builtin_parsing() {
+        assert_eq!(bind_builtin(&["C-g", "C-g", "kill-line"]), "");
+        assert!(matches!(lookup(&keys("C-g")), Lookup::Prefix));
+        assert!(bind_builtin(&["-p"]).contains("bind C-g C-g kill-line\n"));
+        assert_eq!(bind_builtin(&["-r", "C-g", "C-g"]), "");
+        assert!(matches!(lookup(&keys("C-g")), Lookup::Unbound));
+
+        assert_eq!(
+            bind_builtin(&["C-g", "no-such-action"]),
+            "failed to bind: unknown readline action 'no-such-action'\n"
+        );
+        assert_eq!(
+            bind_builtin(&["Ctrl-g", "kill-line"]),
+            "failed to bind: invalid key name 'Ctrl-g'\n"
+        );
+        assert_eq!(
+            bind_builtin(&["-r", "C-g"]),
+            "failed to unbind C-g: no such binding\n"
+        );
+        assert!(bind_builtin(&["kill-line"]).contains("usage: bind "));
+        assert!(bind_builtin(&["-l"])
+            .lines()
+            .any(|line| line == "kill-line"));
+    }
+}
@@ -14,12 +14,26 @@
 #![allow(clippy::too_many_lines)]
 #![allow(clippy::write_with_newline)]
 
+mod aliases;
+mod arith;
 mod async_stdin;
 mod builtins;
+mod completion;
+mod env;
 mod eval;
+mod executables;
+mod finder;
+mod frecency;
+mod history;
 mod key_reader;
-mod parser;
+mod keybindings;
+mod pattern;
+mod process;
+mod rc;
 mod readline;
+mod resolver;
 
+pub mod ast;
+pub mod parser;
 pub mod repl;
 pub mod tui;
@@ -1,18 +1,1248 @@
-use snafu::OptionExt as _;
-
 #[derive(Debug, snafu::Snafu)]
 pub enum Error {
-    #[snafu(display("No command given"))]
-    CommandRequired,
+    #[snafu(display("unterminated {} quote", quote))]
+    UnterminatedQuote { quote: char, location: Location },
+
+    #[snafu(display("unexpected end of input, expected {}", expected))]
+    UnexpectedEof {
+        expected: String,
+        location: Location,
+    },
+
+    #[snafu(display("unexpected `{}`", token))]
+    UnexpectedToken { token: String, location: Location },
+
+    #[snafu(display("bad substitution `{}`", text))]
+    BadSubstitution { text: String, location: Location },
+
+    #[snafu(display("background jobs are not supported"))]
+    Background { location: Location },
+
+    #[snafu(display(
+        "unterminated here-document, expected `{}`",
+        delimiter
+    ))]
+    UnterminatedHeredoc {
+        delimiter: String,
+        location: Location,
+    },
+}
+
+// where in the input an error was found. line and col are 1-based, and col
+// counts characters rather than bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub col: usize,
+    pub span: crate::ast::Span,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.col)
+    }
+}
+
+impl Error {
+    #[must_use]
+    pub fn location(&self) -> Location {
+        match self {
+            Self::UnterminatedQuote { location, .. }
+            | Self::UnexpectedEof { location, .. }
+            | Self::UnexpectedToken { location, .. }
+            | Self::BadSubstitution { location, .. }
+            | Self::Background { location }
+            | Self::UnterminatedHeredoc { location, .. } => *location,
+        }
+    }
+
+    // suggestions for how to fix common mistakes
+    #[must_use]
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Self::UnterminatedQuote { quote, .. } => Some(format!(
+                "add a closing {quote} to end the quote which starts here"
+            )),
+            Self::UnexpectedEof { expected, .. } => {
+                let opener = match expected.as_str() {
+                    "`fi`" => "an `if`",
+                    "`done`" => "a loop",
+                    "`esac`" => "a `case`",
+                    "`}`" => "a `{`",
+                    "`)`" => "a `(`",
+                    "`))`" => "an arithmetic expression",
+                    "`then`" => "an `if` condition",
+                    "`do`" => "a loop condition",
+                    _ => return None,
+                };
+                Some(format!("{opener} was never closed with {expected}"))
+            }
+            Self::UnexpectedToken { token, .. } => match token.as_str() {
+                "fi" | "then" | "elif" | "else" => Some(format!(
+                    "`{token}` without a matching `if`, or a missing `;` or \
+                     newline before it"
+                )),
+                "do" | "done" => Some(format!(
+                    "`{token}` without a matching `while`, `until`, or `for`"
+                )),
+                "esac" => {
+                    Some("`esac` without a matching `case`".to_string())
+                }
+                "}" => Some("`}` without a matching `{`".to_string()),
+                ")" => Some("`)` without a matching `(`".to_string()),
+                ";;" => Some("`;;` can only be used in a `case`".to_string()),
+                _ => None,
+            },
+            Self::BadSubstitution { .. } => Some(
+                "parameter names can only contain letters, digits, and \
+                 underscores"
+                    .to_string(),
+            ),
+            Self::Background { .. } => {
+                Some("remove the `&` to run the command normally".to_string())
+            }
+            Self::UnterminatedHeredoc { delimiter, .. } => Some(format!(
+                "end the here-document with a line containing only \
+                 `{delimiter}`"
+            )),
+        }
+    }
+
+    // formats the error along with the line of input it was found on, in
+    // the style of compiler diagnostics
+    #[must_use]
+    pub fn report(&self, name: &str, input: &str) -> String {
+        let location = self.location();
+        let line = input.split('\n').nth(location.line - 1).unwrap_or("");
+        let number = location.line.to_string();
+        let gutter = " ".repeat(number.len());
+        // keep tabs so that the caret lines up with the text above it
+        let indent: String = line
+            .chars()
+            .take(location.col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let len = input
+            .get(location.span.start..location.span.end)
+            .and_then(|text| text.split('\n').next())
+            .map_or(0, |text| text.chars().count());
+        let carets = "^".repeat(std::cmp::max(len, 1));
+        let hint = self
+            .hint()
+            .map(|hint| format!("{gutter} = hint: {hint}\n"))
+            .unwrap_or_default();
+        format!(
+            "{name}:{number}:{}: error: {self}\n\
+             {gutter} |\n\
+             {number} | {line}\n\
+             {gutter} | {indent}{carets}\n\
+             {hint}",
+            location.col,
+        )
+    }
+
+    // makes the location of an error in a nested piece of input (like the
+    // contents of backticks) relative to the enclosing input instead
+    fn offset(
+        mut self,
+        base_pos: usize,
+        base_line: usize,
+        base_col: usize,
+    ) -> Self {
+        let location = match &mut self {
+            Self::UnterminatedQuote { location, .. }
+            | Self::UnexpectedEof { location, .. }
+            | Self::UnexpectedToken { location, .. }
+            | Self::BadSubstitution { location, .. }
+            | Self::Background { location }
+            | Self::UnterminatedHeredoc { location, .. } => location,
+        };
+        if location.line == 1 {
+            location.col += base_col - 1;
+        }
+        location.line += base_line - 1;
+        location.span.start += base_pos;
+        location.span.end += base_pos;
+        self
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-pub fn parse(line: &str) -> Result<(String, Vec<String>)> {
-    // TODO
-    let mut tokens = line
-        .split_whitespace()
-        .map(std::string::ToString::to_string);
-    let cmd = tokens.next().context(CommandRequired)?;
-    Ok((cmd, tokens.collect()))
+// words which are only special when they appear unquoted in command position
+const RESERVED_WORDS: &[&str] = &[
+    "!", "{", "}", "case", "do", "done", "elif", "else", "esac", "fi", "for",
+    "if", "then", "until", "while",
+];
+
+// reserved words which start a compound command
+const COMPOUND_COMMANDS: &[&str] =
+    &["{", "case", "for", "if", "until", "while"];
+
+// characters which end a word when unquoted
+const METACHARACTERS: &str = ";&|()<>";
+
+// the library only exists so that the binary can share it, so its public
+// functions aren't documented
+#[allow(clippy::missing_errors_doc)]
+pub fn parse(input: &str) -> Result<crate::ast::Program> {
+    Parser::new(input, 0).complete()
+}
+
+// parses the whole input, reporting as many errors as possible rather than
+// stopping at the first one. after an error, parsing starts again at the
+// next line.
+#[must_use]
+pub fn check(input: &str) -> Vec<Error> {
+    let mut errors = vec![];
+    let mut pos = 0;
+    while let Err(e) = Parser::new(input, pos).complete() {
+        let start = e.location().span.start;
+        // these errors mean that everything after them was consumed, so
+        // there is nothing sensible to resume from
+        let fatal = match e {
+            Error::UnterminatedQuote { .. }
+            | Error::UnterminatedHeredoc { .. } => true,
+            Error::UnexpectedEof { .. } => start >= input.len(),
+            _ => false,
+        };
+        errors.push(e);
+        if fatal {
+            break;
+        }
+        match input[start..].find('\n') {
+            Some(idx) if start + idx + 1 > pos => pos = start + idx + 1,
+            _ => break,
+        }
+    }
+    errors
+}
+
+// parses text in which only expansions are special, like the prompt
+#[allow(clippy::missing_errors_doc)]
+pub fn parse_expansions(input: &str) -> Result<crate::ast::Word> {
+    heredoc_body(input)
+}
+
+// returns true if the input could become valid by adding more lines to it,
+// so that interactive input can continue onto the next line rather than
+// failing
+#[must_use]
+pub fn is_incomplete(input: &str) -> bool {
+    matches!(
+        parse(input),
+        Err(Error::UnterminatedQuote { .. }
+            | Error::UnexpectedEof { .. }
+            | Error::UnterminatedHeredoc { .. })
+    )
+}
+
+// returns true if the given string is a valid variable or function name
+#[must_use]
+pub fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// quotes a word so that the parser will turn it back into the same word
+#[must_use]
+pub fn quote(word: &str) -> String {
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_alphanumeric() || "%+,-./:=@_^".contains(c))
+    {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    // here-document bodies start on the line after the command which uses
+    // them, so when we read a body, we have to remember to skip over it
+    // once we reach the end of the current line. this holds the position
+    // of that newline, and the position just after the last body read.
+    heredocs: Option<(usize, usize)>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str, pos: usize) -> Self {
+        Self {
+            input,
+            pos,
+            heredocs: None,
+        }
+    }
+
+    // parses a program which must extend to the end of the input
+    fn complete(&mut self) -> Result<crate::ast::Program> {
+        let program = self.program(&[])?;
+        self.skip_blanks();
+        if self.peek().is_some() {
+            return Err(self.unexpected());
+        }
+        Ok(program)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.rest().chars().nth(n)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        if let Some((newline, end)) = self.heredocs {
+            if self.pos == newline {
+                self.heredocs = None;
+                self.pos = end;
+                return Some(c);
+            }
+        }
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.rest().starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    // the span from the given position to the current position
+    fn span(&self, start: usize) -> crate::ast::Span {
+        crate::ast::Span {
+            start,
+            end: self.pos,
+        }
+    }
+
+    fn location(&self, start: usize, end: usize) -> Location {
+        let before = &self.input[..start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        let col = before[line_start..].chars().count() + 1;
+        Location {
+            line,
+            col,
+            span: crate::ast::Span { start, end },
+        }
+    }
+
+    fn unexpected(&self) -> Error {
+        let rest = self.rest();
+        let token = if rest.starts_with("&&")
+            || rest.starts_with("||")
+            || rest.starts_with(";;")
+        {
+            rest[..2].to_string()
+        } else if rest.starts_with('\n') {
+            "newline".to_string()
+        } else if let Some(word) = self.peek_word() {
+            word.to_string()
+        } else {
+            rest.chars().take(1).collect()
+        };
+        let len = if token == "newline" { 1 } else { token.len() };
+        let location = self.location(self.pos, self.pos + len);
+        Error::UnexpectedToken { token, location }
+    }
+
+    fn unexpected_eof(&self, expected: &str) -> Error {
+        Error::UnexpectedEof {
+            expected: expected.to_string(),
+            location: self.location(self.pos, self.pos),
+        }
+    }
+
+    fn unexpected_or_eof(&self, expected: &str) -> Error {
+        if self.peek().is_none() {
+            self.unexpected_eof(expected)
+        } else {
+            self.unexpected()
+        }
+    }
+
+    // skips whitespace (other than newlines), line continuations, and
+    // comments
+    fn skip_blanks(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t') => {
+                    self.bump();
+                }
+                Some('\\') if self.peek_at(1) == Some('\n') => {
+                    self.pos += 2;
+                }
+                Some('#') => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.bump();
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn skip_linebreaks(&mut self) {
+        loop {
+            self.skip_blanks();
+            if self.peek() != Some('\n') {
+                break;
+            }
+            self.bump();
+        }
+    }
+
+    // the text of the next word, if it consists only of unquoted literal
+    // characters
+    fn peek_word(&self) -> Option<&'a str> {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || METACHARACTERS.contains(c))
+            .unwrap_or(rest.len());
+        let word = &rest[..end];
+        if word.is_empty() || word.contains(&['\'', '"', '\\', '$', '`'][..])
+        {
+            None
+        } else {
+            Some(word)
+        }
+    }
+
+    fn peek_reserved(&self) -> Option<&'static str> {
+        let word = self.peek_word()?;
+        RESERVED_WORDS.iter().find(|r| **r == word).copied()
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        self.skip_blanks();
+        if self.peek_word() == Some(word) {
+            self.pos += word.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<()> {
+        if self.eat_word(word) {
+            Ok(())
+        } else {
+            Err(self.unexpected_or_eof(&format!("`{word}`")))
+        }
+    }
+
+    fn program(
+        &mut self,
+        terminators: &[&str],
+    ) -> Result<crate::ast::Program> {
+        let mut lists = vec![];
+        let mut span = crate::ast::Span::default();
+        loop {
+            self.skip_linebreaks();
+            if matches!(self.peek(), None | Some(')'))
+                || self.rest().starts_with(";;")
+            {
+                break;
+            }
+            if let Some(word) = self.peek_reserved() {
+                if terminators.contains(&word) {
+                    break;
+                }
+            }
+            if lists.is_empty() {
+                span.start = self.pos;
+            }
+            lists.push(self.and_or()?);
+            span.end = self.pos;
+            self.skip_blanks();
+            match self.peek() {
+                Some(';') if !self.rest().starts_with(";;") => {
+                    self.bump();
+                }
+                Some('\n') => {
+                    self.bump();
+                }
+                Some('&') => {
+                    return Err(Error::Background {
+                        location: self.location(self.pos, self.pos + 1),
+                    });
+                }
+                _ => break,
+            }
+        }
+        if lists.is_empty() {
+            span = crate::ast::Span {
+                start: self.pos,
+                end: self.pos,
+            };
+        }
+        Ok(crate::ast::Program { lists, span })
+    }
+
+    fn and_or(&mut self) -> Result<crate::ast::AndOrList> {
+        let first = self.pipeline()?;
+        let mut rest = vec![];
+        loop {
+            self.skip_blanks();
+            let connector = if self.eat("&&") {
+                crate::ast::Connector::And
+            } else if self.eat("||") {
+                crate::ast::Connector::Or
+            } else {
+                break;
+            };
+            self.skip_linebreaks();
+            rest.push((connector, self.pipeline()?));
+        }
+        Ok(crate::ast::AndOrList { first, rest })
+    }
+
+    fn pipeline(&mut self) -> Result<crate::ast::Pipeline> {
+        self.skip_blanks();
+        let start = self.pos;
+        let negated = if self.peek_reserved() == Some("!") {
+            self.bump();
+            true
+        } else {
+            false
+        };
+        let mut commands = vec![self.command()?];
+        loop {
+            self.skip_blanks();
+            if self.rest().starts_with("||") || !self.eat("|") {
+                break;
+            }
+            self.skip_linebreaks();
+            commands.push(self.command()?);
+        }
+        Ok(crate::ast::Pipeline {
+            negated,
+            commands,
+            span: self.span(start),
+        })
+    }
+
+    fn command(&mut self) -> Result<crate::ast::Command> {
+        self.skip_blanks();
+        if self.rest().starts_with("((") {
+            self.pos += 2;
+            let expr = self.arithmetic()?;
+            return Ok(crate::ast::Command::Arithmetic(expr));
+        }
+        let word = match self.peek_reserved() {
+            Some(word) if COMPOUND_COMMANDS.contains(&word) => word,
+            Some(_) => return Err(self.unexpected()),
+            None => return self.simple_command(),
+        };
+        self.pos += word.len();
+        match word {
+            "{" => {
+                let body = self.compound_list(&["}"])?;
+                self.expect_word("}")?;
+                Ok(crate::ast::Command::BraceGroup(body))
+            }
+            "if" => self.if_command(),
+            "while" => self.loop_command(crate::ast::LoopKind::While),
+            "until" => self.loop_command(crate::ast::LoopKind::Until),
+            "for" => self.for_command(),
+            "case" => self.case_command(),
+            _ => unreachable!(),
+        }
+    }
+
+    // like program, but at least one command is required
+    fn compound_list(
+        &mut self,
+        terminators: &[&str],
+    ) -> Result<crate::ast::Program> {
+        let program = self.program(terminators)?;
+        if program.lists.is_empty() {
+            return Err(self.unexpected_or_eof("a command"));
+        }
+        Ok(program)
+    }
+
+    fn if_command(&mut self) -> Result<crate::ast::Command> {
+        let mut conditionals = vec![];
+        let mut else_body = None;
+        loop {
+            let condition = self.compound_list(&["then"])?;
+            self.expect_word("then")?;
+            let body = self.compound_list(&["elif", "else", "fi"])?;
+            conditionals.push(crate::ast::Conditional { condition, body });
+            if self.eat_word("elif") {
+                continue;
+            }
+            if self.eat_word("else") {
+                else_body = Some(self.compound_list(&["fi"])?);
+            }
+            self.expect_word("fi")?;
+            break;
+        }
+        Ok(crate::ast::Command::If(crate::ast::If {
+            conditionals,
+            else_body,
+        }))
+    }
+
+    fn loop_command(
+        &mut self,
+        kind: crate::ast::LoopKind,
+    ) -> Result<crate::ast::Command> {
+        let condition = self.compound_list(&["do"])?;
+        self.expect_word("do")?;
+        let body = self.compound_list(&["done"])?;
+        self.expect_word("done")?;
+        Ok(crate::ast::Command::Loop(crate::ast::Loop {
+            kind,
+            condition,
+            body,
+        }))
+    }
+
+    fn for_command(&mut self) -> Result<crate::ast::Command> {
+        self.skip_blanks();
+        let name = match self.peek_word() {
+            Some(name) if is_name(name) => name.to_string(),
+            _ => return Err(self.unexpected_or_eof("a variable name")),
+        };
+        self.pos += name.len();
+
+        let words = if self.eat_word("in") {
+            let mut words = vec![];
+            loop {
+                self.skip_blanks();
+                match self.peek() {
+                    None | Some('\n' | ';') => break,
+                    Some(c) if METACHARACTERS.contains(c) => {
+                        return Err(self.unexpected());
+                    }
+                    _ => words.push(self.word()?),
+                }
+            }
+            Some(words)
+        } else {
+            None
+        };
+        self.skip_blanks();
+        self.eat(";");
+        self.skip_linebreaks();
+
+        self.expect_word("do")?;
+        let body = self.compound_list(&["done"])?;
+        self.expect_word("done")?;
+        Ok(crate::ast::Command::For(crate::ast::For {
+            name,
+            words,
+            body,
+        }))
+    }
+
+    fn case_command(&mut self) -> Result<crate::ast::Command> {
+        self.skip_blanks();
+        match self.peek() {
+            None => return Err(self.unexpected_eof("a word")),
+            Some(c) if c == '\n' || METACHARACTERS.contains(c) => {
+                return Err(self.unexpected());
+            }
+            _ => {}
+        }
+        let word = self.word()?;
+        self.skip_linebreaks();
+        self.expect_word("in")?;
+
+        let mut items = vec![];
+        loop {
+            self.skip_linebreaks();
+            if self.eat_word("esac") {
+                break;
+            }
+            self.eat("(");
+            let mut patterns = vec![];
+            loop {
+                self.skip_blanks();
+                match self.peek() {
+                    Some(c) if c != '\n' && !METACHARACTERS.contains(c) => {
+                        patterns.push(self.word()?);
+                    }
+                    _ => return Err(self.unexpected_or_eof("a pattern")),
+                }
+                self.skip_blanks();
+                if self.eat(")") {
+                    break;
+                }
+                if !self.eat("|") {
+                    return Err(self.unexpected_or_eof("`)`"));
+                }
+            }
+            let body = self.program(&["esac"])?;
+            items.push(crate::ast::CaseItem { patterns, body });
+            self.skip_blanks();
+            if !self.eat(";;") {
+                self.skip_linebreaks();
+                self.expect_word("esac")?;
+                break;
+            }
+        }
+        Ok(crate::ast::Command::Case(crate::ast::Case { word, items }))
+    }
+
+    fn simple_command(&mut self) -> Result<crate::ast::Command> {
+        let mut command = crate::ast::SimpleCommand {
+            span: self.span(self.pos),
+            ..crate::ast::SimpleCommand::default()
+        };
+        loop {
+            self.skip_blanks();
+            if self.rest().starts_with("<<") {
+                command.redirects.push(self.heredoc()?);
+                command.span.end = self.pos;
+                continue;
+            }
+            if let Some(redirect) = self.redirect()? {
+                command.redirects.push(redirect);
+                command.span.end = self.pos;
+                continue;
+            }
+            match self.peek() {
+                None => break,
+                _ if self.at_process_subst() => {}
+                Some(c) if c == '\n' || METACHARACTERS.contains(c) => break,
+                _ => {}
+            }
+            if command.words.is_empty() {
+                if let Some(assignment) = self.assignment()? {
+                    command.assignments.push(assignment);
+                    command.span.end = self.pos;
+                    continue;
+                }
+            }
+            let start = self.pos;
+            let word = self.word()?;
+            if command.words.is_empty() && command.assignments.is_empty() {
+                let save = self.pos;
+                self.skip_blanks();
+                if self.peek() == Some('(') {
+                    return self.function_def(start, &word);
+                }
+                self.pos = save;
+            }
+            command.words.push(word);
+            command.span.end = self.pos;
+        }
+
+        if command.words.is_empty()
+            && command.assignments.is_empty()
+            && command.redirects.is_empty()
+        {
+            return Err(self.unexpected_or_eof("a command"));
+        }
+        Ok(crate::ast::Command::Simple(command))
+    }
+
+    // `[n]<`, `[n]>`, `[n]>>`, `[n]<&` or `[n]>&`, followed by a word.
+    // `<<` and process substitutions are handled elsewhere.
+    fn redirect(&mut self) -> Result<Option<crate::ast::Redirect>> {
+        let start = self.pos;
+        let digits = self
+            .rest()
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or_else(|| self.rest().len());
+        let op = &self.rest()[digits..];
+        if !(op.starts_with('<') || op.starts_with('>'))
+            || op.starts_with("<<")
+            || op.starts_with("<(")
+            || op.starts_with(">(")
+        {
+            return Ok(None);
+        }
+        let fd = if digits == 0 {
+            None
+        } else {
+            let fd = self.rest()[..digits].parse().map_err(|_| {
+                Error::UnexpectedToken {
+                    token: self.rest()[..digits].to_string(),
+                    location: self.location(start, start + digits),
+                }
+            })?;
+            Some(fd)
+        };
+        self.pos += digits;
+
+        let input = self.bump() == Some('<');
+        let fd = fd.unwrap_or_else(|| u32::from(!input));
+        let (dup, append) = if self.eat("&") {
+            (true, false)
+        } else {
+            (false, !input && self.eat(">"))
+        };
+        // `>#file` writes to `#file`, but `> #file` is a comment
+        if self.peek() != Some('#') {
+            self.skip_blanks();
+        }
+        let target_start = self.pos;
+        let target = self.word()?;
+        if target.parts.is_empty() {
+            self.pos = target_start;
+            return Err(self.unexpected_or_eof("a redirection target"));
+        }
+        let span = self.span(start);
+
+        if dup {
+            let source = target
+                .as_literal()
+                .filter(|s| s.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| Error::UnexpectedToken {
+                    token: self.input[target_start..self.pos].to_string(),
+                    location: self.location(target_start, self.pos),
+                })?;
+            return Ok(Some(crate::ast::Redirect::Duplicate(
+                crate::ast::Duplicate {
+                    fd,
+                    source,
+                    input,
+                    span,
+                },
+            )));
+        }
+        let redirect = crate::ast::FileRedirect { fd, target, span };
+        Ok(Some(if input {
+            crate::ast::Redirect::Input(redirect)
+        } else if append {
+            crate::ast::Redirect::Append(redirect)
+        } else {
+            crate::ast::Redirect::Output(redirect)
+        }))
+    }
+
+    fn heredoc(&mut self) -> Result<crate::ast::Redirect> {
+        let start = self.pos;
+        self.pos += 2;
+        let strip_tabs = self.eat("-");
+        self.skip_blanks();
+        let word_start = self.pos;
+        self.word()?;
+        let raw = &self.input[word_start..self.pos];
+        if raw.is_empty() {
+            return Err(self.unexpected_or_eof("a here-document delimiter"));
+        }
+        let quoted = raw.contains(&['\'', '"', '\\'][..]);
+        let delimiter: String =
+            raw.chars().filter(|c| !"'\"\\".contains(*c)).collect();
+
+        let unterminated = || Error::UnterminatedHeredoc {
+            delimiter: delimiter.clone(),
+            location: self.location(start, self.pos),
+        };
+        let (newline, body_start) = if let Some(heredocs) = self.heredocs {
+            heredocs
+        } else {
+            let newline = self
+                .rest()
+                .find('\n')
+                .map(|idx| self.pos + idx)
+                .ok_or_else(unterminated)?;
+            (newline, newline + 1)
+        };
+
+        let mut body = String::new();
+        let mut pos = body_start;
+        let end = loop {
+            if pos >= self.input.len() {
+                return Err(unterminated());
+            }
+            let line_end = self.input[pos..]
+                .find('\n')
+                .map_or(self.input.len(), |idx| pos + idx);
+            let mut line = &self.input[pos..line_end];
+            if strip_tabs {
+                line = line.trim_start_matches('\t');
+            }
+            let next = std::cmp::min(line_end + 1, self.input.len());
+            if line == delimiter {
+                break next;
+            }
+            body.push_str(line);
+            body.push('\n');
+            pos = next;
+        };
+        self.heredocs = Some((newline, end));
+
+        let mut body = if quoted {
+            crate::ast::Word {
+                parts: vec![crate::ast::WordPart::SingleQuoted(body)],
+                span: crate::ast::Span::default(),
+            }
+        } else {
+            let line = self.location(body_start, body_start).line;
+            heredoc_body(&body).map_err(|e| e.offset(body_start, line, 1))?
+        };
+        body.span = crate::ast::Span {
+            start: body_start,
+            end: pos,
+        };
+        Ok(crate::ast::Redirect::Heredoc(crate::ast::Heredoc {
+            delimiter,
+            quoted,
+            strip_tabs,
+            body,
+            span: self.span(start),
+        }))
+    }
+
+    fn function_def(
+        &mut self,
+        start: usize,
+        name: &crate::ast::Word,
+    ) -> Result<crate::ast::Command> {
+        let name = match name.as_literal() {
+            Some(name) if is_name(name) => name.to_string(),
+            _ => {
+                return Err(Error::UnexpectedToken {
+                    token: self.input[start..self.pos].trim().to_string(),
+                    location: self.location(start, self.pos),
+                });
+            }
+        };
+        self.bump();
+        self.skip_blanks();
+        if !self.eat(")") {
+            return Err(self.unexpected_or_eof("`)`"));
+        }
+        self.skip_linebreaks();
+        if !self
+            .peek_reserved()
+            .is_some_and(|word| COMPOUND_COMMANDS.contains(&word))
+        {
+            return Err(self.unexpected_or_eof("a function body"));
+        }
+        let body = self.command()?;
+        Ok(crate::ast::Command::FunctionDef(crate::ast::FunctionDef {
+            name,
+            body: std::sync::Arc::new(body),
+        }))
+    }
+
+    fn assignment(&mut self) -> Result<Option<crate::ast::Assignment>> {
+        let rest = self.rest();
+        let name_len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let name = &rest[..name_len];
+        if !is_name(name) || !rest[name_len..].starts_with('=') {
+            return Ok(None);
+        }
+        self.pos += name_len + 1;
+        let value = self.word()?;
+        Ok(Some(crate::ast::Assignment {
+            name: name.to_string(),
+            value,
+        }))
+    }
+
+    fn word(&mut self) -> Result<crate::ast::Word> {
+        let word_start = self.pos;
+        let mut parts = vec![];
+        if self.peek() == Some('~') {
+            match self.peek_at(1) {
+                None | Some('/') => {
+                    self.bump();
+                    parts.push(crate::ast::WordPart::Tilde);
+                }
+                Some(c)
+                    if c.is_whitespace() || METACHARACTERS.contains(c) =>
+                {
+                    self.bump();
+                    parts.push(crate::ast::WordPart::Tilde);
+                }
+                _ => {}
+            }
+        }
+        while let Some(c) = self.peek() {
+            if self.at_process_subst() {
+                parts.push(self.process_subst()?);
+                continue;
+            }
+            if c.is_whitespace() || METACHARACTERS.contains(c) {
+                break;
+            }
+            let start = self.pos;
+            self.bump();
+            match c {
+                '\'' => {
+                    let len = self.rest().find('\'').ok_or_else(|| {
+                        self.unterminated_quote('\'', start)
+                    })?;
+                    let quoted = self.rest()[..len].to_string();
+                    self.pos += len + 1;
+                    parts.push(crate::ast::WordPart::SingleQuoted(quoted));
+                }
+                '"' => {
+                    let quoted = self.double_quoted(start)?;
+                    parts.push(crate::ast::WordPart::DoubleQuoted(quoted));
+                }
+                '\\' => match self.bump() {
+                    Some('\n') => {}
+                    Some(c) => parts.push(
+                        crate::ast::WordPart::SingleQuoted(c.to_string()),
+                    ),
+                    None => {
+                        return Err(
+                            self.unexpected_eof("a character after `\\`")
+                        )
+                    }
+                },
+                '$' => parts.push(self.dollar(start)?),
+                '`' => parts.push(self.backquote(start)?),
+                c => push_literal(&mut parts, c),
+            }
+        }
+        Ok(crate::ast::Word {
+            parts,
+            span: self.span(word_start),
+        })
+    }
+
+    fn at_process_subst(&self) -> bool {
+        self.rest().starts_with("<(") || self.rest().starts_with(">(")
+    }
+
+    fn process_subst(&mut self) -> Result<crate::ast::WordPart> {
+        let kind = if self.bump() == Some('<') {
+            crate::ast::ProcessSubstKind::Read
+        } else {
+            crate::ast::ProcessSubstKind::Write
+        };
+        self.bump();
+        let program = self.program(&[])?;
+        self.skip_blanks();
+        if !self.eat(")") {
+            return Err(self.unexpected_or_eof("`)`"));
+        }
+        Ok(crate::ast::WordPart::ProcessSubst(kind, program))
+    }
+
+    fn double_quoted(
+        &mut self,
+        start: usize,
+    ) -> Result<Vec<crate::ast::WordPart>> {
+        let mut parts = vec![];
+        loop {
+            let dollar = self.pos;
+            match self.bump() {
+                Some('"') => break,
+                Some('\\') => match self.bump() {
+                    Some('\n') => {}
+                    Some(c @ ('"' | '\\' | '$' | '`')) => {
+                        push_literal(&mut parts, c);
+                    }
+                    Some(c) => {
+                        push_literal(&mut parts, '\\');
+                        push_literal(&mut parts, c);
+                    }
+                    None => return Err(self.unterminated_quote('"', start)),
+                },
+                Some('$') => parts.push(self.dollar(dollar)?),
+                Some('`') => parts.push(self.backquote(dollar)?),
+                Some(c) => push_literal(&mut parts, c),
+                None => return Err(self.unterminated_quote('"', start)),
+            }
+        }
+        Ok(parts)
+    }
+
+    // parses the part of a word after a `$`
+    fn dollar(&mut self, start: usize) -> Result<crate::ast::WordPart> {
+        match self.peek() {
+            Some('(') if self.peek_at(1) == Some('(') => {
+                self.pos += 2;
+                Ok(crate::ast::WordPart::Arithmetic(self.arithmetic()?))
+            }
+            Some('(') => {
+                self.bump();
+                let program = self.program(&[])?;
+                self.skip_blanks();
+                if !self.eat(")") {
+                    return Err(self.unexpected_or_eof("`)`"));
+                }
+                Ok(crate::ast::WordPart::CommandSubst(program))
+            }
+            Some('{') => {
+                self.bump();
+                let len = self.rest().find('}').ok_or_else(|| {
+                    self.unexpected_eof("`}` to end the parameter")
+                })?;
+                let name = self.rest()[..len].to_string();
+                self.pos += len + 1;
+                if is_name(&name) || is_special_param(&name) {
+                    Ok(crate::ast::WordPart::Param(name))
+                } else {
+                    Err(Error::BadSubstitution {
+                        text: self.input[start..self.pos].to_string(),
+                        location: self.location(start, self.pos),
+                    })
+                }
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let rest = self.rest();
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                self.pos += len;
+                Ok(crate::ast::WordPart::Param(rest[..len].to_string()))
+            }
+            Some(c) if is_special_param(&c.to_string()) => {
+                self.bump();
+                Ok(crate::ast::WordPart::Param(c.to_string()))
+            }
+            _ => Ok(crate::ast::WordPart::Literal("$".to_string())),
+        }
+    }
+
+    // parses an arithmetic expression up to the closing `))`. parentheses
+    // within the expression must be balanced, and expansions are parsed as
+    // they would be within double quotes.
+    fn arithmetic(&mut self) -> Result<crate::ast::Word> {
+        let expr_start = self.pos;
+        let mut parts = vec![];
+        let mut depth = 0_usize;
+        let span = loop {
+            let start = self.pos;
+            match self.bump() {
+                Some(')') if depth == 0 => {
+                    if self.eat(")") {
+                        break self.span(expr_start);
+                    }
+                    return Err(self.unexpected_or_eof("`))`"));
+                }
+                Some(c @ ('(' | ')')) => {
+                    if c == '(' {
+                        depth += 1;
+                    } else {
+                        depth -= 1;
+                    }
+                    push_literal(&mut parts, c);
+                }
+                Some('\\') => match self.bump() {
+                    Some('\n') => {}
+                    Some(c) => push_literal(&mut parts, c),
+                    None => {
+                        return Err(
+                            self.unexpected_eof("a character after `\\`")
+                        )
+                    }
+                },
+                Some('"') => {
+                    let quoted = self.double_quoted(start)?;
+                    parts.push(crate::ast::WordPart::DoubleQuoted(quoted));
+                }
+                Some('$') => parts.push(self.dollar(start)?),
+                Some('`') => parts.push(self.backquote(start)?),
+                Some(c) => push_literal(&mut parts, c),
+                None => return Err(self.unexpected_eof("`))`")),
+            }
+        };
+        Ok(crate::ast::Word {
+            parts,
+            span: crate::ast::Span {
+                start: span.start,
+                end: span.end - 2,
+            },
+        })
+    }
+
+    // parses the part of a word after a backtick. the contents are parsed
+    // separately, after removing the backslashes which were escaping
+    // characters that would otherwise be special within backticks.
+    fn backquote(&mut self, start: usize) -> Result<crate::ast::WordPart> {
+        let mut inner = String::new();
+        loop {
+            match self.bump() {
+                Some('`') => break,
+                Some('\\') => match self.peek() {
+                    Some(c @ ('$' | '`' | '\\')) => {
+                        self.bump();
+                        inner.push(c);
+                    }
+                    _ => inner.push('\\'),
+                },
+                Some(c) => inner.push(c),
+                None => return Err(self.unterminated_quote('`', start)),
+            }
+        }
+        let Location { line, col, .. } = self.location(start + 1, start + 1);
+        let program =
+            parse(&inner).map_err(|e| e.offset(start + 1, line, col))?;
+        Ok(crate::ast::WordPart::CommandSubst(program))
+    }
+
+    fn unterminated_quote(&self, quote: char, start: usize) -> Error {
+        Error::UnterminatedQuote {
+            quote,
+            location: self.location(start, start + quote.len_utf8()),
+        }
+    }
+}
+
+// unquoted here-document bodies are expanded like double quoted strings,
+// except that double quotes themselves aren't special
+fn heredoc_body(body: &str) -> Result<crate::ast::Word> {
+    let mut parser = Parser::new(body, 0);
+    let mut parts = vec![];
+    loop {
+        let start = parser.pos;
+        match parser.bump() {
+            Some('\\') => match parser.peek() {
+                Some('\n') => {
+                    parser.bump();
+                }
+                Some(c @ ('$' | '`' | '\\')) => {
+                    parser.bump();
+                    push_literal(&mut parts, c);
+                }
+                _ => push_literal(&mut parts, '\\'),
+            },
+            Some('$') => parts.push(parser.dollar(start)?),
+            Some('`') => parts.push(parser.backquote(start)?),
+            Some(c) => push_literal(&mut parts, c),
+            None => break,
+        }
+    }
+    Ok(crate::ast::Word {
+        parts: vec![crate::ast::WordPart::DoubleQuoted(parts)],
+        span: parser.span(0),
+    })
+}
+
+fn is_special_param(name: &str) -> bool {
+    match name {
+        "?" | "#" | "@" | "*" | "$" | "!" | "-" => true,
+        _ => !name.is_empty() && name.chars().all(|c| c.is_ascii_digit()),
+    }
+}
+
+fn push_literal(parts: &mut Vec<crate::ast::WordPart>, c: char) {
+    if let Some(crate::ast::WordPart::Literal(s)) = parts.last_mut() {
+        s.push(c);
+    } else {
+        parts.push(crate::ast::WordPart::Literal(c.to_string()));
+    }
 }
@@ -0,0 +1,118 @@
+// shell pattern matching, as used by `case`. supports `*`, `?`, bracket
+// expressions like `[a-z]` or `[!0-9]`, and backslash escapes.
+pub fn matches(pattern: &str, s: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+
+    let mut p = 0;
+    let mut i = 0;
+    // where to resume if the current attempt fails: the position just
+    // after the most recent `*`, and the next character it should absorb
+    let mut backtrack = None;
+    while i < s.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            p += 1;
+            backtrack = Some((p, i));
+            continue;
+        }
+        if let Some(len) = match_one(&pattern[p..], s[i]) {
+            p += len;
+            i += 1;
+            continue;
+        }
+        if let Some((star_p, star_i)) = backtrack {
+            p = star_p;
+            i = star_i + 1;
+            backtrack = Some((star_p, star_i + 1));
+            continue;
+        }
+        return false;
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// escapes a string so that it matches only itself
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        if "*?[]\\".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// returns the length of the pattern element at the start of the pattern if
+// it matches the given character
+fn match_one(pattern: &[char], c: char) -> Option<usize> {
+    match pattern.first()? {
+        '?' => Some(1),
+        '[' => {
+            if let Some((matched, len)) = bracket(pattern, c) {
+                if matched {
+                    Some(len)
+                } else {
+                    None
+                }
+            } else if c == '[' {
+                // unterminated brackets are just literal characters
+                Some(1)
+            } else {
+                None
+            }
+        }
+        '\\' if pattern.len() > 1 => {
+            if pattern[1] == c {
+                Some(2)
+            } else {
+                None
+            }
+        }
+        &p => {
+            if p == c {
+                Some(1)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+// parses a bracket expression, returning whether it matched and how long
+// it was, or None if it was unterminated
+fn bracket(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let mut start = *pattern.get(i)?;
+        if start == ']' && !first {
+            break;
+        }
+        first = false;
+        if start == '\\' {
+            i += 1;
+            start = *pattern.get(i)?;
+        }
+        i += 1;
+        if pattern.get(i) == Some(&'-') && pattern.get(i + 1) != Some(&']') {
+            let mut end = *pattern.get(i + 1)?;
+            i += 2;
+            if end == '\\' {
+                end = *pattern.get(i)?;
+                i += 1;
+            }
+            if start <= c && c <= end {
+                matched = true;
+            }
+        } else if start == c {
+            matched = true;
+        }
+    }
+    Some((matched != negated, i + 1))
+}
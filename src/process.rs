@@ -0,0 +1,486 @@
+use futures::future::Future as _;
+use futures::stream::Stream as _;
+use snafu::ResultExt as _;
+use std::os::fd::AsRawFd as _;
+use std::os::unix::process::CommandExt as _;
+use tokio::io::{AsyncRead as _, AsyncWrite as _};
+use tokio_pty_process::{CommandExt as _, PtyMaster as _};
+
+const READ_BUFFER_SIZE: usize = 4 * 1024;
+
+#[derive(Debug, snafu::Snafu)]
+pub enum Error {
+    #[snafu(display("failed to open a pty: {}", source))]
+    OpenPty { source: std::io::Error },
+
+    #[snafu(display("failed to spawn process for `{}`: {}", cmd, source))]
+    SpawnProcess { cmd: String, source: std::io::Error },
+
+    #[snafu(display("failed to poll for process exit: {}", source))]
+    ProcessExitPoll { source: std::io::Error },
+
+    #[snafu(display("failed to read from pty: {}", source))]
+    ReadPty { source: std::io::Error },
+
+    #[snafu(display("failed to write to pty: {}", source))]
+    WritePty { source: std::io::Error },
+
+    #[snafu(display("failed to set close-on-exec flag: {}", source))]
+    SetCloexec { source: nix::Error },
+
+    #[snafu(display("failed to duplicate file descriptor: {}", source))]
+    DuplicateFd { source: std::io::Error },
+
+    #[snafu(display("failed to read from terminal: {}", source))]
+    ReadTerminal { source: std::io::Error },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// file descriptors are all close-on-exec by default, but things like
+// process substitution need to pass specific ones through to a child.
+// spawning is serialized so that no other child can inherit them while the
+// flag is cleared.
+static SPAWN: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+fn spawn_with_fds<T>(
+    fds: &[std::sync::Arc<std::os::fd::OwnedFd>],
+    spawn: impl FnOnce() -> std::io::Result<T>,
+) -> Result<std::io::Result<T>> {
+    let _guard = SPAWN.lock().unwrap();
+    for fd in fds {
+        set_cloexec(fd, false)?;
+    }
+    let res = spawn();
+    for fd in fds {
+        set_cloexec(fd, true)?;
+    }
+    Ok(res)
+}
+
+pub fn command(
+    cmd: &str,
+    path: Option<&std::path::Path>,
+) -> std::process::Command {
+    path.map_or_else(
+        || std::process::Command::new(cmd),
+        |path| {
+            let mut command = std::process::Command::new(path);
+            command.arg0(cmd);
+            command
+        },
+    )
+}
+
+fn set_cloexec(fd: &std::os::fd::OwnedFd, cloexec: bool) -> Result<()> {
+    let flags = if cloexec {
+        nix::fcntl::FdFlag::FD_CLOEXEC
+    } else {
+        nix::fcntl::FdFlag::empty()
+    };
+    nix::fcntl::fcntl(fd.as_raw_fd(), nix::fcntl::FcntlArg::F_SETFD(flags))
+        .context(SetCloexec)?;
+    Ok(())
+}
+
+// this is mostly the same as tokio_pty_process_stream::ResizingProcess,
+// except that it correctly notices when the process closes the pty (which
+// is reported as EIO rather than as a zero-length read), and it allows
+// setting environment variables for the child.
+#[must_use = "streams do nothing unless polled"]
+pub struct Process<R: tokio::io::AsyncRead> {
+    cmd: String,
+    path: Option<std::path::PathBuf>,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    fds: Vec<std::sync::Arc<std::os::fd::OwnedFd>>,
+    input: R,
+    input_buf: std::collections::VecDeque<u8>,
+    buf: [u8; READ_BUFFER_SIZE],
+    pty: Option<tokio_pty_process::AsyncPtyMaster>,
+    child: Option<tokio_pty_process::Child>,
+    resizer: Option<
+        Box<
+            dyn futures::stream::Stream<
+                    Item = (u16, u16),
+                    Error = tokio_terminal_resize::Error,
+                > + Send,
+        >,
+    >,
+    stdin_closed: bool,
+    stdout_closed: bool,
+    exited: bool,
+}
+
+impl<R: tokio::io::AsyncRead + 'static> Process<R> {
+    pub fn new(cmd: &str, args: &[String], input: R) -> Self {
+        Self {
+            cmd: cmd.to_string(),
+            path: None,
+            args: args.to_vec(),
+            env: vec![],
+            fds: vec![],
+            input,
+            input_buf: std::collections::VecDeque::new(),
+            buf: [0; READ_BUFFER_SIZE],
+            pty: None,
+            child: None,
+            resizer: None,
+            stdin_closed: false,
+            stdout_closed: false,
+            exited: false,
+        }
+    }
+
+    // the executable to run, if it was already looked up. the command name
+    // is still used as argv[0].
+    pub fn path(mut self, path: &std::path::Path) -> Self {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+
+    pub fn env(mut self, env: &[(String, String)]) -> Self {
+        self.env = env.to_vec();
+        self
+    }
+
+    // file descriptors which should stay open (with the same numbers) in
+    // the child
+    pub fn inherit_fds(
+        mut self,
+        fds: &[std::sync::Arc<std::os::fd::OwnedFd>],
+    ) -> Self {
+        self.fds = fds.to_vec();
+        self
+    }
+
+    fn poll_resize(&mut self) -> Option<(u16, u16)> {
+        let resizer = self.resizer.as_mut()?;
+        match resizer.poll() {
+            Ok(futures::Async::Ready(Some((rows, cols)))) => {
+                // resizing is best effort, there's nothing useful to do if
+                // it fails
+                let _ = self.pty.as_ref().unwrap().resize(rows, cols);
+                Some((rows, cols))
+            }
+            Ok(futures::Async::Ready(None)) | Err(_) => {
+                // probably not running on a terminal
+                self.resizer = None;
+                None
+            }
+            Ok(futures::Async::NotReady) => None,
+        }
+    }
+
+    fn poll_stdin(&mut self) -> Result<bool> {
+        if self.stdin_closed {
+            return Ok(false);
+        }
+        match self.input.poll_read(&mut self.buf).context(ReadTerminal)? {
+            futures::Async::Ready(0) => {
+                self.input_buf.push_back(b'\x04');
+                self.stdin_closed = true;
+                Ok(true)
+            }
+            futures::Async::Ready(n) => {
+                self.input_buf.extend(self.buf[..n].iter());
+                Ok(true)
+            }
+            futures::Async::NotReady => Ok(false),
+        }
+    }
+
+    fn poll_write_pty(&mut self) -> Result<bool> {
+        if self.input_buf.is_empty() {
+            return Ok(false);
+        }
+        let (a, b) = self.input_buf.as_slices();
+        let buf = if a.is_empty() { b } else { a };
+        match self
+            .pty
+            .as_mut()
+            .unwrap()
+            .poll_write(buf)
+            .context(WritePty)?
+        {
+            futures::Async::Ready(n) => {
+                self.input_buf.drain(..n);
+                Ok(true)
+            }
+            futures::Async::NotReady => Ok(false),
+        }
+    }
+
+    fn poll_read_pty(&mut self) -> Result<futures::Async<Option<Vec<u8>>>> {
+        match self.pty.as_mut().unwrap().poll_read(&mut self.buf) {
+            Ok(futures::Async::Ready(0)) => Ok(futures::Async::Ready(None)),
+            Ok(futures::Async::Ready(n)) => {
+                Ok(futures::Async::Ready(Some(self.buf[..n].to_vec())))
+            }
+            Ok(futures::Async::NotReady) => Ok(futures::Async::NotReady),
+            Err(ref e) if e.raw_os_error() == Some(nix::libc::EIO) => {
+                Ok(futures::Async::Ready(None))
+            }
+            Err(e) => Err(e).context(ReadPty),
+        }
+    }
+}
+
+impl<R: tokio::io::AsyncRead + 'static> futures::stream::Stream
+    for Process<R>
+{
+    type Item = tokio_pty_process_stream::Event;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        if self.child.is_none() {
+            let pty =
+                tokio_pty_process::AsyncPtyMaster::open().context(OpenPty)?;
+            let mut command = command(&self.cmd, self.path.as_deref());
+            command
+                .args(&self.args)
+                .envs(self.env.iter().map(|(k, v)| (k, v)));
+            let child =
+                spawn_with_fds(&self.fds, || command.spawn_pty_async(&pty))?
+                    .context(SpawnProcess {
+                        cmd: self.cmd.clone(),
+                    })?;
+            self.pty = Some(pty);
+            self.child = Some(child);
+            self.resizer = Some(Box::new(
+                tokio_terminal_resize::resizes().flatten_stream(),
+            ));
+            return Ok(futures::Async::Ready(Some(
+                tokio_pty_process_stream::Event::CommandStart {
+                    cmd: self.cmd.clone(),
+                    args: self.args.clone(),
+                },
+            )));
+        }
+
+        if self.exited {
+            return Ok(futures::Async::Ready(None));
+        }
+
+        loop {
+            if self.stdout_closed {
+                let status = futures::try_ready!(self
+                    .child
+                    .as_mut()
+                    .unwrap()
+                    .poll()
+                    .context(ProcessExitPoll));
+                self.exited = true;
+                return Ok(futures::Async::Ready(Some(
+                    tokio_pty_process_stream::Event::CommandExit { status },
+                )));
+            }
+
+            if let Some(size) = self.poll_resize() {
+                return Ok(futures::Async::Ready(Some(
+                    tokio_pty_process_stream::Event::Resize { size },
+                )));
+            }
+
+            let mut did_work = self.poll_stdin()?;
+            did_work |= self.poll_write_pty()?;
+
+            match self.poll_read_pty()? {
+                futures::Async::Ready(Some(data)) => {
+                    return Ok(futures::Async::Ready(Some(
+                        tokio_pty_process_stream::Event::Output { data },
+                    )));
+                }
+                futures::Async::Ready(None) => {
+                    self.stdout_closed = true;
+                    did_work = true;
+                }
+                futures::Async::NotReady => {}
+            }
+
+            if !did_work {
+                return Ok(futures::Async::NotReady);
+            }
+        }
+    }
+}
+
+// runs a process without a pty, for when its input or output needs to be
+// connected somewhere other than the terminal. anything not explicitly
+// given is inherited from the shell.
+#[must_use = "streams do nothing unless polled"]
+pub struct PipedProcess {
+    cmd: String,
+    path: Option<std::path::PathBuf>,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    fds: Vec<std::sync::Arc<std::os::fd::OwnedFd>>,
+    stdin: Option<std::sync::Arc<std::os::fd::OwnedFd>>,
+    stdout: Option<std::sync::Arc<std::os::fd::OwnedFd>>,
+    stderr: Option<std::sync::Arc<std::os::fd::OwnedFd>>,
+    // descriptors other than stdin, stdout and stderr to set up in the
+    // child, as (child fd, fd)
+    redirects:
+        Vec<(std::os::fd::RawFd, std::sync::Arc<std::os::fd::OwnedFd>)>,
+    exit: Option<
+        futures::sync::oneshot::Receiver<
+            std::io::Result<std::process::ExitStatus>,
+        >,
+    >,
+    exited: bool,
+}
+
+impl PipedProcess {
+    pub fn new(cmd: &str, args: &[String]) -> Self {
+        Self {
+            cmd: cmd.to_string(),
+            path: None,
+            args: args.to_vec(),
+            env: vec![],
+            fds: vec![],
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            redirects: vec![],
+            exit: None,
+            exited: false,
+        }
+    }
+
+    // the executable to run, if it was already looked up. the command name
+    // is still used as argv[0].
+    pub fn path(mut self, path: &std::path::Path) -> Self {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+
+    pub fn env(mut self, env: &[(String, String)]) -> Self {
+        self.env = env.to_vec();
+        self
+    }
+
+    pub fn inherit_fds(
+        mut self,
+        fds: &[std::sync::Arc<std::os::fd::OwnedFd>],
+    ) -> Self {
+        self.fds = fds.to_vec();
+        self
+    }
+
+    pub fn stdin(mut self, fd: std::sync::Arc<std::os::fd::OwnedFd>) -> Self {
+        self.stdin = Some(fd);
+        self
+    }
+
+    pub fn stdout(
+        mut self,
+        fd: std::sync::Arc<std::os::fd::OwnedFd>,
+    ) -> Self {
+        self.stdout = Some(fd);
+        self
+    }
+
+    pub fn stderr(
+        mut self,
+        fd: std::sync::Arc<std::os::fd::OwnedFd>,
+    ) -> Self {
+        self.stderr = Some(fd);
+        self
+    }
+
+    pub fn redirect(
+        mut self,
+        child_fd: std::os::fd::RawFd,
+        fd: std::sync::Arc<std::os::fd::OwnedFd>,
+    ) -> Self {
+        self.redirects.push((child_fd, fd));
+        self
+    }
+}
+
+impl futures::stream::Stream for PipedProcess {
+    type Item = tokio_pty_process_stream::Event;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        if self.exit.is_none() {
+            let mut command = command(&self.cmd, self.path.as_deref());
+            command
+                .args(&self.args)
+                .envs(self.env.iter().map(|(k, v)| (k, v)));
+            // our copies are closed once the child has started, so that
+            // whatever is on the other end of a pipe sees eof when the
+            // child exits
+            if let Some(fd) = self.stdin.take() {
+                command.stdin(fd.try_clone().context(DuplicateFd)?);
+            }
+            if let Some(fd) = self.stdout.take() {
+                command.stdout(fd.try_clone().context(DuplicateFd)?);
+            }
+            if let Some(fd) = self.stderr.take() {
+                command.stderr(fd.try_clone().context(DuplicateFd)?);
+            }
+            let redirects = std::mem::take(&mut self.redirects);
+            if !redirects.is_empty() {
+                let fds: Vec<_> = redirects
+                    .iter()
+                    .map(|(child_fd, fd)| (*child_fd, fd.as_raw_fd()))
+                    .collect();
+                // dup2 clears the close-on-exec flag on the new descriptor,
+                // but does nothing if the descriptors are the same
+                unsafe {
+                    command.pre_exec(move || {
+                        for &(child_fd, fd) in &fds {
+                            let res = if child_fd == fd {
+                                nix::fcntl::fcntl(
+                                    fd,
+                                    nix::fcntl::FcntlArg::F_SETFD(
+                                        nix::fcntl::FdFlag::empty(),
+                                    ),
+                                )
+                            } else {
+                                nix::unistd::dup2(fd, child_fd)
+                            };
+                            res.map_err(|_| std::io::Error::last_os_error())?;
+                        }
+                        Ok(())
+                    });
+                }
+            }
+            let mut child = spawn_with_fds(&self.fds, || command.spawn())?
+                .context(SpawnProcess {
+                    cmd: self.cmd.clone(),
+                })?;
+            // std doesn't have a way to wait for a child asynchronously
+            let (send, recv) = futures::sync::oneshot::channel();
+            std::thread::spawn(move || {
+                // the receiver only goes away if we stopped caring
+                let _ = send.send(child.wait());
+            });
+            self.exit = Some(recv);
+            return Ok(futures::Async::Ready(Some(
+                tokio_pty_process_stream::Event::CommandStart {
+                    cmd: self.cmd.clone(),
+                    args: self.args.clone(),
+                },
+            )));
+        }
+
+        if self.exited {
+            return Ok(futures::Async::Ready(None));
+        }
+
+        match self.exit.as_mut().unwrap().poll() {
+            Ok(futures::Async::Ready(status)) => {
+                let status = status.context(ProcessExitPoll)?;
+                self.exited = true;
+                Ok(futures::Async::Ready(Some(
+                    tokio_pty_process_stream::Event::CommandExit { status },
+                )))
+            }
+            Ok(futures::Async::NotReady) => Ok(futures::Async::NotReady),
+            // the waiting thread panicked, which shouldn't be possible
+            Err(futures::sync::oneshot::Canceled) => unreachable!(),
+        }
+    }
+}
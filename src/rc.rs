@@ -0,0 +1,77 @@
+use futures::future::Future as _;
+use futures::stream::Stream as _;
+use std::io::Write as _;
+
+fn path() -> Option<std::path::PathBuf> {
+    std::env::var_os("HOME")
+        .map(|home| std::path::Path::new(&home).join(".nbshrc"))
+}
+
+// runs ~/.nbshrc. the whole file is parsed up front, so that things like
+// function definitions can span multiple lines, but each command is then
+// run on its own so that one failing doesn't skip the rest of the file.
+// errors are reported, but don't prevent the shell from starting.
+pub fn load() -> impl futures::future::Future<Item = (), Error = ()> {
+    let script = path().and_then(|path| {
+        std::fs::read_to_string(&path)
+            .ok()
+            .map(|contents| (path.to_string_lossy().into_owned(), contents))
+    });
+    let Some((path, contents)) = script else {
+        return futures::future::Either::A(futures::future::ok(()));
+    };
+
+    let program = match crate::parser::parse(&contents) {
+        Ok(program) => program,
+        Err(e) => {
+            report(&e.report(&path, &contents));
+            return futures::future::Either::A(futures::future::ok(()));
+        }
+    };
+
+    let span = program.span;
+    futures::future::Either::B(
+        futures::stream::iter_ok(program.lists)
+            // `exit` stops reading the rc file too
+            .take_while(|_| Ok(!crate::env::exit_requested()))
+            .for_each(move |list| {
+                let path = path.clone();
+                let program = crate::ast::Program {
+                    lists: vec![list],
+                    span,
+                };
+                crate::eval::Eval::program(program)
+                    .for_each(|event| {
+                        print(&event);
+                        Ok(())
+                    })
+                    .then(move |res| {
+                        if let Err(e) = res {
+                            report(&format!("{path}: {e}\n"));
+                        }
+                        Ok(())
+                    })
+            }),
+    )
+}
+
+fn report(message: &str) {
+    let stderr = std::io::stderr();
+    let mut stderr = stderr.lock();
+    // nothing useful to do if this fails
+    let _ = stderr.write_all(message.replace('\n', "\r\n").as_bytes());
+    let _ = stderr.flush();
+}
+
+fn print(event: &tokio_pty_process_stream::Event) {
+    match event {
+        tokio_pty_process_stream::Event::Output { data } => {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            // nothing useful to do if this fails
+            let _ = stdout.write_all(data);
+            let _ = stdout.flush();
+        }
+        _ => {}
+    }
+}
@@ -1,5 +1,6 @@
 use futures::stream::Stream as _;
 use snafu::ResultExt as _;
+use std::fmt::Write as _;
 use std::io::Write as _;
 
 #[derive(Debug, snafu::Snafu)]
@@ -8,7 +9,7 @@ pub enum Error {
     WriteToTerminal { source: std::io::Error },
 
     #[snafu(display("end of input"))]
-    EOF,
+    Eof,
 
     #[snafu(display(
         "failed to put the terminal into raw mode: {}",
@@ -16,31 +17,99 @@ pub enum Error {
     ))]
     IntoRawMode { source: crossterm::ErrorKind },
 
+    #[snafu(display(
+        "failed to take the terminal out of raw mode: {}",
+        source
+    ))]
+    FromRawMode { source: crossterm::ErrorKind },
+
     #[snafu(display("{}", source))]
     KeyReader { source: crate::key_reader::Error },
+
+    #[snafu(display("failed to create temporary file: {}", source))]
+    CreateTempFile { source: nix::Error },
+
+    #[snafu(display(
+        "failed to access temporary file {}: {}",
+        path,
+        source
+    ))]
+    TempFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("failed to run editor `{}`: {}", editor, source))]
+    RunEditor {
+        editor: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("{}", source))]
+    HistoryExpansion { source: crate::history::Error },
+
+    #[snafu(display("failed to restore terminal settings: {}", source))]
+    RestoreTerminal { source: nix::Error },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 pub fn readline() -> Readline {
-    Readline::new()
+    Readline::new().prompt(&crate::eval::prompt())
 }
 
+#[must_use = "futures do nothing unless polled"]
 pub struct Readline {
     reader: crate::key_reader::KeyReader,
     state: ReadlineState,
     raw_screen: Option<crossterm::screen::RawScreen>,
 }
 
+// the settings are independent of each other, since they come from
+// separate builder methods
+#[allow(clippy::struct_excessive_bools)]
 struct ReadlineState {
     prompt: String,
+    continuation_prompt: String,
     echo: bool,
     output: bool,
     manage_screen: bool,
+    history: bool,
 
     buffer: String,
     cursor: usize,
     wrote_prompt: bool,
+    pending_keys: Vec<crossterm::input::KeyEvent>,
+    history_idx: Option<usize>,
+    saved_buffer: String,
+    edit: Option<EditMode>,
+    // which entries moving through and searching the history considers
+    filter: crate::history::Filter,
+    search: Option<Search>,
+    finder: Option<crate::finder::Finder>,
+}
+
+// the state of an incremental history search, which replaces the prompt
+// and buffer while it is active
+struct Search {
+    query: String,
+    // the matching entry, if anything has matched yet
+    idx: Option<usize>,
+    // set if nothing matches the query, in which case the last match is
+    // still shown
+    failed: bool,
+    // restored if the search is cancelled
+    saved_buffer: String,
+    saved_cursor: usize,
+    // the number of extra rows taken up by the match, which is needed to
+    // redraw it
+    rows: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditMode {
+    Load,
+    Execute,
 }
 
 impl Readline {
@@ -49,18 +118,26 @@ impl Readline {
             reader: crate::key_reader::KeyReader::new(),
             state: ReadlineState {
                 prompt: String::from("$ "),
+                continuation_prompt: String::from("> "),
                 echo: true,
                 output: true,
                 manage_screen: true,
+                history: true,
                 buffer: String::new(),
                 cursor: 0,
                 wrote_prompt: false,
+                pending_keys: vec![],
+                history_idx: None,
+                saved_buffer: String::new(),
+                edit: None,
+                filter: crate::history::Filter::from_env(),
+                search: None,
+                finder: None,
             },
             raw_screen: None,
         }
     }
 
-    #[allow(dead_code)]
     pub fn prompt(mut self, prompt: &str) -> Self {
         self.state.prompt = prompt.to_string();
         self
@@ -78,6 +155,13 @@ impl Readline {
         self
     }
 
+    // answers to questions like confirmation prompts shouldn't end up in
+    // the history
+    pub fn history(mut self, history: bool) -> Self {
+        self.state.history = history;
+        self
+    }
+
     pub fn set_raw(mut self, raw: bool) -> Self {
         self.state.manage_screen = raw;
         self
@@ -87,18 +171,111 @@ impl Readline {
     pub fn cursor_pos(&self) -> usize {
         self.state.cursor
     }
+
+    // runs $VISUAL or $EDITOR on a file containing the current buffer.
+    // returns the line to execute, if the edited contents should be run
+    // immediately.
+    fn edit_in_editor(&mut self, mode: EditMode) -> Result<Option<String>> {
+        let editor = std::env::var("VISUAL")
+            .or_else(|_| std::env::var("EDITOR"))
+            .unwrap_or_else(|_| "vi".to_string());
+
+        let template = std::env::temp_dir().join("nbsh-edit-XXXXXX");
+        let (fd, path) =
+            nix::unistd::mkstemp(&template).context(CreateTempFile)?;
+        let path_str = path.to_string_lossy().into_owned();
+        let _ = nix::unistd::close(fd);
+        std::fs::write(&path, format!("{}\n", self.state.buffer))
+            .context(TempFile { path: &path_str })?;
+
+        // the editor needs the terminal to itself, so stop reading input
+        // and put the terminal back the way we found it until it exits
+        self.reader = crate::key_reader::KeyReader::new();
+        if self.raw_screen.take().is_none() {
+            crossterm::screen::RawScreen::disable_raw_mode()
+                .context(FromRawMode)?;
+        }
+        let termios = nix::sys::termios::tcgetattr(0);
+
+        let mut words = editor.split_whitespace();
+        let status = std::process::Command::new(words.next().unwrap_or("vi"))
+            .args(words)
+            .arg(&path)
+            .status();
+
+        // restore the terminal even if the editor failed or crashed, since
+        // it may have left the terminal in an arbitrary state
+        if let Ok(termios) = &termios {
+            nix::sys::termios::tcsetattr(
+                0,
+                nix::sys::termios::SetArg::TCSANOW,
+                termios,
+            )
+            .context(RestoreTerminal)?;
+        }
+        if self.state.manage_screen {
+            self.raw_screen = Some(
+                crossterm::screen::RawScreen::into_raw_mode()
+                    .context(IntoRawMode)?,
+            );
+        } else {
+            crossterm::screen::RawScreen::into_raw_mode()
+                .context(IntoRawMode)?
+                .keep_raw_mode_on_drop();
+        }
+
+        let contents = std::fs::read_to_string(&path);
+        let _ = std::fs::remove_file(&path);
+        let status = status.context(RunEditor { editor })?;
+        if !status.success() {
+            self.state.redraw(0).context(WriteToTerminal)?;
+            return Ok(None);
+        }
+        let contents = contents.context(TempFile { path: path_str })?;
+        let contents = contents.strip_suffix('\n').unwrap_or(&contents);
+
+        self.state.buffer = contents.to_string();
+        self.state.cursor = self.state.buffer.len();
+        self.state.history_idx = None;
+        self.state.redraw(0).context(WriteToTerminal)?;
+
+        if mode == EditMode::Execute {
+            if let futures::Async::Ready(line) = self
+                .state
+                .run_action(crate::keybindings::Action::AcceptLine)?
+            {
+                return Ok(Some(line));
+            }
+        }
+        Ok(None)
+    }
 }
 
 impl ReadlineState {
     fn process_event(
         &mut self,
-        event: &crossterm::input::InputEvent,
+        event: &crate::key_reader::Event,
     ) -> Result<futures::Async<String>> {
         match event {
-            crossterm::input::InputEvent::Keyboard(e) => {
+            crate::key_reader::Event::Key(e) => {
                 return self.process_keyboard_event(*e);
             }
-            _ => {}
+            crate::key_reader::Event::Paste(text) => {
+                if let Some(search) = &mut self.search {
+                    search.query.push_str(&text.replace('\n', " "));
+                    // the current match may still match, like when typing
+                    let idx = search
+                        .idx
+                        .map_or_else(crate::history::len, |idx| idx + 1);
+                    self.search_from(idx);
+                    self.draw_search().context(WriteToTerminal)?;
+                } else if let Some(finder) = &mut self.finder {
+                    finder.push_str(&text.replace('\n', " "));
+                    self.draw_finder().context(WriteToTerminal)?;
+                } else {
+                    self.paste(text).context(WriteToTerminal)?;
+                }
+            }
         }
 
         Ok(futures::Async::NotReady)
@@ -108,66 +285,139 @@ impl ReadlineState {
         &mut self,
         event: crossterm::input::KeyEvent,
     ) -> Result<futures::Async<String>> {
-        match event {
-            crossterm::input::KeyEvent::Char('\n') => {
+        if self.search.is_some() {
+            return self.process_search_event(event);
+        }
+        if self.finder.is_some() {
+            return self.process_finder_event(event);
+        }
+        self.pending_keys.push(event);
+        match crate::keybindings::lookup(&self.pending_keys) {
+            crate::keybindings::Lookup::Action(action) => {
+                self.pending_keys.clear();
+                return self.run_action(action);
+            }
+            crate::keybindings::Lookup::Prefix => {}
+            crate::keybindings::Lookup::Unbound => {
+                let keys = std::mem::take(&mut self.pending_keys);
+                match keys.as_slice() {
+                    [crossterm::input::KeyEvent::Char(c)] => {
+                        self.self_insert(*c).context(WriteToTerminal)?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(futures::Async::NotReady)
+    }
+
+    fn run_action(
+        &mut self,
+        action: crate::keybindings::Action,
+    ) -> Result<futures::Async<String>> {
+        use crate::keybindings::Action;
+
+        match action {
+            Action::AcceptLine => {
+                self.move_cursor(self.cursor, self.buffer.len())
+                    .context(WriteToTerminal)?;
                 self.echo_char('\n').context(WriteToTerminal)?;
+                // things like here-documents and unterminated quotes
+                // continue onto the next line
+                if crate::parser::is_incomplete(&self.buffer) {
+                    self.buffer.push('\n');
+                    self.cursor = self.buffer.len();
+                    self.write(self.continuation_prompt.as_bytes())
+                        .context(WriteToTerminal)?;
+                    return Ok(futures::Async::NotReady);
+                }
+                if self.history {
+                    // show what is actually going to be run
+                    if let Some(expanded) =
+                        crate::history::expand(&self.buffer)
+                            .context(HistoryExpansion)?
+                    {
+                        self.buffer = expanded;
+                        self.write(
+                            self.buffer.replace('\n', "\r\n").as_bytes(),
+                        )
+                        .and_then(|()| self.write(b"\r\n"))
+                        .context(WriteToTerminal)?;
+                    }
+                    crate::history::add(&self.buffer);
+                }
                 return Ok(futures::Async::Ready(self.buffer.clone()));
             }
-            crossterm::input::KeyEvent::Char('\t') => {
-                // TODO
+            Action::BeginningOfLine => {
+                self.move_cursor(self.cursor, 0).context(WriteToTerminal)?;
+                self.cursor = 0;
             }
-            crossterm::input::KeyEvent::Char(c) => {
-                if self.cursor != self.buffer.len() {
-                    self.echo(b"\x1b[@").context(WriteToTerminal)?;
+            Action::EditAndExecuteCommand => {
+                self.edit = Some(EditMode::Execute);
+            }
+            Action::EditCommandLine => {
+                self.edit = Some(EditMode::Load);
+            }
+            Action::EndOfLine => {
+                self.move_cursor(self.cursor, self.buffer.len())
+                    .context(WriteToTerminal)?;
+                self.cursor = self.buffer.len();
+            }
+            Action::BackwardChar => {
+                if self.cursor != 0 {
+                    let prev = self.prev_char_boundary();
+                    self.move_cursor(self.cursor, prev)
+                        .context(WriteToTerminal)?;
+                    self.cursor = prev;
                 }
-                self.echo_char(c).context(WriteToTerminal)?;
-                self.buffer.insert(self.cursor, c);
-                self.cursor += 1;
             }
-            crossterm::input::KeyEvent::Ctrl(c) => match c {
-                'a' => {
-                    if self.cursor != 0 {
-                        self.echo(
-                            &format!("\x1b[{}D", self.cursor).into_bytes(),
-                        )
+            Action::ForwardChar => {
+                if self.cursor != self.buffer.len() {
+                    let next = self.next_char_boundary();
+                    self.move_cursor(self.cursor, next)
                         .context(WriteToTerminal)?;
-                        self.cursor = 0;
-                    }
+                    self.cursor = next;
                 }
-                'c' => {
-                    self.buffer = String::new();
-                    self.cursor = 0;
+            }
+            Action::BackwardDeleteChar => {
+                self.backward_delete_char().context(WriteToTerminal)?;
+            }
+            Action::DeleteChar => {
+                self.delete_char().context(WriteToTerminal)?;
+            }
+            Action::DeleteCharOrEof => {
+                if self.buffer.is_empty() {
                     self.echo_char('\n').context(WriteToTerminal)?;
-                    self.prompt().context(WriteToTerminal)?;
-                }
-                'd' => {
-                    if self.buffer.is_empty() {
-                        self.echo_char('\n').context(WriteToTerminal)?;
-                        return EOF.fail();
-                    }
+                    return Eof.fail();
                 }
-                'e' => {
-                    if self.cursor != self.buffer.len() {
-                        self.echo(
-                            &format!(
-                                "\x1b[{}C",
-                                self.buffer.len() - self.cursor
-                            )
-                            .into_bytes(),
-                        )
-                        .context(WriteToTerminal)?;
-                        self.cursor = self.buffer.len();
+                self.delete_char().context(WriteToTerminal)?;
+            }
+            Action::KillLine => {
+                if self.cursor != self.buffer.len() {
+                    let (row, _) = self.position(self.cursor);
+                    let multiline = self.is_multiline();
+                    self.buffer.truncate(self.cursor);
+                    if multiline {
+                        self.redraw(row).context(WriteToTerminal)?;
+                    } else {
+                        self.echo(b"\x1b[K").context(WriteToTerminal)?;
                     }
                 }
-                'u' => {
-                    if self.cursor != 0 {
+            }
+            Action::UnixLineDiscard => {
+                if self.cursor != 0 {
+                    if self.is_multiline() {
+                        let (row, _) = self.position(self.cursor);
+                        self.buffer = self.buffer.split_off(self.cursor);
+                        self.cursor = 0;
+                        self.redraw(row).context(WriteToTerminal)?;
+                    } else {
+                        let width =
+                            self.buffer[..self.cursor].chars().count();
                         self.echo(
-                            std::iter::repeat(b'\x08')
-                                .take(self.cursor)
-                                .chain(
-                                    format!("\x1b[{}P", self.cursor)
-                                        .into_bytes(),
-                                )
+                            std::iter::repeat_n(b'\x08', width)
+                                .chain(format!("\x1b[{width}P").into_bytes())
                                 .collect::<Vec<_>>()
                                 .as_ref(),
                         )
@@ -176,43 +426,641 @@ impl ReadlineState {
                         self.cursor = 0;
                     }
                 }
-                _ => {}
-            },
-            crossterm::input::KeyEvent::Backspace => {
-                if self.cursor != 0 {
-                    self.cursor -= 1;
-                    self.buffer.remove(self.cursor);
-                    if self.cursor == self.buffer.len() {
-                        self.echo(b"\x08 \x08").context(WriteToTerminal)?;
-                    } else {
-                        self.echo(b"\x08\x1b[P").context(WriteToTerminal)?;
-                    }
+            }
+            Action::BackwardKillWord => {
+                let before = &self.buffer[..self.cursor];
+                let word_start = before
+                    .trim_end()
+                    .rfind(char::is_whitespace)
+                    .map_or(0, |idx| {
+                        idx + before[idx..]
+                            .chars()
+                            .next()
+                            .map_or(1, char::len_utf8)
+                    });
+                if word_start != self.cursor {
+                    let mut buffer = self.buffer.clone();
+                    buffer.replace_range(word_start..self.cursor, "");
+                    self.set_buffer(buffer, word_start)
+                        .context(WriteToTerminal)?;
                 }
             }
-            crossterm::input::KeyEvent::Left => {
-                if self.cursor != 0 {
-                    self.cursor -= 1;
-                    self.write(b"\x1b[D").context(WriteToTerminal)?;
+            Action::CancelLine => {
+                self.move_cursor(self.cursor, self.buffer.len())
+                    .context(WriteToTerminal)?;
+                self.buffer = String::new();
+                self.cursor = 0;
+                self.history_idx = None;
+                self.echo_char('\n').context(WriteToTerminal)?;
+                self.prompt().context(WriteToTerminal)?;
+            }
+            Action::ClearScreen => {
+                self.write(b"\x1b[H\x1b[2J").context(WriteToTerminal)?;
+                self.redraw(0).context(WriteToTerminal)?;
+            }
+            // lines which were run several times in a row are only shown
+            // once
+            Action::PreviousHistory => {
+                let idx =
+                    self.history_idx.unwrap_or_else(crate::history::len);
+                let query = crate::history::Query::new()
+                    .filter(self.filter)
+                    .exclude(&self.buffer);
+                if let Some(idx) = crate::history::find_before(&query, idx) {
+                    self.load_history(idx, None).context(WriteToTerminal)?;
                 }
             }
-            crossterm::input::KeyEvent::Right => {
-                if self.cursor != self.buffer.len() {
-                    self.cursor += 1;
-                    self.write(b"\x1b[C").context(WriteToTerminal)?;
+            Action::NextHistory => {
+                if let Some(idx) = self.history_idx {
+                    let query = crate::history::Query::new()
+                        .filter(self.filter)
+                        .exclude(&self.buffer);
+                    let idx = crate::history::find_after(&query, idx)
+                        .unwrap_or_else(crate::history::len);
+                    self.load_history(idx, None).context(WriteToTerminal)?;
                 }
             }
-            crossterm::input::KeyEvent::Delete => {
-                if self.cursor != self.buffer.len() {
-                    self.buffer.remove(self.cursor);
-                    self.echo(b"\x1b[P").context(WriteToTerminal)?;
+            Action::HistorySearchBackward => {
+                let prefix = self.buffer[..self.cursor].to_string();
+                let start =
+                    self.history_idx.unwrap_or_else(crate::history::len);
+                let query = crate::history::Query::new()
+                    .filter(self.filter)
+                    .prefix(&prefix)
+                    .exclude(&self.buffer);
+                if let Some(idx) = crate::history::find_before(&query, start)
+                {
+                    self.load_history(idx, Some(prefix.len()))
+                        .context(WriteToTerminal)?;
+                }
+            }
+            Action::Complete => {
+                if self.history {
+                    self.complete().context(WriteToTerminal)?;
+                }
+            }
+            Action::ReverseSearchHistory | Action::ForwardSearchHistory => {
+                if self.history {
+                    self.start_search().context(WriteToTerminal)?;
+                }
+            }
+            // only does anything while searching
+            Action::CycleHistoryFilter => {}
+            // the finders are only useful when entering commands
+            Action::FuzzyFindHistory => {
+                if self.history {
+                    let candidates = crate::finder::history(self.filter);
+                    self.start_finder(
+                        crate::finder::Kind::History,
+                        candidates,
+                    )
+                    .context(WriteToTerminal)?;
+                }
+            }
+            Action::FuzzyFindFile => {
+                if self.history {
+                    let candidates = crate::finder::files(
+                        &std::env::current_dir().unwrap_or_default(),
+                    );
+                    self.start_finder(crate::finder::Kind::File, candidates)
+                        .context(WriteToTerminal)?;
+                }
+            }
+            Action::FuzzyFindDirectory => {
+                if self.history {
+                    let candidates = crate::finder::directories(
+                        &std::env::current_dir().unwrap_or_default(),
+                    );
+                    self.start_finder(
+                        crate::finder::Kind::Directory,
+                        candidates,
+                    )
+                    .context(WriteToTerminal)?;
+                }
+            }
+            Action::HistorySearchForward => {
+                if let Some(start) = self.history_idx {
+                    let prefix = self.buffer[..self.cursor].to_string();
+                    let query = crate::history::Query::new()
+                        .filter(self.filter)
+                        .prefix(&prefix)
+                        .exclude(&self.buffer);
+                    let idx = crate::history::find_after(&query, start)
+                        .unwrap_or_else(crate::history::len);
+                    self.load_history(idx, Some(prefix.len()))
+                        .context(WriteToTerminal)?;
                 }
             }
-            _ => {}
         }
 
         Ok(futures::Async::NotReady)
     }
 
+    fn process_search_event(
+        &mut self,
+        event: crossterm::input::KeyEvent,
+    ) -> Result<futures::Async<String>> {
+        use crate::keybindings::Action;
+
+        self.pending_keys.push(event);
+        let action = match crate::keybindings::lookup(&self.pending_keys) {
+            crate::keybindings::Lookup::Action(action) => Some(action),
+            crate::keybindings::Lookup::Prefix => {
+                return Ok(futures::Async::NotReady);
+            }
+            crate::keybindings::Lookup::Unbound => None,
+        };
+        let keys = std::mem::take(&mut self.pending_keys);
+        let search = self.search.as_mut().unwrap();
+        match action {
+            // C-r is bound to the history finder by default, but while
+            // searching it still means to search further back
+            Some(Action::ReverseSearchHistory | Action::FuzzyFindHistory) => {
+                self.search_step(true);
+            }
+            Some(Action::ForwardSearchHistory) => self.search_step(false),
+            Some(Action::CycleHistoryFilter) => {
+                self.filter = self.filter.next();
+                self.search_restart();
+            }
+            Some(Action::BackwardDeleteChar) => {
+                search.query.pop();
+                self.search_restart();
+            }
+            Some(Action::CancelLine) => {
+                self.end_search(false).context(WriteToTerminal)?;
+                return Ok(futures::Async::NotReady);
+            }
+            // anything else uses the match as the buffer, and then acts on
+            // it as usual
+            Some(action) => {
+                self.end_search(true).context(WriteToTerminal)?;
+                return self.run_action(action);
+            }
+            None => {
+                if let [crossterm::input::KeyEvent::Char(c)] = keys.as_slice()
+                {
+                    search.query.push(*c);
+                    // the current match may still match
+                    let idx = search
+                        .idx
+                        .map_or_else(crate::history::len, |idx| idx + 1);
+                    self.search_from(idx);
+                }
+            }
+        }
+        self.draw_search().context(WriteToTerminal)?;
+        Ok(futures::Async::NotReady)
+    }
+
+    fn start_search(&mut self) -> std::io::Result<()> {
+        let (row, _) = self.position(self.cursor);
+        if row > 0 {
+            self.write(format!("\x1b[{row}A").as_bytes())?;
+        }
+        self.search = Some(Search {
+            query: String::new(),
+            idx: None,
+            failed: false,
+            saved_buffer: self.buffer.clone(),
+            saved_cursor: self.cursor,
+            rows: 0,
+        });
+        self.draw_search()
+    }
+
+    // searches backward from the given index (exclusive)
+    fn search_from(&mut self, idx: usize) {
+        let search = self.search.as_mut().unwrap();
+        if search.query.is_empty() {
+            search.idx = None;
+            search.failed = false;
+            return;
+        }
+        let query = crate::history::Query::new()
+            .filter(self.filter)
+            .contains(&search.query);
+        match crate::history::find_before(&query, idx) {
+            Some(idx) => {
+                search.idx = Some(idx);
+                search.failed = false;
+            }
+            None => search.failed = true,
+        }
+    }
+
+    // searches again from the most recent entry, after the query or the
+    // filter changed
+    fn search_restart(&mut self) {
+        self.search.as_mut().unwrap().idx = None;
+        self.search_from(crate::history::len());
+    }
+
+    // moves to the next older or newer match, skipping any which are the
+    // same as the current one
+    fn search_step(&mut self, backward: bool) {
+        let search = self.search.as_mut().unwrap();
+        if search.query.is_empty() {
+            return;
+        }
+        let mut query = crate::history::Query::new()
+            .filter(self.filter)
+            .contains(&search.query);
+        if let Some(line) = search.idx.and_then(crate::history::get) {
+            query = query.exclude(&line);
+        }
+        let found = if backward {
+            crate::history::find_before(
+                &query,
+                search.idx.unwrap_or_else(crate::history::len),
+            )
+        } else {
+            search
+                .idx
+                .and_then(|idx| crate::history::find_after(&query, idx))
+        };
+        match found {
+            Some(idx) => {
+                search.idx = Some(idx);
+                search.failed = false;
+            }
+            None => search.failed = true,
+        }
+    }
+
+    // the terminal cursor is expected to be at the end of whatever was
+    // drawn last
+    fn draw_search(&mut self) -> std::io::Result<()> {
+        let search = self.search.as_ref().unwrap();
+        let line =
+            search.idx.and_then(crate::history::get).unwrap_or_default();
+        let mut buf = String::new();
+        if search.rows > 0 {
+            write!(buf, "\x1b[{}A", search.rows).unwrap();
+        }
+        buf.push_str("\r\x1b[J");
+        write!(
+            buf,
+            "({}{} search)'{}': {}",
+            if search.failed { "failing " } else { "" },
+            self.filter.name(),
+            search.query,
+            line.replace('\n', "\r\n")
+        )
+        .unwrap();
+        let rows = line.matches('\n').count();
+        self.search.as_mut().unwrap().rows = rows;
+        self.write(buf.as_bytes())
+    }
+
+    // if keep is true, the match becomes the buffer, otherwise the buffer
+    // from before the search is restored
+    fn end_search(&mut self, keep: bool) -> std::io::Result<()> {
+        let search = self.search.take().unwrap();
+        if search.rows > 0 {
+            self.write(format!("\x1b[{}A", search.rows).as_bytes())?;
+        }
+        self.write(b"\r\x1b[J")?;
+        let found = search
+            .idx
+            .filter(|_| keep)
+            .and_then(|idx| crate::history::get(idx).map(|line| (idx, line)));
+        if let Some((idx, line)) = found {
+            if self.history_idx.is_none() {
+                self.saved_buffer = search.saved_buffer;
+            }
+            self.history_idx = Some(idx);
+            self.cursor = line.len();
+            self.buffer = line;
+        } else {
+            self.buffer = search.saved_buffer;
+            self.cursor = search.saved_cursor;
+        }
+        self.redraw(0)
+    }
+
+    fn process_finder_event(
+        &mut self,
+        event: crossterm::input::KeyEvent,
+    ) -> Result<futures::Async<String>> {
+        use crate::keybindings::Action;
+
+        self.pending_keys.push(event);
+        let action = match crate::keybindings::lookup(&self.pending_keys) {
+            crate::keybindings::Lookup::Action(action) => Some(action),
+            crate::keybindings::Lookup::Prefix => {
+                return Ok(futures::Async::NotReady);
+            }
+            crate::keybindings::Lookup::Unbound => None,
+        };
+        let keys = std::mem::take(&mut self.pending_keys);
+        let finder = self.finder.as_mut().unwrap();
+        match action {
+            Some(Action::AcceptLine) => return self.accept_finder(),
+            Some(Action::CancelLine) => {
+                self.end_finder().context(WriteToTerminal)?;
+                return Ok(futures::Async::NotReady);
+            }
+            Some(Action::PreviousHistory) => finder.up(),
+            Some(Action::NextHistory) => finder.down(),
+            Some(Action::BackwardDeleteChar) => finder.pop(),
+            Some(Action::BackwardKillWord) => finder.backward_kill_word(),
+            Some(Action::UnixLineDiscard) => finder.clear_query(),
+            Some(Action::CycleHistoryFilter) => {
+                if finder.kind() == crate::finder::Kind::History {
+                    self.filter = self.filter.next();
+                    finder
+                        .set_candidates(crate::finder::history(self.filter));
+                }
+            }
+            Some(_) => {}
+            None => match keys.as_slice() {
+                [crossterm::input::KeyEvent::Char(c)] => {
+                    finder.push_str(c.encode_utf8(&mut [0_u8; 4]));
+                }
+                [crossterm::input::KeyEvent::Esc
+                | crossterm::input::KeyEvent::Ctrl('g')] => {
+                    self.end_finder().context(WriteToTerminal)?;
+                    return Ok(futures::Async::NotReady);
+                }
+                _ => {}
+            },
+        }
+        self.draw_finder().context(WriteToTerminal)?;
+        Ok(futures::Async::NotReady)
+    }
+
+    // the finder is drawn in place of the prompt and buffer, which are
+    // redrawn when it ends
+    fn start_finder(
+        &mut self,
+        kind: crate::finder::Kind,
+        candidates: Vec<String>,
+    ) -> std::io::Result<()> {
+        let (row, _) = self.position(self.cursor);
+        if row > 0 {
+            self.write(format!("\x1b[{row}A").as_bytes())?;
+        }
+        self.finder = Some(crate::finder::Finder::new(kind, candidates));
+        self.draw_finder()
+    }
+
+    fn draw_finder(&mut self) -> std::io::Result<()> {
+        let finder = self.finder.as_mut().unwrap();
+        let info = if finder.kind() == crate::finder::Kind::History {
+            format!("{} ({})", finder.kind().name(), self.filter.name())
+        } else {
+            finder.kind().name().to_string()
+        };
+        let (cols, rows) = crate::finder::terminal_size();
+        let buf = finder.render(&info, cols, rows);
+        self.write(buf.as_bytes())
+    }
+
+    fn end_finder(&mut self) -> std::io::Result<()> {
+        self.finder = None;
+        self.write(b"\r\x1b[J")?;
+        self.redraw(0)
+    }
+
+    // history lines replace the buffer, files are inserted at the cursor,
+    // and directories are changed to immediately
+    fn accept_finder(&mut self) -> Result<futures::Async<String>> {
+        let finder = self.finder.as_ref().unwrap();
+        let kind = finder.kind();
+        let selected = finder.selected().map(ToString::to_string);
+        self.end_finder().context(WriteToTerminal)?;
+        let Some(selected) = selected else {
+            return Ok(futures::Async::NotReady);
+        };
+        match kind {
+            crate::finder::Kind::History => {
+                self.history_idx = None;
+                let cursor = selected.len();
+                self.set_buffer(selected, cursor).context(WriteToTerminal)?;
+            }
+            crate::finder::Kind::File => {
+                let word = format!("{} ", crate::parser::quote(&selected));
+                let mut buffer = self.buffer.clone();
+                buffer.insert_str(self.cursor, &word);
+                let cursor = self.cursor + word.len();
+                self.set_buffer(buffer, cursor).context(WriteToTerminal)?;
+            }
+            crate::finder::Kind::Directory => {
+                self.history_idx = None;
+                let line = format!("cd {}", crate::parser::quote(&selected));
+                let cursor = line.len();
+                self.set_buffer(line, cursor).context(WriteToTerminal)?;
+                return self
+                    .run_action(crate::keybindings::Action::AcceptLine);
+            }
+        }
+        Ok(futures::Async::NotReady)
+    }
+
+    fn self_insert(&mut self, c: char) -> std::io::Result<()> {
+        if self.cursor != self.buffer.len() {
+            self.echo(b"\x1b[@")?;
+        }
+        self.echo_char(c)?;
+        self.buffer.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+        Ok(())
+    }
+
+    fn backward_delete_char(&mut self) -> std::io::Result<()> {
+        if self.cursor != 0 {
+            if self.is_multiline() {
+                let (row, _) = self.position(self.cursor);
+                self.cursor = self.prev_char_boundary();
+                self.buffer.remove(self.cursor);
+                self.redraw(row)?;
+            } else {
+                self.cursor = self.prev_char_boundary();
+                self.buffer.remove(self.cursor);
+                if self.cursor == self.buffer.len() {
+                    self.echo(b"\x08 \x08")?;
+                } else {
+                    self.echo(b"\x08\x1b[P")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn delete_char(&mut self) -> std::io::Result<()> {
+        if self.cursor != self.buffer.len() {
+            if self.is_multiline() {
+                let (row, _) = self.position(self.cursor);
+                self.buffer.remove(self.cursor);
+                self.redraw(row)?;
+            } else {
+                self.buffer.remove(self.cursor);
+                self.echo(b"\x1b[P")?;
+            }
+        }
+        Ok(())
+    }
+
+    // idx == history::len() means the line that was being edited before we
+    // started moving through the history. if cursor is None, the cursor is
+    // moved to the end of the line.
+    fn load_history(
+        &mut self,
+        idx: usize,
+        cursor: Option<usize>,
+    ) -> std::io::Result<()> {
+        if self.history_idx.is_none() {
+            self.saved_buffer = self.buffer.clone();
+        }
+        let buffer = if let Some(line) = crate::history::get(idx) {
+            self.history_idx = Some(idx);
+            line
+        } else {
+            self.history_idx = None;
+            std::mem::take(&mut self.saved_buffer)
+        };
+        let cursor = cursor.unwrap_or(buffer.len());
+        self.set_buffer(buffer, cursor)
+    }
+
+    // replaces the word before the cursor with its completion, or with the
+    // longest prefix shared by all of the candidates. if that doesn't add
+    // anything, the candidates are listed below the buffer instead.
+    fn complete(&mut self) -> std::io::Result<()> {
+        let completion =
+            crate::completion::complete(&self.buffer, self.cursor);
+        let word = &self.buffer[completion.start..self.cursor];
+        if let Some(replacement) = completion.replacement(word) {
+            let mut buffer = self.buffer.clone();
+            buffer.replace_range(completion.start..self.cursor, &replacement);
+            let cursor = completion.start + replacement.len();
+            return self.set_buffer(buffer, cursor);
+        }
+        if completion.candidates.len() < 2 {
+            return Ok(());
+        }
+
+        let (cols, _) = crate::finder::terminal_size();
+        let width = completion
+            .candidates
+            .iter()
+            .map(|candidate| candidate.chars().count() + 2)
+            .max()
+            .unwrap_or(1);
+        let per_row = (usize::from(cols) / width).max(1);
+        let mut buf = String::new();
+        for (i, candidate) in completion.candidates.iter().enumerate() {
+            buf.push_str(if i % per_row == 0 { "\r\n" } else { "" });
+            write!(buf, "{candidate:width$}").unwrap();
+        }
+        buf.push_str("\r\n");
+        self.move_cursor(self.cursor, self.buffer.len())?;
+        self.write(buf.as_bytes())?;
+        self.redraw(0)
+    }
+
+    fn set_buffer(
+        &mut self,
+        buffer: String,
+        cursor: usize,
+    ) -> std::io::Result<()> {
+        let (row, _) = self.position(self.cursor);
+        self.buffer = buffer;
+        self.cursor = cursor;
+        self.redraw(row)
+    }
+
+    // pasted text is inserted literally - in particular, newlines become
+    // part of the buffer rather than submitting it
+    fn paste(&mut self, text: &str) -> std::io::Result<()> {
+        let (row, _) = self.position(self.cursor);
+        let at_end = self.cursor == self.buffer.len();
+        self.buffer.insert_str(self.cursor, text);
+        self.cursor += text.len();
+        if self.is_multiline() {
+            self.redraw(row)
+        } else {
+            if !at_end {
+                self.echo(
+                    &format!("\x1b[{}@", text.chars().count()).into_bytes(),
+                )?;
+            }
+            self.echo(text.as_bytes())
+        }
+    }
+
+    // the cursor is a byte offset into the buffer, so it has to move by
+    // whole characters
+    fn prev_char_boundary(&self) -> usize {
+        self.buffer[..self.cursor]
+            .chars()
+            .next_back()
+            .map_or(self.cursor, |c| self.cursor - c.len_utf8())
+    }
+
+    fn next_char_boundary(&self) -> usize {
+        self.buffer[self.cursor..]
+            .chars()
+            .next()
+            .map_or(self.cursor, |c| self.cursor + c.len_utf8())
+    }
+
+    fn is_multiline(&self) -> bool {
+        self.buffer.contains('\n')
+    }
+
+    // returns the terminal row (relative to the prompt) and column of the
+    // given buffer position
+    fn position(&self, pos: usize) -> (usize, usize) {
+        let before = &self.buffer[..pos];
+        before.rfind('\n').map_or_else(
+            || (0, self.prompt.chars().count() + before.chars().count()),
+            |idx| {
+                (
+                    before.matches('\n').count(),
+                    self.continuation_prompt.chars().count()
+                        + before[idx + 1..].chars().count(),
+                )
+            },
+        )
+    }
+
+    fn move_cursor(&self, from: usize, to: usize) -> std::io::Result<()> {
+        let (from_row, from_col) = self.position(from);
+        let (to_row, to_col) = self.position(to);
+        let mut buf = String::new();
+        if to_row < from_row {
+            write!(buf, "\x1b[{}A", from_row - to_row).unwrap();
+        } else if to_row > from_row {
+            write!(buf, "\x1b[{}B", to_row - from_row).unwrap();
+        }
+        if to_col < from_col {
+            write!(buf, "\x1b[{}D", from_col - to_col).unwrap();
+        } else if to_col > from_col {
+            write!(buf, "\x1b[{}C", to_col - from_col).unwrap();
+        }
+        self.write(buf.as_bytes())
+    }
+
+    // rewrites the prompt and the entire buffer, given that the terminal
+    // cursor is currently on the given row (relative to the prompt)
+    fn redraw(&self, row: usize) -> std::io::Result<()> {
+        if row > 0 {
+            self.write(format!("\x1b[{row}A").as_bytes())?;
+        }
+        self.write(b"\r\x1b[J")?;
+        self.prompt()?;
+        for (i, line) in self.buffer.split('\n').enumerate() {
+            if i > 0 {
+                self.echo(b"\n")?;
+                self.write(self.continuation_prompt.as_bytes())?;
+            }
+            self.echo(line.as_bytes())?;
+        }
+        self.move_cursor(self.buffer.len(), self.cursor)
+    }
+
     fn write(&self, buf: &[u8]) -> std::io::Result<()> {
         if !self.output {
             return Ok(());
@@ -236,10 +1084,8 @@ impl ReadlineState {
                 if c == b'\n' {
                     acc.push(b'\r');
                     acc.push(b'\n');
-                } else {
-                    if self.echo {
-                        acc.push(c);
-                    }
+                } else if self.echo {
+                    acc.push(c);
                 }
                 acc
             });
@@ -261,7 +1107,6 @@ impl std::fmt::Display for Readline {
     }
 }
 
-#[must_use = "futures do nothing unless polled"]
 impl futures::future::Future for Readline {
     type Item = String;
     type Error = Error;
@@ -270,6 +1115,9 @@ impl futures::future::Future for Readline {
         if !self.state.wrote_prompt {
             self.state.prompt().context(WriteToTerminal)?;
             self.state.wrote_prompt = true;
+            // rebuild the index of executables (if needed) while the user
+            // is still typing, rather than when the command is run
+            crate::executables::refresh();
         }
 
         if self.state.manage_screen && self.raw_screen.is_none() {
@@ -287,9 +1135,13 @@ impl futures::future::Future for Readline {
                 if a.is_ready() {
                     return Ok(a);
                 }
+                if let Some(mode) = self.state.edit.take() {
+                    if let Some(line) = self.edit_in_editor(mode)? {
+                        return Ok(futures::Async::Ready(line));
+                    }
+                }
             } else {
-                eprintln!("EEEOOOFFF");
-                return Err(Error::EOF);
+                return Err(Error::Eof);
             }
         }
     }
@@ -14,46 +14,125 @@ enum Error {
 
     #[snafu(display("error during print: {}", source))]
     Print { source: std::io::Error },
+
+    #[snafu(display("exit"))]
+    Exit,
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
+// only panics if an error can't be written to stderr
+#[allow(clippy::missing_panics_doc)]
 pub fn repl() {
-    tokio::run(futures::future::loop_fn((), |_| {
-        read()
-            .and_then(|line| eval(&line).for_each(|event| print(&event)))
-            .then(|res| match res {
-                // successful run or empty input means prompt again
-                Ok(_)
-                | Err(Error::Eval {
-                    source:
-                        crate::eval::Error::Parser {
-                            source: crate::parser::Error::CommandRequired,
-                            ..
-                        },
-                }) => Ok(futures::future::Loop::Continue(())),
-                // eof means we're done
-                Err(Error::Read {
-                    source: crate::readline::Error::EOF,
-                }) => Ok(futures::future::Loop::Break(())),
-                // any other errors should be displayed, then we
-                // prompt again
-                Err(e) => {
-                    let stderr = std::io::stderr();
-                    let mut stderr = stderr.lock();
-                    // panics seem fine for errors during error handling
-                    write!(stderr, "{}\r\n", e).unwrap();
-                    stderr.flush().unwrap();
-                    Ok(futures::future::Loop::Continue(()))
-                }
-            })
-    }))
+    tokio::run(crate::rc::load().and_then(|()| {
+        futures::future::loop_fn((), |()| {
+            // `exit` in ~/.nbshrc shouldn't wait for input first
+            let read: Box<
+                dyn futures::future::Future<Item = String, Error = Error>
+                    + Send,
+            > = if crate::env::exit_requested() {
+                Box::new(futures::future::err(Error::Exit))
+            } else {
+                Box::new(read())
+            };
+            read.and_then(run)
+                .then(|res| {
+                    crate::history::finish(crate::env::status());
+                    res
+                })
+                .then(|res| match res {
+                    Ok(()) if crate::env::exit_requested() => {
+                        Ok(futures::future::Loop::Break(()))
+                    }
+                    // successful run means prompt again
+                    Ok(()) => Ok(futures::future::Loop::Continue(())),
+                    // eof means we're done
+                    Err(
+                        Error::Read {
+                            source: crate::readline::Error::Eof,
+                        }
+                        | Error::Exit,
+                    ) => Ok(futures::future::Loop::Break(())),
+                    // any other errors should be displayed, then we
+                    // prompt again
+                    Err(e) => {
+                        let stderr = std::io::stderr();
+                        let mut stderr = stderr.lock();
+                        // panics seem fine for errors during error handling
+                        write!(stderr, "{e}\r\n").unwrap();
+                        if let Some(hint) = hint(&e) {
+                            write!(stderr, "{hint}\r\n").unwrap();
+                        }
+                        stderr.flush().unwrap();
+                        if crate::env::exit_requested() {
+                            return Ok(futures::future::Loop::Break(()));
+                        }
+                        Ok(futures::future::Loop::Continue(()))
+                    }
+                })
+        })
+    }));
+    // `exit` sets the status to exit with
+    if crate::env::exit_requested() {
+        std::process::exit(crate::env::status());
+    }
 }
 
 fn read() -> impl futures::future::Future<Item = String, Error = Error> {
     crate::readline::readline().context(Read)
 }
 
+// runs a command line, offering to rerun it with the command name corrected
+// if it ran a command which doesn't exist because of a typo
+fn run(
+    line: String,
+) -> Box<dyn futures::future::Future<Item = (), Error = Error> + Send> {
+    Box::new(eval(&line).for_each(|event| print(&event)).and_then(
+        move |()| {
+            crate::eval::correction(&line).map_or_else(
+                || futures::future::Either::B(futures::future::ok(())),
+                |correction| {
+                    futures::future::Either::A(
+                        confirm(&correction.prompt()).and_then(move |yes| {
+                            if yes {
+                                futures::future::Either::A(run(
+                                    correction.line
+                                ))
+                            } else {
+                                futures::future::Either::B(
+                                    futures::future::ok(()),
+                                )
+                            }
+                        }),
+                    )
+                },
+            )
+        },
+    ))
+}
+
+// eof or an error while reading the answer counts as no
+fn confirm(
+    prompt: &str,
+) -> impl futures::future::Future<Item = bool, Error = Error> {
+    crate::readline::Readline::new()
+        .prompt(prompt)
+        .history(false)
+        .then(|answer| {
+            Ok(answer.is_ok_and(|answer| {
+                answer.trim().eq_ignore_ascii_case("y")
+                    || answer.trim().eq_ignore_ascii_case("yes")
+            }))
+        })
+}
+
+fn hint(e: &Error) -> Option<String> {
+    match e {
+        Error::Eval { source } => source.hint(),
+        _ => None,
+    }
+}
+
 fn eval(
     line: &str,
 ) -> impl futures::stream::Stream<
@@ -0,0 +1,204 @@
+// figures out what running a given command name would do. like in sh,
+// aliases take precedence over functions, which take precedence over
+// builtins, which take precedence over executables in $PATH. executables
+// which have been found are remembered until `hash -r`.
+
+pub enum Resolution {
+    Alias(String),
+    Function(std::sync::Arc<crate::ast::Command>),
+    Builtin,
+    // hashed is true if the path was remembered from an earlier lookup
+    Executable {
+        path: std::path::PathBuf,
+        hashed: bool,
+    },
+}
+
+static HASH: std::sync::LazyLock<
+    std::sync::Mutex<std::collections::HashMap<String, std::path::PathBuf>>,
+> = std::sync::LazyLock::new(|| {
+    std::sync::Mutex::new(std::collections::HashMap::new())
+});
+
+fn hash() -> std::sync::MutexGuard<
+    'static,
+    std::collections::HashMap<String, std::path::PathBuf>,
+> {
+    HASH.lock().unwrap()
+}
+
+pub fn resolve(name: &str) -> Option<Resolution> {
+    if let Some(value) = crate::aliases::get(name) {
+        return Some(Resolution::Alias(value));
+    }
+    resolve_command(name)
+}
+
+// aliases have already been expanded by the time a command is run, so they
+// aren't considered here
+pub fn resolve_command(name: &str) -> Option<Resolution> {
+    if let Some(body) = crate::env::function(name) {
+        return Some(Resolution::Function(body));
+    }
+    resolve_without_functions(name)
+}
+
+// the lookup done by `command`, which bypasses functions
+pub fn resolve_without_functions(name: &str) -> Option<Resolution> {
+    if crate::builtins::is_builtin(name) {
+        return Some(Resolution::Builtin);
+    }
+    resolve_executable(name)
+        .map(|(path, hashed)| Resolution::Executable { path, hashed })
+}
+
+// the lookup done by `exec`, which only runs executables. also returns
+// whether the path was remembered from an earlier lookup.
+pub fn resolve_executable(name: &str) -> Option<(std::path::PathBuf, bool)> {
+    let hashed = hash().get(name).cloned();
+    // if a remembered executable was removed, look for it again
+    if let Some(path) = hashed.filter(|path| is_executable(path)) {
+        return Some((path, true));
+    }
+    find_executable(name).map(|path| (path, false))
+}
+
+// names with a slash in them are paths, and aren't looked up in $PATH
+fn find_executable(name: &str) -> Option<std::path::PathBuf> {
+    if name.contains('/') {
+        let path = std::path::PathBuf::from(name);
+        return if is_executable(&path) {
+            Some(path)
+        } else {
+            None
+        };
+    }
+    if name.is_empty() {
+        return None;
+    }
+    // the index can miss files which were made executable after it was
+    // built, since that doesn't modify the directory
+    let path = crate::executables::get(name)
+        .filter(|path| is_executable(path))
+        .or_else(|| search_path(name))?;
+    hash().insert(name.to_string(), path.clone());
+    Some(path)
+}
+
+fn search_path(name: &str) -> Option<std::path::PathBuf> {
+    let path_var = crate::env::var("PATH").unwrap_or_default();
+    path_var
+        .split(':')
+        // an empty entry means the current directory
+        .map(|dir| if dir.is_empty() { "." } else { dir })
+        .map(|dir| std::path::Path::new(dir).join(name))
+        .find(|path| is_executable(path))
+}
+
+fn is_executable(path: &std::path::Path) -> bool {
+    crate::executables::is_executable(path)
+}
+
+pub fn hashed() -> Vec<(String, std::path::PathBuf)> {
+    let mut hashed: Vec<_> = hash()
+        .iter()
+        .map(|(name, path)| (name.clone(), path.clone()))
+        .collect();
+    hashed.sort();
+    hashed
+}
+
+pub fn clear_hash() {
+    hash().clear();
+}
+
+// forgets any remembered location for the given command and looks it up
+// again
+pub fn rehash(name: &str) -> Option<std::path::PathBuf> {
+    hash().remove(name);
+    find_executable(name)
+}
+
+// alternatives to suggest when a command isn't found: the command in the
+// current directory if there is one, followed by the closest known command
+// names
+pub fn suggestions(name: &str) -> Vec<String> {
+    let mut suggestions = vec![];
+    // the current directory isn't in $PATH by default
+    if !name.contains('/') && is_executable(std::path::Path::new(name)) {
+        suggestions.push(format!("./{name}"));
+    }
+    if name.is_empty() || name.contains('/') {
+        return suggestions;
+    }
+
+    // short names need to be closer to count as typos
+    let max_distance = match name.chars().count() {
+        0..=2 => 0,
+        3..=4 => 1,
+        _ => 2,
+    };
+    let mut candidates: Vec<_> = command_names()
+        .into_iter()
+        .filter(|candidate| candidate != name)
+        .filter_map(|candidate| {
+            let distance = edit_distance(name, &candidate);
+            if distance <= max_distance {
+                Some((distance, candidate))
+            } else {
+                None
+            }
+        })
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    suggestions.extend(
+        candidates
+            .into_iter()
+            .map(|(_, candidate)| candidate)
+            .take(MAX_SUGGESTIONS),
+    );
+    suggestions
+}
+
+const MAX_SUGGESTIONS: usize = 3;
+
+// every name that could currently be run as a command
+fn command_names() -> Vec<String> {
+    let mut names: Vec<String> = crate::builtins::all()
+        .iter()
+        .map(|builtin| builtin.name().to_string())
+        .collect();
+    names.extend(crate::aliases::all().into_iter().map(|(name, _)| name));
+    names.extend(crate::env::function_names());
+    names.extend(crate::executables::names());
+    names
+}
+
+// levenshtein distance, except that swapping two adjacent characters (the
+// most common typo, like `gti` for `git`) only counts as one edit
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1]
+            {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
@@ -24,13 +24,17 @@ enum Error {
     Print { source: std::io::Error },
 
     #[snafu(display("eof"))]
-    EOF,
+    Eof,
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
 pub fn tui() {
-    tokio::run(Tui::new());
+    tokio::run(crate::rc::load().and_then(|()| Tui::new()));
+    // `exit` sets the status to exit with
+    if crate::env::exit_requested() {
+        std::process::exit(crate::env::status());
+    }
 }
 
 #[derive(Default)]
@@ -39,15 +43,18 @@ pub struct Tui {
     readline: Option<crate::readline::Readline>,
     commands: std::collections::HashMap<usize, Command>,
     raw_screen: Option<crossterm::screen::RawScreen>,
+    // set while asking whether to rerun a mistyped command line
+    correction: Option<crate::eval::Correction>,
 }
 
 impl Tui {
+    #[must_use]
     pub fn new() -> Self {
         Self::default()
     }
 
     fn read() -> crate::readline::Readline {
-        crate::readline::Readline::new().set_raw(false)
+        crate::readline::readline().set_raw(false)
     }
 
     fn eval(
@@ -59,7 +66,7 @@ impl Tui {
             return Err(Error::InvalidCommandIndex { idx });
         }
         let eval = crate::eval::Eval::new(line).set_raw(false);
-        self.commands.insert(idx, Command::new(eval));
+        self.commands.insert(idx, Command::new(eval, line));
         Ok(())
     }
 
@@ -129,6 +136,11 @@ impl Tui {
         Ok(())
     }
 
+    #[allow(
+        clippy::needless_pass_by_ref_mut,
+        clippy::unnecessary_wraps,
+        clippy::unused_self
+    )]
     fn command_resize(
         &mut self,
         _idx: usize,
@@ -141,7 +153,7 @@ impl Tui {
     fn poll_read(&mut self) {
         if self.readline.is_none() && self.commands.is_empty() {
             self.idx += 1;
-            self.readline = Some(Self::read())
+            self.readline = Some(Self::read());
         }
     }
 
@@ -149,17 +161,15 @@ impl Tui {
         if let Some(mut r) = self.readline.take() {
             match r.poll() {
                 Ok(futures::Async::Ready(line)) => {
-                    match self.eval(self.idx, &line) {
-                        Ok(())
-                        | Err(Error::Eval {
-                            source:
-                                crate::eval::Error::Parser {
-                                    source:
-                                        crate::parser::Error::CommandRequired,
-                                    ..
-                                },
-                        }) => {}
-                        Err(e) => return Err(e),
+                    if let Some(correction) = self.correction.take() {
+                        let answer = line.trim();
+                        if answer.eq_ignore_ascii_case("y")
+                            || answer.eq_ignore_ascii_case("yes")
+                        {
+                            self.eval(self.idx, &correction.line)?;
+                        }
+                    } else {
+                        self.eval(self.idx, &line)?;
                     }
                     Ok(true)
                 }
@@ -167,7 +177,13 @@ impl Tui {
                     self.readline.replace(r);
                     Ok(false)
                 }
-                Err(crate::readline::Error::EOF) => Err(Error::EOF),
+                // eof while confirming a correction counts as no
+                Err(crate::readline::Error::Eof)
+                    if self.correction.take().is_some() =>
+                {
+                    Ok(true)
+                }
+                Err(crate::readline::Error::Eof) => Err(Error::Eof),
                 Err(e) => Err(e).context(Read),
             }
         } else {
@@ -178,32 +194,40 @@ impl Tui {
     fn poll_print(&mut self) -> Result<bool> {
         let mut did_work = false;
 
-        for idx in self.commands.keys().cloned().collect::<Vec<usize>>() {
+        let idxs: Vec<usize> = self.commands.keys().copied().collect();
+        for idx in idxs {
             match self.commands.get_mut(&idx).unwrap().future.poll() {
                 Ok(futures::Async::Ready(Some(event))) => {
                     self.print(idx, event)?;
                     did_work = true;
                 }
                 Ok(futures::Async::Ready(None)) => {
-                    self.commands
+                    let command = self
+                        .commands
                         .remove(&idx)
                         .context(InvalidCommandIndex { idx })?;
+                    crate::history::finish(crate::env::status());
+                    if let Some(correction) =
+                        crate::eval::correction(&command.line)
+                    {
+                        self.readline = Some(
+                            Self::read()
+                                .prompt(&correction.prompt())
+                                .history(false),
+                        );
+                        self.correction = Some(correction);
+                    }
                     did_work = true;
                 }
                 Ok(futures::Async::NotReady) => {}
 
-                // Parser and Command errors are always fatal, but execution
-                // errors might not be
+                // Parser errors are always fatal, but execution errors
+                // might not be
                 Err(e @ crate::eval::Error::Parser { .. }) => {
                     self.commands
                         .remove(&idx)
                         .context(InvalidCommandIndex { idx })?;
-                    return Err(e).context(Eval);
-                }
-                Err(e @ crate::eval::Error::Command { .. }) => {
-                    self.commands
-                        .remove(&idx)
-                        .context(InvalidCommandIndex { idx })?;
+                    crate::history::finish(crate::env::status());
                     return Err(e).context(Eval);
                 }
                 Err(e) => {
@@ -224,6 +248,10 @@ impl Tui {
         }
 
         loop {
+            if crate::env::exit_requested() {
+                return Err(Error::Eof);
+            }
+
             let mut did_work = false;
 
             self.poll_read();
@@ -245,9 +273,14 @@ impl futures::future::Future for Tui {
         loop {
             match self.poll_with_errors() {
                 Ok(a) => return Ok(a),
-                Err(Error::EOF) => return Ok(futures::Async::Ready(())),
+                Err(Error::Eof) => return Ok(futures::Async::Ready(())),
                 Err(e) => {
-                    eprint!("error polling state: {}\r\n", e);
+                    eprint!("error polling state: {e}\r\n");
+                    if let Error::Eval { source } = &e {
+                        if let Some(hint) = source.hint() {
+                            eprint!("{hint}\r\n");
+                        }
+                    }
                 }
             }
         }
@@ -256,6 +289,7 @@ impl futures::future::Future for Tui {
 
 struct Command {
     future: crate::eval::Eval,
+    line: String,
     cmd: Option<String>,
     args: Option<Vec<String>>,
     output: Vec<u8>,
@@ -263,9 +297,10 @@ struct Command {
 }
 
 impl Command {
-    fn new(future: crate::eval::Eval) -> Self {
+    fn new(future: crate::eval::Eval, line: &str) -> Self {
         Self {
             future,
+            line: line.to_string(),
             cmd: None,
             args: None,
             output: vec![],
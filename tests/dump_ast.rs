@@ -0,0 +1,67 @@
+use std::io::Write as _;
+
+fn dump_ast(script: &str) -> std::process::Output {
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_nbsh"))
+        .arg("--dump-ast")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(script.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn dumps_json() {
+    let output = dump_ast("echo hi | cat >out\n");
+    assert!(output.status.success());
+    let program: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        program["span"],
+        serde_json::json!({ "start": 0, "end": 18 })
+    );
+    let pipeline = &program["lists"][0]["first"];
+    assert_eq!(pipeline["negated"], false);
+    assert_eq!(
+        pipeline["span"],
+        serde_json::json!({ "start": 0, "end": 18 })
+    );
+    let commands = pipeline["commands"].as_array().unwrap();
+    assert_eq!(commands.len(), 2);
+    assert_eq!(
+        commands[0]["Simple"]["words"][1],
+        serde_json::json!({
+            "parts": [{ "Literal": "hi" }],
+            "span": { "start": 5, "end": 7 },
+        })
+    );
+    assert_eq!(
+        commands[1]["Simple"]["redirects"],
+        serde_json::json!([{
+            "Output": {
+                "fd": 1,
+                "target": {
+                    "parts": [{ "Literal": "out" }],
+                    "span": { "start": 15, "end": 18 },
+                },
+                "span": { "start": 14, "end": 18 },
+            }
+        }])
+    );
+}
+
+#[test]
+fn reports_syntax_errors() {
+    let output = dump_ast("echo 'unterminated\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("-:1:6: error: unterminated ' quote"));
+}